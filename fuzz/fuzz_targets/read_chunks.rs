@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// r2t2 is only a binary, so the reader is pulled in straight from the sources. It refers to the
+// metrics module by its path from the crate root, hence the re-export.
+#[path = "../../src"]
+#[allow(dead_code)]
+mod src {
+    pub mod ftdc;
+    pub mod metric;
+}
+
+use src::{ftdc, metric};
+
+// Reads all the chunks, the same way the data set does. Any malformed input has to end up as an
+// error rather than a panic, and the reader has to come to an end after it.
+fuzz_target!(|data: &[u8]| {
+    for chunk in ftdc::ChunkReader::new(data) {
+        let _ = chunk;
+    }
+});
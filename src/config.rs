@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+// What the app remembers from one run to the next. Unlike a workspace, it isn't about any data, so
+// it is saved on exit rather than on request.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window: Option<WindowGeometry>,
+    // None for the scale FLTK detects
+    pub ui_scale: Option<f32>,
+    pub chart_size: i32,
+    pub descriptors_file: Option<PathBuf>,
+    pub last_dir: Option<PathBuf>,
+    pub dark_mode: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Config {
+    // No file just means nothing to restore.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = match config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = config_path().ok_or_else(|| anyhow::anyhow!("no directory for the config"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(CONFIG_FILE_NAME))
+}
+
+// Follows the convention of each platform for where settings go.
+fn config_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Preferences"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".config")))
+    };
+    Some(base?.join(APP_DIR_NAME))
+}
+
+const APP_DIR_NAME: &str = "r2t2";
+const CONFIG_FILE_NAME: &str = "config.json";
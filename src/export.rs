@@ -0,0 +1,22 @@
+pub mod html;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod samples;
+pub mod stats;
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// Gaps are left empty.
+fn csv_value(value: f64) -> String {
+    if value.is_nan() {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
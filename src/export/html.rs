@@ -0,0 +1,182 @@
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use serde_json::json;
+
+use crate::metric::{Timestamp, TimestampFormat};
+
+pub struct HtmlSection {
+    pub name: String,
+    pub charts: Vec<HtmlChart>,
+}
+
+pub struct HtmlChart {
+    pub name: String,
+    pub unit: Option<String>,
+    pub points: Vec<(Timestamp, f64)>,
+}
+
+// The page draws the charts itself, with inline SVG, so that it can be opened anywhere without
+// r2t2 or access to the internet.
+pub fn write_html<W: Write>(
+    writer: &mut W,
+    title: &str,
+    range: &RangeInclusive<Timestamp>,
+    sections: &[HtmlSection],
+) -> anyhow::Result<()> {
+    let data = json!({
+        "start": range.start().timestamp_millis(),
+        "end": range.end().timestamp_millis(),
+        "sections": sections
+            .iter()
+            .map(|section| json!({
+                "name": section.name,
+                "charts": section
+                    .charts
+                    .iter()
+                    .map(|chart| json!({
+                        "name": chart.name,
+                        "unit": chart.unit,
+                        // Gaps are NaN, which become nulls.
+                        "points": chart
+                            .points
+                            .iter()
+                            .map(|(time, value)| json!([time.timestamp_millis(), value]))
+                            .collect::<Vec<_>>(),
+                    }))
+                    .collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    // Nothing in the data may end the script element it is embedded in.
+    let data = data.to_string().replace("</", "<\\/");
+    let heading = format!(
+        "{} ({} to {})",
+        title,
+        range.start().to_timestamp_string(),
+        range.end().to_timestamp_string(),
+    );
+
+    let page = HTML_TEMPLATE
+        .replace("{{TITLE}}", &escape_html(&heading))
+        .replace("{{DATA}}", &data);
+    writer.write_all(page.as_bytes())?;
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+body { font-family: Helvetica, Arial, sans-serif; font-size: 12px; margin: 20px; }
+h1 { font-size: 16px; }
+h2 { font-size: 14px; background: #f3f5f9; padding: 4px; }
+.chart { display: flex; align-items: center; margin-bottom: 8px; }
+.key { width: 280px; padding-right: 10px; word-break: break-all; }
+svg { background: #fff; border: 1px solid #ddd; }
+.tip { font-size: 11px; fill: #000; }
+</style>
+</head>
+<body>
+<h1>{{TITLE}}</h1>
+<div id="charts"></div>
+<script>
+const DATA = {{DATA}};
+const W = 800, H = 100, PAD = 4;
+
+function fmtTime(millis) {
+  return new Date(millis).toISOString();
+}
+
+function fmtValue(value) {
+  return value === null ? "-" : (Math.round(value * 1000) / 1000).toLocaleString();
+}
+
+function svgEl(name, attrs) {
+  const el = document.createElementNS("http://www.w3.org/2000/svg", name);
+  for (const key in attrs) el.setAttribute(key, attrs[key]);
+  return el;
+}
+
+function drawChart(parent, chart) {
+  const values = chart.points.map(p => p[1]).filter(v => v !== null);
+  let min = Math.min(0, ...values), max = Math.max(0, ...values);
+  if (max === min) max = min + 1;
+  const x = t => PAD + (t - DATA.start) / Math.max(1, DATA.end - DATA.start) * (W - 2 * PAD);
+  const y = v => H - PAD - (v - min) / (max - min) * (H - 2 * PAD);
+
+  const row = document.createElement("div");
+  row.className = "chart";
+  const key = document.createElement("div");
+  key.className = "key";
+  key.textContent = chart.unit ? chart.name + " [" + chart.unit + "]" : chart.name;
+  row.appendChild(key);
+
+  const svg = svgEl("svg", { width: W, height: H });
+  let line = [];
+  const flush = () => {
+    if (line.length > 0) {
+      svg.appendChild(svgEl("polyline", {
+        points: line.join(" "), fill: "none", stroke: "#000", "stroke-width": 1,
+      }));
+    }
+    line = [];
+  };
+  for (const [t, v] of chart.points) {
+    if (v === null) flush(); else line.push(x(t) + "," + y(v));
+  }
+  flush();
+  svg.appendChild(svgEl("text", { x: W - PAD, y: 12, "text-anchor": "end", class: "tip" }))
+    .textContent = fmtValue(max);
+
+  const cursor = svgEl("line", { y1: 0, y2: H, stroke: "#c00", visibility: "hidden" });
+  const tip = svgEl("text", { x: PAD, y: H - PAD, class: "tip" });
+  svg.appendChild(cursor);
+  svg.appendChild(tip);
+  svg.addEventListener("mousemove", event => {
+    if (chart.points.length === 0) return;
+    const mouseX = event.clientX - svg.getBoundingClientRect().left;
+    let nearest = chart.points[0];
+    for (const point of chart.points) {
+      if (Math.abs(x(point[0]) - mouseX) < Math.abs(x(nearest[0]) - mouseX)) nearest = point;
+    }
+    cursor.setAttribute("x1", x(nearest[0]));
+    cursor.setAttribute("x2", x(nearest[0]));
+    cursor.setAttribute("visibility", "visible");
+    tip.textContent = fmtTime(nearest[0]) + "  " + fmtValue(nearest[1]);
+  });
+  svg.addEventListener("mouseleave", () => {
+    cursor.setAttribute("visibility", "hidden");
+    tip.textContent = "";
+  });
+  row.appendChild(svg);
+  parent.appendChild(row);
+}
+
+const container = document.getElementById("charts");
+for (const section of DATA.sections) {
+  const heading = document.createElement("h2");
+  heading.textContent = section.name;
+  container.appendChild(heading);
+  for (const chart of section.charts) drawChart(container, chart);
+}
+</script>
+</body>
+</html>
+"##;
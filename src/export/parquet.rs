@@ -0,0 +1,131 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use ::arrow::array::{ArrayRef, Float64Array, Int64Array, TimestampMillisecondArray};
+use ::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+
+use crate::metric::Timestamp;
+
+pub struct ExportColumn {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+pub fn write_parquet<W: Write + Send>(
+    writer: W,
+    timestamps: &[Timestamp],
+    columns: &[ExportColumn],
+) -> anyhow::Result<()> {
+    let mut fields = Vec::with_capacity(columns.len() + 1);
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len() + 1);
+
+    fields.push(Field::new(
+        TIMESTAMP_COLUMN,
+        DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        false,
+    ));
+    arrays.push(Arc::new(
+        TimestampMillisecondArray::from(
+            timestamps
+                .iter()
+                .map(|t| t.timestamp_millis())
+                .collect::<Vec<_>>(),
+        )
+        .with_timezone("UTC"),
+    ));
+
+    // FTDC stores every metric as an integer, but raw values are kept as floats so that gaps can
+    // be NaN. Gaps become nulls, and columns that only hold whole numbers go back to integers.
+    for column in columns {
+        let is_integral = column
+            .values
+            .iter()
+            .filter(|value| !value.is_nan())
+            .all(|value| value.fract() == 0.0 && value.abs() < i64::MAX as f64);
+        let valid = |value: &f64| (!value.is_nan()).then_some(*value);
+        if is_integral {
+            fields.push(Field::new(&column.name, DataType::Int64, true));
+            arrays.push(Arc::new(Int64Array::from(
+                column
+                    .values
+                    .iter()
+                    .map(|value| valid(value).map(|value| value as i64))
+                    .collect::<Vec<_>>(),
+            )));
+        } else {
+            fields.push(Field::new(&column.name, DataType::Float64, true));
+            arrays.push(Arc::new(Float64Array::from(
+                column.values.iter().map(valid).collect::<Vec<_>>(),
+            )));
+        }
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(Arc::clone(&schema), arrays)?;
+    let mut writer = ArrowWriter::try_new(writer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+const TIMESTAMP_COLUMN: &str = "timestamp";
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use ::arrow::array::Array;
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::ftdc::fixture::temp_path;
+    use crate::metric::unix_millis_to_timestamp;
+
+    use super::*;
+
+    #[test]
+    fn columns_read_back_with_their_types_and_gaps() {
+        let timestamps: Vec<_> = [0, 1000, 2000]
+            .into_iter()
+            .map(unix_millis_to_timestamp)
+            .collect();
+        let columns = [
+            ExportColumn {
+                name: "ops".to_string(),
+                values: vec![1.0, f64::NAN, 3.0],
+            },
+            ExportColumn {
+                name: "ratio".to_string(),
+                values: vec![0.5, 1.0, 1.5],
+            },
+        ];
+        let path = temp_path("export.parquet");
+        write_parquet(File::create(&path).unwrap(), &timestamps, &columns).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let batch = &batches[0];
+        let schema = batch.schema();
+        let names: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, [TIMESTAMP_COLUMN, "ops", "ratio"]);
+        let column = |idx: usize| batch.column(idx).as_any();
+        let times = column(0)
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!(times.values().to_vec(), [0, 1000, 2000]);
+        let ops = column(1).downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ops.iter().collect::<Vec<_>>(), [Some(1), None, Some(3)]);
+        let ratio = column(2).downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(ratio.values().to_vec(), [0.5, 1.0, 1.5]);
+    }
+}
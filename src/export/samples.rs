@@ -0,0 +1,39 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::metric::{Timestamp, TimestampFormat};
+
+use super::{csv_field, csv_value};
+
+pub struct SampleColumn {
+    pub name: String,
+    pub points: Vec<(Timestamp, f64)>,
+}
+
+// There is a row for every time that any of the columns has a point at, so the columns needn't be
+// sampled at the same times. A column without a point at the time of a row has an empty field.
+pub fn write_samples_csv<W: Write>(mut writer: W, columns: &[SampleColumn]) -> anyhow::Result<()> {
+    let mut rows: BTreeMap<Timestamp, Vec<f64>> = BTreeMap::new();
+    for (idx, column) in columns.iter().enumerate() {
+        for &(time, value) in column.points.iter() {
+            rows.entry(time)
+                .or_insert_with(|| vec![f64::NAN; columns.len()])[idx] = value;
+        }
+    }
+
+    write!(writer, "time")?;
+    for column in columns {
+        write!(writer, ",{}", csv_field(&column.name))?;
+    }
+    writeln!(writer)?;
+
+    for (time, values) in rows {
+        write!(writer, "{}", time.to_timestamp_string())?;
+        for value in values {
+            write!(writer, ",{}", csv_value(value))?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::io::Write;
+
+use super::{csv_field, csv_value};
+
+pub struct MetricStats {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub last: f64,
+    pub count: usize,
+}
+
+impl MetricStats {
+    // Gaps are skipped, so a metric with nothing but gaps has a count of zero and NaN for
+    // everything else.
+    pub fn compute<I: IntoIterator<Item = f64>>(name: String, values: I) -> Self {
+        let mut stats = Self {
+            name,
+            min: f64::NAN,
+            max: f64::NAN,
+            mean: f64::NAN,
+            last: f64::NAN,
+            count: 0,
+        };
+        let mut sum = 0.0;
+        for value in values.into_iter().filter(|value| !value.is_nan()) {
+            stats.min = stats.min.min(value);
+            stats.max = stats.max.max(value);
+            stats.last = value;
+            stats.count += 1;
+            sum += value;
+        }
+        if stats.count > 0 {
+            stats.mean = sum / stats.count as f64;
+        }
+        stats
+    }
+}
+
+pub fn write_stats_csv<W: Write>(mut writer: W, stats: &[MetricStats]) -> anyhow::Result<()> {
+    writeln!(writer, "metric,min,max,mean,last,count")?;
+    for row in stats {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            csv_field(&row.name),
+            csv_value(row.min),
+            csv_value(row.max),
+            csv_value(row.mean),
+            csv_value(row.last),
+            row.count,
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -1,50 +1,313 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
 use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
 
 use bson::document::ValueAccessError;
-use bson::spec::BinarySubtype;
+use bson::spec::ElementType;
 use bson::{Binary, Bson, Document};
 use flate2::bufread::ZlibDecoder;
 use lebe::io::ReadEndian;
 
 mod decode;
 mod error;
+#[cfg(test)]
+pub mod fixture;
+mod warning;
 
-use crate::metric::{MetricKey, Timestamp};
+use crate::metric::{unix_millis_to_timestamp, MetricKey, Timestamp};
 
 use self::decode::MetricsDecoder;
 pub use self::error::Error;
+pub use self::warning::{summarize_warnings, ParseWarning};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Chunk {
-    Metadata(Document),
+    Metadata(MetadataChunk),
     Data(MetricsChunk),
 }
 
+#[derive(Debug)]
+pub struct MetadataChunk {
+    pub timestamp: Timestamp,
+    pub doc: Document,
+}
+
 #[derive(Debug)]
 pub struct MetricsChunk {
     pub timestamps: Vec<Timestamp>,
     pub metrics: HashMap<MetricKey, Vec<i64>>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+// What a file shows of the version of FTDC that wrote it. Newer servers add chunk types, so the
+// types seen, along with the version of the server, tell what the file should look like.
+#[derive(Debug, Clone, Default)]
+pub struct FormatInfo {
+    pub chunk_types: BTreeMap<i32, usize>,
+    pub server_version: Option<String>,
+}
+
+// The BSON types whose values become metrics. The values of the other types are still decoded,
+// since the delta stream covers them, but they are left out of the chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricTypes {
+    pub int32: bool,
+    pub int64: bool,
+    pub double: bool,
+    pub decimal128: bool,
+    pub boolean: bool,
+    pub date_time: bool,
+    pub timestamp: bool,
+}
+
+impl Default for MetricTypes {
+    fn default() -> Self {
+        Self {
+            int32: true,
+            int64: true,
+            double: true,
+            decimal128: true,
+            boolean: true,
+            date_time: true,
+            timestamp: true,
+        }
+    }
+}
+
+impl MetricTypes {
+    pub fn accepts(&self, element_type: ElementType) -> bool {
+        match element_type {
+            ElementType::Int32 => self.int32,
+            ElementType::Int64 => self.int64,
+            ElementType::Double => self.double,
+            ElementType::Decimal128 => self.decimal128,
+            ElementType::Boolean => self.boolean,
+            ElementType::DateTime => self.date_time,
+            ElementType::Timestamp => self.timestamp,
+            _ => false,
+        }
+    }
+
+    // Takes the names used in the BSON spec and in the usage text, ignoring case.
+    pub fn set(&mut self, name: &str, accepted: bool) -> bool {
+        let flag = match name.to_ascii_lowercase().as_str() {
+            "int32" | "int" => &mut self.int32,
+            "int64" | "long" => &mut self.int64,
+            "double" => &mut self.double,
+            "decimal128" | "decimal" => &mut self.decimal128,
+            "boolean" | "bool" => &mut self.boolean,
+            "datetime" | "date" => &mut self.date_time,
+            "timestamp" => &mut self.timestamp,
+            _ => return false,
+        };
+        *flag = accepted;
+        true
+    }
 }
 
+// Reads the chunks of an FTDC file in order, ending at the end of the file. A chunk that can't be
+// parsed is an error, after which the chunks that follow it are still read, unless the error leaves
+// no way of telling where the next one starts.
+pub struct ChunkReader<R> {
+    reader: R,
+    types: MetricTypes,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    #[allow(dead_code)]
+    pub fn new(reader: R) -> Self {
+        Self::with_types(reader, MetricTypes::default())
+    }
+
+    pub fn with_types(reader: R, types: MetricTypes) -> Self {
+        Self { reader, types, done: false }
+    }
+
+    // Reads the next chunk without decoding it, for when whether to decode it at all depends on
+    // its type or start time.
+    pub fn next_doc(&mut self) -> Option<Result<Document>> {
+        if self.done {
+            return None;
+        }
+        match read_chunk_doc(&mut self.reader) {
+            Ok(chunk_doc) => Some(Ok(chunk_doc)),
+            Err(Error::EOF) => {
+                self.done = true;
+                None
+            }
+            // The whole chunk was read, so the reader is at the start of the next one.
+            Err(err @ Error::BSON(_)) => Some(Err(err)),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let types = self.types;
+        self.next_doc()
+            .map(|chunk_doc| chunk_doc.and_then(|chunk_doc| decode_chunk(chunk_doc, types)))
+    }
+}
+
+#[allow(dead_code)]
 pub fn read_chunk<R: Read>(reader: &mut R) -> Result<Chunk> {
+    decode_chunk(read_chunk_doc(reader)?, MetricTypes::default())
+}
+
+pub fn read_chunk_doc<R: Read>(reader: &mut R) -> Result<Document> {
     let chunk_buf = {
         let len = read_chunk_len(reader)?;
-        let mut buf = vec![0u8; len as _];
-        buf[0..4].copy_from_slice(&u32::to_le_bytes(len));
-        reader.read_exact(&mut buf[4..])?;
+        // The length comes from the file, so the buffer only grows as the data actually arrives,
+        // rather than trusting the length with a huge allocation up front.
+        let mut buf = Vec::with_capacity(std::cmp::min(len as usize, INITIAL_CHUNK_CAPACITY));
+        buf.extend_from_slice(&u32::to_le_bytes(len));
+        reader.take((len - 4) as _).read_to_end(&mut buf)?;
+        if buf.len() < len as usize {
+            return Err(Error::IO(std::io::ErrorKind::UnexpectedEof.into()));
+        }
         buf
     };
-    let chunk_doc = Document::from_reader(&mut chunk_buf.as_slice())?;
-    match chunk_doc.get_i32("type")? {
+    Ok(Document::from_reader(&mut chunk_buf.as_slice())?)
+}
+
+pub fn decode_chunk(chunk_doc: Document, types: MetricTypes) -> Result<Chunk> {
+    match chunk_type(&chunk_doc)? {
         0 => extract_metadata(chunk_doc),
-        1 => extract_data(chunk_doc),
+        // The data is decoded from memory, so failing to read it means it's corrupted rather than
+        // that the file couldn't be read.
+        1 => extract_data(chunk_doc, types).map_err(|err| match err {
+            Error::IO(err) => Error::CorruptChunkData(err),
+            err => err,
+        }),
         unk => Err(Error::UnknownChunkType(unk)),
     }
 }
 
+// Decodes the chunks on as many threads as there are cores, keeping them in order.
+pub fn decode_chunks(chunk_docs: Vec<Document>, types: MetricTypes) -> Vec<Result<Chunk>> {
+    let num_threads = std::cmp::min(available_threads(), chunk_docs.len());
+    if num_threads <= 1 {
+        return chunk_docs
+            .into_iter()
+            .map(|chunk_doc| decode_chunk(chunk_doc, types))
+            .collect();
+    }
+
+    let per_thread = (chunk_docs.len() + num_threads - 1) / num_threads;
+    let mut chunk_docs = chunk_docs.into_iter();
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let batch: Vec<_> = chunk_docs.by_ref().take(per_thread).collect();
+                scope.spawn(move || {
+                    batch
+                        .into_iter()
+                        .map(|chunk_doc| decode_chunk(chunk_doc, types))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .flat_map(|thread| {
+                thread
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+// How many chunks to read before decoding them, which is enough to keep every thread busy for a
+// while, without holding too much of the file in memory.
+pub fn decode_batch_len() -> usize {
+    available_threads() * CHUNKS_PER_THREAD
+}
+
+fn available_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+pub fn is_data_chunk(chunk_doc: &Document) -> Result<bool> {
+    Ok(chunk_type(chunk_doc)? == 1)
+}
+
+pub fn chunk_start(chunk_doc: &Document) -> Result<Timestamp> {
+    Ok(unix_millis_to_timestamp(
+        chunk_doc.get_datetime("_id")?.timestamp_millis(),
+    ))
+}
+
+impl FormatInfo {
+    pub fn count_chunk(&mut self, chunk_doc: &Document) {
+        if let Ok(chunk_type) = chunk_type(chunk_doc) {
+            *self.chunk_types.entry(chunk_type).or_default() += 1;
+        }
+    }
+
+    // From the `buildInfo` that the server puts in the metadata
+    pub fn set_metadata(&mut self, metadata: &Document) {
+        self.server_version = metadata
+            .get_document("buildInfo")
+            .and_then(|build_info| build_info.get_str("version"))
+            .ok()
+            .map(str::to_string);
+    }
+
+    pub fn clear(&mut self) {
+        self.chunk_types.clear();
+        self.server_version = None;
+    }
+}
+
+impl Display for FormatInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (idx, (chunk_type, count)) in self.chunk_types.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            match CHUNK_TYPE_NAMES.get(*chunk_type as usize) {
+                Some(name) if *chunk_type >= 0 => write!(f, "{} {}", count, name)?,
+                _ => write!(f, "{} of unknown type {}", count, chunk_type)?,
+            }
+        }
+        if self.chunk_types.is_empty() {
+            f.write_str("no chunks")?;
+        }
+        if let Some(version) = self.server_version.as_ref() {
+            write!(f, " (server {})", version)?;
+        }
+        Ok(())
+    }
+}
+
+impl MetricsChunk {
+    pub fn retain_range(&mut self, range: &RangeInclusive<Timestamp>) {
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+
+        self.timestamps.truncate(end_idx);
+        self.timestamps.drain(..start_idx);
+        for values in self.metrics.values_mut() {
+            values.truncate(end_idx);
+            values.drain(..start_idx);
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn skip_chunk<R: Read + Seek>(reader: &mut R) -> Result<()> {
     let len = read_chunk_len(reader)?;
@@ -52,8 +315,19 @@ pub fn skip_chunk<R: Read + Seek>(reader: &mut R) -> Result<()> {
     Ok(())
 }
 
+// Every FTDC chunk has an integer type, so a document without one most likely comes from a
+// regular BSON file rather than a corrupted FTDC file.
+fn chunk_type(chunk_doc: &Document) -> Result<i32> {
+    chunk_doc.get_i32("type").map_err(|err| match err {
+        ValueAccessError::NotPresent | ValueAccessError::UnexpectedType => Error::NotFtdcFile,
+        err => Error::InvalidDocumentFormat(err),
+    })
+}
+
+// The length includes itself, so anything shorter than that can't be the start of a chunk.
 fn read_chunk_len<R: Read>(reader: &mut R) -> Result<u32> {
     match reader.read_from_little_endian() {
+        Ok(len) if len < 4 => Err(Error::InvalidChunkLength(len)),
         Ok(len) => Ok(len),
         Err(err) => match err.kind() {
             std::io::ErrorKind::UnexpectedEof => Err(Error::EOF),
@@ -63,8 +337,9 @@ fn read_chunk_len<R: Read>(reader: &mut R) -> Result<u32> {
 }
 
 fn extract_metadata(mut doc: Document) -> Result<Chunk> {
+    let timestamp = chunk_start(&doc)?;
     match doc.remove("doc") {
-        Some(Bson::Document(doc)) => Ok(Chunk::Metadata(doc)),
+        Some(Bson::Document(doc)) => Ok(Chunk::Metadata(MetadataChunk { timestamp, doc })),
         Some(_) => Err(Error::InvalidDocumentFormat(
             ValueAccessError::UnexpectedType,
         )),
@@ -72,9 +347,11 @@ fn extract_metadata(mut doc: Document) -> Result<Chunk> {
     }
 }
 
-fn extract_data(mut doc: Document) -> Result<Chunk> {
+fn extract_data(mut doc: Document, types: MetricTypes) -> Result<Chunk> {
     let compressed = match doc.remove("data") {
-        Some(Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes })) => bytes,
+        // The subtype doesn't affect how the payload is decoded, and some tools don't use the
+        // generic one.
+        Some(Bson::Binary(Binary { bytes, .. })) => bytes,
         Some(_) => {
             return Err(Error::InvalidDocumentFormat(
                 ValueAccessError::UnexpectedType,
@@ -84,8 +361,21 @@ fn extract_data(mut doc: Document) -> Result<Chunk> {
     };
 
     let uncompressed_len: u32 = Cursor::new(compressed.as_slice()).read_from_little_endian()?;
-    let mut uncompressed = vec![0; uncompressed_len as _];
-    ZlibDecoder::new(&compressed[4..]).read_exact(&mut uncompressed)?;
+    if uncompressed_len as usize > MAX_UNCOMPRESSED_LEN {
+        return Err(Error::ChunkTooLarge);
+    }
+    // The zlib stream knows where it ends, so anything after it is ignored. A stream that ends
+    // before producing the advertised number of bytes, however, means the chunk is corrupted.
+    let mut uncompressed = Vec::with_capacity(uncompressed_len as _);
+    ZlibDecoder::new(&compressed[4..])
+        .take(uncompressed_len as _)
+        .read_to_end(&mut uncompressed)?;
+    if uncompressed.len() < uncompressed_len as usize {
+        return Err(Error::IncompleteChunkData {
+            expected: uncompressed_len as _,
+            actual: uncompressed.len(),
+        });
+    }
 
     let doc = Document::from_reader(uncompressed.as_slice())?;
 
@@ -96,10 +386,167 @@ fn extract_data(mut doc: Document) -> Result<Chunk> {
 
     let num_keys: u32 = cursor.read_from_little_endian()?;
     let num_deltas: u32 = cursor.read_from_little_endian()?;
+    // Runs of zeroes take next to no space, so a corrupted count could otherwise ask for far more
+    // values than any real chunk has.
+    if num_keys as u64 * (num_deltas as u64 + 1) > MAX_CHUNK_VALUES {
+        return Err(Error::ChunkTooLarge);
+    }
 
-    let mut decoder = MetricsDecoder::new(num_keys as usize, num_deltas as usize);
+    let mut decoder = MetricsDecoder::new(num_keys as usize, num_deltas as usize, types);
     decoder.collect_metrics(doc);
-    decoder.decode_deltas(&mut cursor)?;
+    // Running out of deltas means the counts don't go with the data, rather than that the data was
+    // cut short, which the length of the uncompressed data already rules out.
+    let available = uncompressed.len() - cursor.position() as usize;
+    decoder
+        .decode_deltas(&mut cursor)
+        .map_err(|err| match err {
+            Error::IO(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Error::VersionMismatch {
+                    num_keys: num_keys as _,
+                    num_deltas: num_deltas as _,
+                    available,
+                }
+            }
+            err => err,
+        })?;
+
+    Ok(Chunk::Data(decoder.finish()?))
+}
+
+const CHUNK_TYPE_NAMES: [&str; 3] = ["metadata", "metrics", "periodic metadata"];
+const CHUNKS_PER_THREAD: usize = 4;
+const INITIAL_CHUNK_CAPACITY: usize = 1 << 20;
+const MAX_UNCOMPRESSED_LEN: usize = 1 << 28;
+const MAX_CHUNK_VALUES: u64 = 1 << 26;
 
-    Ok(Chunk::Data(decoder.finish()))
+#[cfg(test)]
+mod tests {
+    use bson::spec::BinarySubtype;
+    use bson::{doc, Decimal128};
+
+    use super::fixture::{compress, data_chunk, data_chunk_with, payload, sample};
+    use super::*;
+
+    #[test]
+    fn delta_stream_short_of_counts_is_version_mismatch() {
+        let samples = [
+            sample(0, doc! { "x": 1i64 }),
+            sample(1000, doc! { "x": 5i64 }),
+            sample(2000, doc! { "x": 20i64 }),
+        ];
+        let mut payload = payload(&samples);
+        payload.pop();
+        let chunk_doc = data_chunk_with(&samples, compress(&payload), BinarySubtype::Generic);
+
+        let result = decode_chunk(chunk_doc, MetricTypes::default());
+        assert!(
+            matches!(
+                result,
+                Err(Error::VersionMismatch { num_keys: 2, num_deltas: 2, .. })
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    fn samples() -> [Document; 3] {
+        [
+            sample(0, doc! { "x": 1i64 }),
+            sample(1000, doc! { "x": 5i64 }),
+            sample(2000, doc! { "x": 20i64 }),
+        ]
+    }
+
+    fn x_values(result: Result<Chunk>) -> Vec<i64> {
+        match result {
+            Ok(Chunk::Data(chunk)) => chunk.metrics[&MetricKey::from(&["x"][..])].clone(),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn junk_after_the_zlib_stream_is_ignored() {
+        let samples = samples();
+        let mut data = compress(&payload(&samples));
+        data.extend_from_slice(b"junk");
+        let chunk_doc = data_chunk_with(&samples, data, BinarySubtype::Generic);
+
+        let result = decode_chunk(chunk_doc, MetricTypes::default());
+        assert_eq!(x_values(result), [1, 5, 20]);
+    }
+
+    #[test]
+    fn zlib_stream_short_of_the_length_is_incomplete() {
+        let samples = samples();
+        let payload = payload(&samples);
+        let mut data = compress(&payload);
+        data[..4].copy_from_slice(&(payload.len() as u32 + 10).to_le_bytes());
+        let chunk_doc = data_chunk_with(&samples, data, BinarySubtype::Generic);
+
+        let result = decode_chunk(chunk_doc, MetricTypes::default());
+        assert!(
+            matches!(
+                result,
+                Err(Error::IncompleteChunkData { expected, actual })
+                    if expected == payload.len() + 10 && actual == payload.len()
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn data_of_any_binary_subtype_is_decoded() {
+        let samples = samples();
+        for subtype in [BinarySubtype::BinaryOld, BinarySubtype::UserDefined(0x80)] {
+            let data = compress(&payload(&samples));
+            let chunk_doc = data_chunk_with(&samples, data, subtype);
+
+            let result = decode_chunk(chunk_doc, MetricTypes::default());
+            assert_eq!(x_values(result), [1, 5, 20]);
+        }
+    }
+
+    #[test]
+    fn excluded_types_leave_out_their_metrics() {
+        let samples = [
+            sample(0, doc! { "x": 1i64, "ratio": 0.5, "y": 2i64, "up": true }),
+            sample(
+                1000,
+                doc! { "x": 5i64, "ratio": 1.5, "y": 3i64, "up": false },
+            ),
+        ];
+        let mut types = MetricTypes::default();
+        assert!(types.set("Double", false));
+        assert!(!types.set("string", false));
+
+        let chunk = match decode_chunk(data_chunk(&samples), types) {
+            Ok(Chunk::Data(chunk)) => chunk,
+            result => panic!("{:?}", result),
+        };
+        let metric = |path: &[&str]| chunk.metrics.get(&MetricKey::from(path));
+        assert_eq!(metric(&["ratio"]), None);
+        assert_eq!(metric(&["x"]), Some(&vec![1, 5]));
+        assert_eq!(metric(&["y"]), Some(&vec![2, 3]));
+        assert_eq!(metric(&["up"]), Some(&vec![1, 0]));
+    }
+
+    #[test]
+    fn decimal128_metric_is_in_the_chunk() {
+        let decimal = |value: u128| {
+            // A coefficient with no exponent, which is biased by 6176
+            Decimal128::from_bytes((6176u128 << 113 | value).to_le_bytes())
+        };
+        let samples = [
+            sample(0, doc! { "cache": decimal(100), "x": 1i64 }),
+            sample(1000, doc! { "cache": decimal(250), "x": 2i64 }),
+        ];
+
+        let chunk = match decode_chunk(data_chunk(&samples), MetricTypes::default()) {
+            Ok(Chunk::Data(chunk)) => chunk,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(chunk.metrics[&MetricKey::from(&["cache"][..])], [100, 250]);
+        assert_eq!(chunk.metrics[&MetricKey::from(&["x"][..])], [1, 2]);
+    }
 }
@@ -1,25 +1,43 @@
 use std::collections::HashMap;
 use std::io::Read;
 
-use bson::{Bson, Document};
+use bson::spec::ElementType;
+use bson::{Bson, Decimal128, Document};
 
 use crate::metric::{unix_millis_to_timestamp, MetricKey};
 
-use super::{MetricsChunk, Result};
+use super::{Error, MetricTypes, MetricsChunk, ParseWarning, Result};
 
 pub(super) struct MetricsDecoder {
+    num_keys: usize,
     num_deltas: usize,
+    types: MetricTypes,
     metrics: Vec<(MetricKey, Vec<i64>)>,
+    rejected: Vec<MetricKey>,
+    warnings: Vec<ParseWarning>,
 }
 
 impl MetricsDecoder {
-    pub fn new(num_keys: usize, num_deltas: usize) -> Self {
-        Self { num_deltas, metrics: Vec::with_capacity(num_keys) }
+    pub fn new(num_keys: usize, num_deltas: usize, types: MetricTypes) -> Self {
+        Self {
+            num_keys,
+            num_deltas,
+            types,
+            metrics: Vec::with_capacity(num_keys),
+            rejected: vec![],
+            warnings: vec![],
+        }
     }
 
     pub fn collect_metrics(&mut self, doc: Document) {
         let mut prefix = MetricKey::new();
         self.collect_element_metrics(&Bson::Document(doc), &mut prefix);
+        if self.metrics.len() != self.num_keys {
+            self.warnings.push(ParseWarning::KeyCountMismatch {
+                expected: self.num_keys,
+                actual: self.metrics.len(),
+            });
+        }
     }
 
     pub fn decode_deltas<R: Read>(&mut self, reader: &mut R) -> Result<()> {
@@ -41,24 +59,30 @@ impl MetricsDecoder {
                 // we should've just used proper LEB128 for negative deltas, but here we are
                 let delta = leb128::read::unsigned(reader)? as i64;
                 if delta != 0 {
-                    value += delta;
+                    value = value.wrapping_add(delta);
                     values.push(value);
                     deltas_left -= 1;
                 } else {
-                    num_zeroes = 1 + leb128::read::unsigned(reader)? as usize;
+                    num_zeroes = (leb128::read::unsigned(reader)? as usize).saturating_add(1);
                 }
             }
         }
         Ok(())
     }
 
-    pub fn finish(self) -> MetricsChunk {
-        let metrics: HashMap<_, _> = self.metrics.into_iter().collect();
-        let timestamps = metrics["start"]
+    pub fn finish(self) -> Result<MetricsChunk> {
+        let mut metrics: HashMap<_, _> = self.metrics.into_iter().collect();
+        // The sample times come from the start date even if dates aren't wanted as metrics.
+        let timestamps = metrics
+            .get(TIMESTAMP_FIELD)
+            .ok_or(Error::MissingTimestampField)?
             .iter()
             .map(|&millis| unix_millis_to_timestamp(millis))
             .collect();
-        MetricsChunk { timestamps, metrics }
+        for key in self.rejected.iter() {
+            metrics.remove(key);
+        }
+        Ok(MetricsChunk { timestamps, metrics, warnings: self.warnings })
     }
 
     fn collect_element_metrics(&mut self, elem: &Bson, prefix: &mut MetricKey) {
@@ -71,17 +95,41 @@ impl MetricsDecoder {
                     .enumerate()
                     .map(|(idx, elem)| (idx.to_string(), elem)),
             ),
-            Bson::DateTime(value) => self.add_metric(prefix, value.timestamp_millis()),
+            Bson::DateTime(value) => {
+                self.add_metric(prefix, value.timestamp_millis(), ElementType::DateTime)
+            }
             Bson::Timestamp(value) => {
-                let t = Bson::Int64(value.time as i64);
-                let i = Bson::Int64(value.increment as i64);
-                self.collect_children(prefix, [("t", &t), ("i", &i)]);
+                let prefix_len = prefix.len();
+                prefix.push("t");
+                self.add_metric(prefix, value.time as i64, ElementType::Timestamp);
+                prefix.truncate(prefix_len);
+                prefix.push("i");
+                self.add_metric(prefix, value.increment as i64, ElementType::Timestamp);
+                prefix.truncate(prefix_len);
+            }
+            Bson::Int64(value) => self.add_metric(prefix, *value, ElementType::Int64),
+            Bson::Int32(value) => self.add_metric(prefix, *value as i64, ElementType::Int32),
+            Bson::Double(value) => self.add_metric(prefix, *value as i64, ElementType::Double),
+            Bson::Decimal128(value) => {
+                self.add_metric(prefix, decimal128_to_i64(value), ElementType::Decimal128)
+            }
+            Bson::Boolean(value) => {
+                self.add_metric(prefix, if *value { 1 } else { 0 }, ElementType::Boolean)
             }
-            Bson::Int64(value) => self.add_metric(prefix, *value),
-            Bson::Int32(value) => self.add_metric(prefix, *value as i64),
-            Bson::Double(value) => self.add_metric(prefix, *value as i64),
-            Bson::Boolean(value) => self.add_metric(prefix, if *value { 1 } else { 0 }),
-            _ => (), // TODO: Log
+            // These have no delta stream, and a change of type from a number to one of these
+            // starts a new chunk. The metric is therefore absent from this chunk as a whole, which
+            // the data set turns into a NaN gap spanning the chunk's samples.
+            Bson::Null => self
+                .warnings
+                .push(ParseWarning::NullMetric { key: prefix.clone() }),
+            Bson::Undefined | Bson::MinKey | Bson::MaxKey => (),
+            // Strings, like the host name and version in serverStatus, are part of every capture
+            // and were never meant to be metrics, so they aren't worth a warning.
+            Bson::String(_) => (),
+            _ => self.warnings.push(ParseWarning::UnsupportedType {
+                key: prefix.clone(),
+                element_type: elem.element_type(),
+            }),
         }
     }
 
@@ -98,10 +146,90 @@ impl MetricsDecoder {
         }
     }
 
-    fn add_metric(&mut self, key: &MetricKey, init_val: i64) {
+    fn add_metric(&mut self, key: &MetricKey, init_val: i64, element_type: ElementType) {
         let mut values = Vec::with_capacity(self.num_deltas + 1);
         values.push(init_val);
 
+        if !self.types.accepts(element_type) {
+            self.rejected.push(key.clone());
+        }
+
         self.metrics.push((key.clone(), values));
     }
 }
+
+// Converts the same way `as` does from a double: the fraction is truncated, values out of range
+// saturate, and NaN becomes zero.
+pub(super) fn decimal128_to_i64(value: &Decimal128) -> i64 {
+    let bits = u128::from_le_bytes(value.bytes());
+    let negative = bits >> 127 != 0;
+    let saturated = if negative { i64::MIN } else { i64::MAX };
+
+    // The five bits after the sign tell the special values from the finite ones, and which of the
+    // two layouts a finite one has.
+    let (exponent, coefficient) = match (bits >> 122) & 0x1f {
+        0x1f => return 0,
+        0x1e => return saturated,
+        combination if combination >> 3 == 0b11 => {
+            // The implied coefficient is larger than the maximum of 10^34 - 1, so the value is
+            // non-canonical, which the spec says to read as zero.
+            return 0;
+        }
+        _ => (((bits >> 113) & 0x3fff) as i32, bits & ((1 << 113) - 1)),
+    };
+    let exponent = exponent - DECIMAL128_EXPONENT_BIAS;
+
+    let magnitude = if exponent >= 0 {
+        10u128
+            .checked_pow(exponent as u32)
+            .and_then(|scale| coefficient.checked_mul(scale))
+    } else {
+        Some(match 10u128.checked_pow(-exponent as u32) {
+            Some(scale) => coefficient / scale,
+            None => 0,
+        })
+    };
+    match magnitude {
+        Some(magnitude) if negative && magnitude <= i64::MIN.unsigned_abs() as u128 => {
+            (magnitude as i128).wrapping_neg() as i64
+        }
+        Some(magnitude) if !negative && magnitude <= i64::MAX as u128 => magnitude as i64,
+        // Zero never needs saturating, however large the exponent.
+        _ if coefficient == 0 => 0,
+        _ => saturated,
+    }
+}
+
+const TIMESTAMP_FIELD: &str = "start";
+const DECIMAL128_EXPONENT_BIAS: i32 = 6176;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(negative: bool, exponent: i32, coefficient: u128) -> Decimal128 {
+        let biased = (exponent + DECIMAL128_EXPONENT_BIAS) as u128;
+        let bits = (negative as u128) << 127 | biased << 113 | coefficient;
+        Decimal128::from_bytes(bits.to_le_bytes())
+    }
+
+    #[test]
+    fn decimal128_is_truncated_toward_zero() {
+        assert_eq!(decimal128_to_i64(&decimal(false, 0, 42)), 42);
+        assert_eq!(decimal128_to_i64(&decimal(false, -1, 129)), 12);
+        assert_eq!(decimal128_to_i64(&decimal(true, -1, 129)), -12);
+        assert_eq!(decimal128_to_i64(&decimal(false, 3, 5)), 5000);
+        assert_eq!(decimal128_to_i64(&decimal(false, -40, 5)), 0);
+    }
+
+    #[test]
+    fn decimal128_out_of_range_saturates() {
+        assert_eq!(decimal128_to_i64(&decimal(false, 30, 1)), i64::MAX);
+        assert_eq!(decimal128_to_i64(&decimal(true, 30, 1)), i64::MIN);
+        assert_eq!(decimal128_to_i64(&decimal(false, 6000, 0)), 0);
+        let infinity = Decimal128::from_bytes((0x1e_u128 << 122).to_le_bytes());
+        assert_eq!(decimal128_to_i64(&infinity), i64::MAX);
+        let nan = Decimal128::from_bytes((0x1f_u128 << 122).to_le_bytes());
+        assert_eq!(decimal128_to_i64(&nan), 0);
+    }
+}
@@ -12,6 +12,12 @@ pub enum Error {
     #[error("error parsing BSON")]
     BSON(#[from] bson::de::Error),
 
+    #[error("not an FTDC file")]
+    NotFtdcFile,
+
+    #[error("no FTDC metrics files in the directory")]
+    NoMetricsFiles,
+
     #[error("unrecognized chunk type: {0}")]
     UnknownChunkType(i32),
 
@@ -20,6 +26,32 @@ pub enum Error {
 
     #[error("error decoding FTDC data")]
     InvalidNumericFormat(leb128::read::Error),
+
+    #[error("invalid chunk length: {0}")]
+    InvalidChunkLength(u32),
+
+    #[error("chunk is too large to be FTDC data")]
+    ChunkTooLarge,
+
+    #[error("chunk has no \"start\" field to take the sample times from")]
+    MissingTimestampField,
+
+    #[error("compressed chunk data ended after {actual} of {expected} bytes")]
+    IncompleteChunkData { expected: usize, actual: usize },
+
+    #[error("chunk data is corrupted")]
+    CorruptChunkData(#[source] std::io::Error),
+
+    // The chunk itself is intact, but doesn't decode the way this version of FTDC is laid out.
+    #[error(
+        "chunk declares {num_keys} metrics with {num_deltas} deltas each, but has only {available} \
+         bytes of deltas; the file may use a newer FTDC format"
+    )]
+    VersionMismatch {
+        num_keys: usize,
+        num_deltas: usize,
+        available: usize,
+    },
 }
 
 impl From<leb128::read::Error> for Error {
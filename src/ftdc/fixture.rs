@@ -0,0 +1,131 @@
+// Builds FTDC chunks and files for tests, laid out the way the server writes them.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::spec::BinarySubtype;
+use bson::{doc, Binary, Bson, DateTime, Document};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::decode::decimal128_to_i64;
+
+// A sample taken at `millis`, with `fields` after the start time, as serverStatus and the like would
+// be.
+pub fn sample(millis: i64, fields: Document) -> Document {
+    let mut sample = doc! { "start": DateTime::from_millis(millis) };
+    sample.extend(fields);
+    sample
+}
+
+pub fn metadata_chunk(millis: i64, doc: Document) -> Document {
+    doc! { "_id": DateTime::from_millis(millis), "type": 0, "doc": doc }
+}
+
+// The samples all need the same fields, of the same types, like those of a real chunk.
+pub fn data_chunk(samples: &[Document]) -> Document {
+    data_chunk_with(samples, compress(&payload(samples)), BinarySubtype::Generic)
+}
+
+// A data chunk for the samples, but with the given contents in place of the encoded samples.
+pub fn data_chunk_with(samples: &[Document], data: Vec<u8>, subtype: BinarySubtype) -> Document {
+    let start = samples[0].get_datetime("start").unwrap();
+    doc! {
+        "_id": *start,
+        "type": 1,
+        "data": Binary { subtype, bytes: data },
+    }
+}
+
+// The uncompressed contents of a data chunk: the first sample, the counts, and the deltas of the
+// rest of the samples, a metric at a time, with runs of zeroes collapsed.
+pub fn payload(samples: &[Document]) -> Vec<u8> {
+    let rows: Vec<_> = samples
+        .iter()
+        .map(|sample| {
+            let mut values = vec![];
+            flatten(&Bson::Document(sample.clone()), &mut values);
+            values
+        })
+        .collect();
+    let num_keys = rows[0].len();
+    let num_deltas = rows.len() - 1;
+
+    let mut deltas = Vec::with_capacity(num_keys * num_deltas);
+    for key_idx in 0..num_keys {
+        for pair in rows.windows(2) {
+            deltas.push(pair[1][key_idx].wrapping_sub(pair[0][key_idx]) as u64);
+        }
+    }
+
+    let mut payload = bson::to_vec(&samples[0]).unwrap();
+    payload.extend_from_slice(&(num_keys as u32).to_le_bytes());
+    payload.extend_from_slice(&(num_deltas as u32).to_le_bytes());
+    let mut idx = 0;
+    while idx < deltas.len() {
+        if deltas[idx] != 0 {
+            leb128::write::unsigned(&mut payload, deltas[idx]).unwrap();
+            idx += 1;
+            continue;
+        }
+        let run = deltas[idx..]
+            .iter()
+            .take_while(|delta| **delta == 0)
+            .count();
+        leb128::write::unsigned(&mut payload, 0).unwrap();
+        leb128::write::unsigned(&mut payload, (run - 1) as u64).unwrap();
+        idx += run;
+    }
+    payload
+}
+
+// Prefixed with the uncompressed length, as the `data` of a chunk is.
+pub fn compress(payload: &[u8]) -> Vec<u8> {
+    let mut data = (payload.len() as u32).to_le_bytes().to_vec();
+    let mut encoder = ZlibEncoder::new(&mut data, Compression::default());
+    encoder.write_all(payload).unwrap();
+    encoder.finish().unwrap();
+    data
+}
+
+pub fn file_bytes(chunks: &[Document]) -> Vec<u8> {
+    chunks
+        .iter()
+        .flat_map(|chunk| bson::to_vec(chunk).unwrap())
+        .collect()
+}
+
+pub fn write_file(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+// Tests run in parallel, so every path is different, even for the same name.
+pub fn temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "r2t2-test-{}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
+
+// In the order the decoder collects them in, including the fields of other types that it keeps, so
+// that the counts match.
+fn flatten(elem: &Bson, values: &mut Vec<i64>) {
+    match elem {
+        Bson::Document(doc) => doc.values().for_each(|elem| flatten(elem, values)),
+        Bson::Array(array) => array.iter().for_each(|elem| flatten(elem, values)),
+        Bson::DateTime(value) => values.push(value.timestamp_millis()),
+        Bson::Timestamp(value) => values.extend([value.time as i64, value.increment as i64]),
+        Bson::Int64(value) => values.push(*value),
+        Bson::Int32(value) => values.push(*value as i64),
+        Bson::Double(value) => values.push(*value as i64),
+        Bson::Decimal128(value) => values.push(decimal128_to_i64(value)),
+        Bson::Boolean(value) => values.push(*value as i64),
+        _ => (),
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use bson::spec::ElementType;
+
+use crate::metric::{MetricKey, Timestamp, TimestampFormat, KEY_PATH_SEPARATOR};
+
+#[derive(Debug, Clone)]
+pub enum ParseWarning {
+    UnsupportedType {
+        key: MetricKey,
+        element_type: ElementType,
+    },
+    NullMetric {
+        key: MetricKey,
+    },
+    KeyCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    OutOfOrderChunk {
+        start: Timestamp,
+        prev_end: Timestamp,
+    },
+    FileGap {
+        start: Timestamp,
+        prev_end: Timestamp,
+    },
+}
+
+impl ParseWarning {
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::UnsupportedType { .. } => "Ignored BSON types",
+            Self::NullMetric { .. } => "Null metrics",
+            Self::KeyCountMismatch { .. } => "Mismatched metric counts",
+            Self::OutOfOrderChunk { .. } => "Out-of-order timestamps",
+            Self::FileGap { .. } => "Gaps between files",
+        }
+    }
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedType { key, element_type } => {
+                write!(
+                    f,
+                    "{}: unsupported type {:?}",
+                    format_key(key),
+                    element_type
+                )
+            }
+            Self::NullMetric { key } => write!(f, "{}: null value", format_key(key)),
+            Self::KeyCountMismatch { expected, actual } => write!(
+                f,
+                "chunk header declares {} metrics, but the reference document has {}",
+                expected, actual
+            ),
+            Self::OutOfOrderChunk { start, prev_end } => write!(
+                f,
+                "chunk starting at {} precedes the end of the previous one at {}",
+                start.to_timestamp_string(),
+                prev_end.to_timestamp_string()
+            ),
+            Self::FileGap { start, prev_end } => write!(
+                f,
+                "no data from {} to {}, between the end of one file and the start of the next",
+                prev_end.to_timestamp_string(),
+                start.to_timestamp_string()
+            ),
+        }
+    }
+}
+
+// Warnings usually repeat in every chunk, so this counts them by category, and lists identical ones
+// once, with the number of times they occurred. Both are in order of first occurrence.
+pub fn summarize_warnings(
+    warnings: &[ParseWarning],
+) -> (Vec<(&'static str, usize)>, Vec<(String, usize)>) {
+    let mut categories: Vec<(&str, usize)> = vec![];
+    let mut messages: Vec<(String, usize)> = vec![];
+    let mut message_indices: HashMap<String, usize> = HashMap::new();
+    for warning in warnings {
+        match categories
+            .iter_mut()
+            .find(|(category, _)| *category == warning.category())
+        {
+            Some((_, count)) => *count += 1,
+            None => categories.push((warning.category(), 1)),
+        }
+
+        let message = warning.to_string();
+        match message_indices.get(&message) {
+            Some(&idx) => messages[idx].1 += 1,
+            None => {
+                message_indices.insert(message.clone(), messages.len());
+                messages.push((message, 1));
+            }
+        }
+    }
+    (categories, messages)
+}
+
+fn format_key(key: &MetricKey) -> String {
+    key.join(KEY_PATH_SEPARATOR)
+}
@@ -2,6 +2,12 @@ mod chart;
 mod layout;
 mod main_window;
 mod menu;
+mod metadata;
+mod notes;
+mod parse_report;
+mod png;
+mod raw_values;
+mod workspace;
 
 pub use main_window::{MainWindow, Update};
 
@@ -1,17 +1,20 @@
 use std::ops::RangeInclusive;
 
 use fltk::enums::{Color, Font};
+use thousands::Separable;
 
-use crate::metric::{unix_millis_to_timestamp, Timestamp};
+use crate::metric::{unix_millis_to_timestamp, DisplayZone, Timestamp};
 
 mod draw;
 mod widget;
 
 pub use self::draw::{
-    draw_data_fill, draw_data_line, draw_time_tick_labels, draw_time_tick_lines,
-    draw_value_tick_labels, draw_value_tick_lines,
+    draw_chart_stats, draw_data_fill, draw_data_line, draw_extreme_markers, draw_note_marker,
+    draw_point_count, draw_right_value_tick_labels, draw_right_value_tick_lines, draw_state_bands,
+    draw_time_tick_labels, draw_time_tick_lines, draw_value_tick_labels, draw_value_tick_lines,
+    draw_value_zones, time_at_x,
 };
-pub use self::widget::{ChartListData, ChartListSection, ChartListView, SectionState};
+pub use self::widget::{ChartListData, ChartListSection, ChartListView, HoverStyle, SectionState};
 
 pub type DataPoint = (Timestamp, f64);
 
@@ -25,6 +28,29 @@ pub struct ChartStyle {
     pub value_tick_color: Color,
     pub data_line_color: Color,
     pub data_fill_color: Color,
+    pub state_band_color: Color,
+    pub moving_average_color: Color,
+    pub smoothing_color: Color,
+    pub secondary_axis_color: Color,
+    pub snapshot_color: Color,
+    pub note_color: Color,
+    pub selection_color: Color,
+    // The colors of the overlaid lines, in turn, after the main one in `data_line_color`
+    pub overlay_colors: Vec<Color>,
+    pub fill_data: bool,
+    pub line_style: LineStyle,
+    pub cell_time_ticks: bool,
+    pub value_decimals: Option<usize>,
+    pub section_band_colors: Option<(Color, Color)>,
+    pub extreme_marker_colors: Option<(Color, Color)>,
+    pub point_counts: bool,
+    pub chart_stats: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineStyle {
+    Linear,
+    Step,
 }
 
 impl Default for ChartStyle {
@@ -38,6 +64,74 @@ impl Default for ChartStyle {
             value_tick_color: Color::Light1,
             data_line_color: Color::Foreground,
             data_fill_color: Color::from_hex(0xeeeeee),
+            state_band_color: Color::from_hex(0xb8d8b8),
+            moving_average_color: Color::from_hex(0xe07020),
+            smoothing_color: Color::from_hex(0x8030a0),
+            secondary_axis_color: Color::from_hex(0x2060c0),
+            snapshot_color: Color::from_hex(0xb0b0b0),
+            note_color: Color::from_hex(0xe0a000),
+            selection_color: Color::from_hex(0xd0e0f8),
+            overlay_colors: vec![
+                Color::from_hex(0xd03030),
+                Color::from_hex(0x2080d0),
+                Color::from_hex(0x30a040),
+                Color::from_hex(0xc060c0),
+                Color::from_hex(0x909020),
+            ],
+            fill_data: true,
+            line_style: LineStyle::Linear,
+            cell_time_ticks: true,
+            value_decimals: None,
+            section_band_colors: Some((Color::Background2, Color::from_hex(0xf3f5f9))),
+            extreme_marker_colors: None,
+            point_counts: false,
+            chart_stats: false,
+        }
+    }
+}
+
+impl ChartStyle {
+    // For when the app colors are dark, with the same settings as the default otherwise.
+    pub fn dark() -> Self {
+        Self {
+            time_tick_color: Color::from_hex(0x4a4e57),
+            value_tick_color: Color::from_hex(0x4a4e57),
+            data_fill_color: Color::from_hex(0x39424f),
+            state_band_color: Color::from_hex(0x2f4a33),
+            moving_average_color: Color::from_hex(0xf09050),
+            smoothing_color: Color::from_hex(0xc080e0),
+            secondary_axis_color: Color::from_hex(0x60a0f0),
+            snapshot_color: Color::from_hex(0x686c74),
+            note_color: Color::from_hex(0xf0c040),
+            selection_color: Color::from_hex(0x2f4466),
+            overlay_colors: vec![
+                Color::from_hex(0xf06060),
+                Color::from_hex(0x60b0f0),
+                Color::from_hex(0x70d080),
+                Color::from_hex(0xe090e0),
+                Color::from_hex(0xd0d060),
+            ],
+            section_band_colors: Some((Color::Background2, Color::from_hex(0x2c2f36))),
+            ..Self::default()
+        }
+    }
+
+    pub fn sparkline(self) -> Self {
+        Self { fill_data: false, cell_time_ticks: false, ..self }
+    }
+
+    // Takes the colors from `palette` and keeps the rest. Section banding stays off if it was.
+    pub fn with_colors_of(self, palette: Self) -> Self {
+        Self {
+            fill_data: self.fill_data,
+            line_style: self.line_style,
+            cell_time_ticks: self.cell_time_ticks,
+            value_decimals: self.value_decimals,
+            section_band_colors: self.section_band_colors.and(palette.section_band_colors),
+            extreme_marker_colors: self.extreme_marker_colors,
+            point_counts: self.point_counts,
+            chart_stats: self.chart_stats,
+            ..palette
         }
     }
 }
@@ -46,17 +140,41 @@ impl Default for ChartStyle {
 pub struct TimeAxis {
     pub range: RangeInclusive<Timestamp>,
     pub ticks: Vec<Timestamp>,
+    pub zone: DisplayZone,
+    // The lines aren't drawn across these
+    pub gaps: Vec<RangeInclusive<Timestamp>>,
 }
 
 #[derive(Debug)]
 pub struct ValueAxis {
     pub range: RangeInclusive<f64>,
     pub ticks: Vec<f64>,
+    pub scale: AxisScale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisScale {
+    Linear,
+    // The range of a logarithmic axis starts at a power of ten above zero, and anything below it is
+    // drawn at the bottom of the chart.
+    Log10,
 }
 
 pub type ChartData = Vec<DataPoint>;
 
-pub fn calculate_time_ticks(range: RangeInclusive<Timestamp>, max_ticks: usize) -> Vec<Timestamp> {
+#[derive(Debug, Clone, Copy)]
+pub struct ChartStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+// The ticks fall on round times in `zone`, going by its offset at the start of the range.
+pub fn calculate_time_ticks(
+    range: RangeInclusive<Timestamp>,
+    zone: DisplayZone,
+    max_ticks: usize,
+) -> Vec<Timestamp> {
     if max_ticks == 0 {
         return vec![];
     }
@@ -70,7 +188,9 @@ pub fn calculate_time_ticks(range: RangeInclusive<Timestamp>, max_ticks: usize)
         None => align_up_to(tick_delta, MILLIS_PER_DAY),
     };
 
-    let start_millis = align_up_to(range.start().timestamp_millis(), tick_delta);
+    let offset_millis = zone.offset_at(range.start()).local_minus_utc() as i64 * 1000;
+    let start_millis =
+        align_up_to(range.start().timestamp_millis() + offset_millis, tick_delta) - offset_millis;
     let tick_delta = chrono::Duration::milliseconds(tick_delta);
 
     let mut ticks = Vec::with_capacity(max_ticks);
@@ -82,11 +202,22 @@ pub fn calculate_time_ticks(range: RangeInclusive<Timestamp>, max_ticks: usize)
     ticks
 }
 
-pub fn calculate_value_ticks(max_value: f64, max_ticks: usize) -> Vec<f64> {
+pub fn calculate_value_ticks(
+    range: &RangeInclusive<f64>,
+    scale: AxisScale,
+    max_ticks: usize,
+) -> Vec<f64> {
     if max_ticks == 0 {
         return vec![];
     }
 
+    match scale {
+        AxisScale::Linear => linear_value_ticks(*range.end(), max_ticks),
+        AxisScale::Log10 => log_value_ticks(range, max_ticks),
+    }
+}
+
+fn linear_value_ticks(max_value: f64, max_ticks: usize) -> Vec<f64> {
     let magnitude = 10f64.powf(max_value.log10().floor());
     let mut tick_delta = max_value / max_ticks as f64 / magnitude;
     for td in VALUE_TICK_THRESHOLDS {
@@ -105,11 +236,110 @@ pub fn calculate_value_ticks(max_value: f64, max_ticks: usize) -> Vec<f64> {
     ticks
 }
 
+// Ticks go at 1, 2 and 5 times each power of ten, or only at the powers of ten, or even only at some
+// of them, if there isn't room for more.
+fn log_value_ticks(range: &RangeInclusive<f64>, max_ticks: usize) -> Vec<f64> {
+    let (floor, max_value) = (*range.start(), *range.end());
+    let decades = std::cmp::max(1, (max_value / floor).log10().ceil() as usize);
+    let (multiples, stride): (&[f64], usize) = if 3 * decades <= max_ticks {
+        (&[1.0, 2.0, 5.0], 1)
+    } else {
+        let stride = match max_ticks - 1 {
+            0 => decades + 1,
+            spaces => (decades + spaces - 1) / spaces,
+        };
+        (&[1.0], stride)
+    };
+
+    let mut ticks = Vec::with_capacity(max_ticks);
+    for decade in (0..=decades).step_by(stride) {
+        let power = floor * 10f64.powi(decade as i32);
+        for multiple in multiples {
+            let tick = power * multiple;
+            if tick <= max_value * LOG_TICK_TOLERANCE {
+                ticks.push(tick);
+            }
+        }
+    }
+    ticks
+}
+
+// Averages each point with the ones before it, up to `window` points in total. A gap, which is an
+// interval much longer than usual between two points, starts the window over, so that the average
+// doesn't mix values from both sides of it.
+pub fn moving_average(data: &ChartData, window: usize) -> ChartData {
+    let is_gap = gap_detector(data);
+
+    let mut result = Vec::with_capacity(data.len());
+    let mut start = 0;
+    let mut sum = 0.0;
+    for (idx, point) in data.iter().enumerate() {
+        if is_gap(idx) {
+            start = idx;
+            sum = 0.0;
+        }
+
+        sum += point.1;
+        if idx - start == window {
+            sum -= data[start].1;
+            start += 1;
+        }
+        result.push((point.0, sum / (idx + 1 - start) as f64));
+    }
+    result
+}
+
+// Each point moves the average towards itself by `alpha`, so higher values follow the data more
+// closely. Like with the moving average, a gap starts the average over.
+pub fn exponential_moving_average(data: &ChartData, alpha: f64) -> ChartData {
+    let is_gap = gap_detector(data);
+
+    let mut result = Vec::with_capacity(data.len());
+    let mut average = None;
+    for (idx, point) in data.iter().enumerate() {
+        if is_gap(idx) {
+            average = None;
+        }
+
+        let value = match average {
+            Some(average) => average + alpha * (point.1 - average),
+            None => point.1,
+        };
+        average = Some(value);
+        result.push((point.0, value));
+    }
+    result
+}
+
+// A gap is an interval much longer than the median one between two points.
+fn gap_detector(data: &ChartData) -> impl Fn(usize) -> bool + '_ {
+    let mut intervals: Vec<_> = data.windows(2).map(|pair| pair[1].0 - pair[0].0).collect();
+    intervals.sort();
+    let max_interval = intervals
+        .get(intervals.len() / 2)
+        .map(|median| *median * MOVING_AVERAGE_GAP_FACTOR);
+
+    move |idx| match (idx.checked_sub(1), max_interval) {
+        (Some(prev), Some(max_interval)) => data[idx].0 - data[prev].0 > max_interval,
+        _ => false,
+    }
+}
+
+pub fn format_value(value: f64, decimals: Option<usize>) -> String {
+    let text = match decimals {
+        Some(decimals) => format!("{:.*} ", decimals, value),
+        None => format!("{} ", (value * 1000.0).round() / 1000.0),
+    };
+    text.separate_with_commas()
+}
+
 fn align_up_to(value: i64, delta: i64) -> i64 {
     (value + delta - 1) / delta * delta
 }
 
+const LOG_TICK_TOLERANCE: f64 = 1.000_001;
 const MILLIS_PER_DAY: i64 = 86_400_000;
+const MOVING_AVERAGE_GAP_FACTOR: i32 = 3;
 const TIME_TICK_THRESHOLDS_MILLIS: &[i64] = {
     const fn sec(s: i64) -> i64 {
         s * 1000
@@ -1,12 +1,16 @@
-use std::ops::Sub;
+use std::ops::{RangeInclusive, Sub};
 
+use chrono::{DateTime, FixedOffset};
 use fltk::draw;
-use fltk::enums::Align;
+use fltk::enums::{Align, Color};
 use thousands::Separable;
 
 use crate::metric::Timestamp;
 
-use super::{ChartData, ChartStyle, DataPoint, TimeAxis, ValueAxis};
+use super::{
+    format_value, AxisScale, ChartData, ChartStats, ChartStyle, DataPoint, LineStyle, TimeAxis,
+    ValueAxis,
+};
 
 pub fn draw_time_tick_labels(
     x: i32,
@@ -20,20 +24,21 @@ pub fn draw_time_tick_labels(
     draw::set_draw_color(style.time_text_color);
 
     let xform = CoordTransform::from_time_axis(time_axis, x, w);
-    let mut last_tick: Option<Timestamp> = None;
+    let mut last_tick = None;
     for tick in time_axis.ticks.iter() {
         let tick_x = xform.transform(*tick);
+        let local_tick = time_axis.zone.to_local(tick);
 
         let include_date = last_tick
-            .map(|t| t.date_naive() != tick.date_naive())
+            .map(|t: DateTime<FixedOffset>| t.date_naive() != local_tick.date_naive())
             .unwrap_or(true);
         let fmt = if include_date { "%Y-%m-%d\n%H:%M:%S" } else { "\n%H:%M:%S" };
 
-        let text = tick.format(fmt).to_string();
+        let text = local_tick.format(fmt).to_string();
         let (text_w, _) = draw::measure(&text, false);
         draw::draw_text2(&text, tick_x - text_w / 2, y, text_w, h, Align::Center);
 
-        last_tick = Some(*tick);
+        last_tick = Some(local_tick);
     }
 }
 
@@ -61,6 +66,7 @@ pub fn draw_value_tick_labels(
     w: i32,
     h: i32,
     value_axis: &ValueAxis,
+    decimals: Option<usize>,
     style: &ChartStyle,
 ) {
     draw::set_font(style.value_text_font.0, style.value_text_font.1);
@@ -70,8 +76,7 @@ pub fn draw_value_tick_labels(
     for tick in value_axis.ticks.iter() {
         let tick_y = xform.transform(*tick);
 
-        let tick = (tick * 1000.0).round() / 1000.0;
-        let text = format!("{} ", tick).separate_with_commas();
+        let text = format_value(*tick, decimals);
         let (_, text_h) = draw::measure(&text, false);
         draw::draw_text2(&text, x, tick_y - text_h / 2, w, text_h, Align::Right);
     }
@@ -94,31 +99,92 @@ pub fn draw_value_tick_lines(
     }
 }
 
-pub fn draw_data_line(
+// The secondary axis is anchored to the right edge of the chart. Its labels go just inside the edge,
+// and instead of lines across the chart, which would be confused with those of the primary axis,
+// it only gets short ticks.
+pub fn draw_right_value_tick_labels(
     x: i32,
     y: i32,
     w: i32,
     h: i32,
-    time_axis: &TimeAxis,
     value_axis: &ValueAxis,
-    data: &ChartData,
+    decimals: Option<usize>,
     style: &ChartStyle,
 ) {
-    if data.is_empty() {
-        return;
+    draw::set_font(style.value_text_font.0, style.value_text_font.1);
+    draw::set_draw_color(style.secondary_axis_color);
+
+    let xform = CoordTransform::from_value_axis(value_axis, y, h);
+    for tick in value_axis.ticks.iter() {
+        let tick_y = xform.transform(*tick);
+
+        let text = format_value(*tick, decimals);
+        let (_, text_h) = draw::measure(&text, false);
+        draw::draw_text2(
+            &text,
+            x,
+            tick_y - text_h / 2,
+            w - RIGHT_VALUE_TICK_SIZE,
+            text_h,
+            Align::Right,
+        );
+    }
+}
+
+pub fn draw_right_value_tick_lines(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    value_axis: &ValueAxis,
+    style: &ChartStyle,
+) {
+    draw::set_draw_color(style.secondary_axis_color);
+
+    let xform = CoordTransform::from_value_axis(value_axis, y, h);
+    let right_x = x + w - 1;
+    for tick in value_axis.ticks.iter() {
+        let tick_y = xform.transform(*tick);
+        draw::draw_line(right_x - RIGHT_VALUE_TICK_SIZE + 1, tick_y, right_x, tick_y);
     }
+    draw::draw_line(right_x, y, right_x, y + h - 1);
+}
 
+pub fn draw_data_line(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    time_axis: &TimeAxis,
+    value_axis: &ValueAxis,
+    series: &[ChartData],
+    line_style: LineStyle,
+    style: &ChartStyle,
+) {
     let xform = PointTransform::new(x, y, w, h, time_axis, value_axis);
 
-    draw::set_draw_color(style.data_line_color);
-    draw::begin_line();
+    for (idx, data) in series.iter().enumerate() {
+        if data.is_empty() {
+            continue;
+        }
 
-    for pt in data.iter() {
-        let (pt_x, pt_y) = xform.transform(pt);
-        draw::vertex(pt_x as _, pt_y as _);
+        draw::set_draw_color(series_color(idx, style));
+        for piece in split_at_gaps(data, &time_axis.gaps) {
+            draw::begin_line();
+            draw_data_vertices(&xform, piece, line_style);
+            draw::end_line();
+        }
     }
+}
 
-    draw::end_line();
+// The first series is the main one, the rest are overlaid on it.
+fn series_color(idx: usize, style: &ChartStyle) -> Color {
+    match idx.checked_sub(1) {
+        Some(idx) if !style.overlay_colors.is_empty() => {
+            style.overlay_colors[idx % style.overlay_colors.len()]
+        }
+        _ => style.data_line_color,
+    }
 }
 
 pub fn draw_data_fill(
@@ -128,44 +194,270 @@ pub fn draw_data_fill(
     h: i32,
     time_axis: &TimeAxis,
     value_axis: &ValueAxis,
-    data: &ChartData,
+    series: &[ChartData],
+    line_style: LineStyle,
     style: &ChartStyle,
 ) {
-    if data.is_empty() {
+    if !style.fill_data {
         return;
     }
 
     let xform = PointTransform::new(x, y, w, h, time_axis, value_axis);
 
     draw::set_draw_color(style.data_fill_color);
-    draw::begin_complex_polygon();
+    let pieces = series
+        .iter()
+        .filter(|data| !data.is_empty())
+        .flat_map(|data| split_at_gaps(data, &time_axis.gaps));
+    for data in pieces {
+        draw::begin_complex_polygon();
 
-    let (left_bottom_x, _) = xform.transform(data.first().unwrap());
-    draw::vertex(left_bottom_x as _, xform.value_xform.coord_origin as _);
+        let (left_bottom_x, _) = xform.transform(data.first().unwrap());
+        draw::vertex(left_bottom_x as _, xform.value_xform.coord_origin as _);
+
+        draw_data_vertices(&xform, data, line_style);
+
+        let (right_bottom_x, _) = xform.transform(data.last().unwrap());
+        draw::vertex(right_bottom_x as _, xform.value_xform.coord_origin as _);
+
+        draw::end_complex_polygon();
+    }
+}
+
+// Splits the data between each pair of consecutive points on either side of a gap.
+fn split_at_gaps<'a>(
+    data: &'a [DataPoint],
+    gaps: &[RangeInclusive<Timestamp>],
+) -> Vec<&'a [DataPoint]> {
+    let straddles_gap = |left: &DataPoint, right: &DataPoint| {
+        gaps.iter()
+            .any(|gap| left.0 <= *gap.start() && right.0 >= *gap.end())
+    };
+    let mut pieces = vec![];
+    let mut rest = data;
+    while let Some(idx) = rest
+        .windows(2)
+        .position(|pair| straddles_gap(&pair[0], &pair[1]))
+    {
+        let (piece, tail) = rest.split_at(idx + 1);
+        pieces.push(piece);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
 
+fn draw_data_vertices(xform: &PointTransform, data: &[DataPoint], line_style: LineStyle) {
+    let mut prev_y = None;
     for pt in data.iter() {
         let (pt_x, pt_y) = xform.transform(pt);
+        if let (LineStyle::Step, Some(prev_y)) = (line_style, prev_y) {
+            draw::vertex(pt_x as _, prev_y as _);
+        }
         draw::vertex(pt_x as _, pt_y as _);
+        prev_y = Some(pt_y);
+    }
+}
+
+// Draws a band wherever the value is non-zero, from each point to the next, and a tick at the top
+// and bottom of the chart wherever the state changes.
+// Zones are in ascending order of where they start, and are cut off at the edges of the value axis.
+pub fn draw_value_zones(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    value_axis: &ValueAxis,
+    zones: &[(f64, Color)],
+) {
+    let xform = CoordTransform::from_value_axis(value_axis, y, h);
+    let (axis_min, axis_max) = (*value_axis.range.start(), *value_axis.range.end());
+
+    for (idx, (from, color)) in zones.iter().enumerate() {
+        let to = zones.get(idx + 1).map_or(axis_max, |next| next.0);
+        let (from, to) = (from.max(axis_min), to.min(axis_max));
+        if from >= to {
+            continue;
+        }
+        let top_y = xform.transform(to);
+        let bottom_y = xform.transform(from);
+        draw::draw_rect_fill(x, top_y, w, bottom_y - top_y + 1, *color);
+    }
+}
+
+pub fn draw_point_count(x: i32, y: i32, w: i32, h: i32, count: usize, style: &ChartStyle) {
+    draw::set_font(style.value_text_font.0, style.value_text_font.1);
+    draw::set_draw_color(style.value_tick_color);
+    let text = format!("{} pts", count.separate_with_commas());
+    draw::draw_text2(
+        &text,
+        x + POINT_COUNT_MARGIN,
+        y + POINT_COUNT_MARGIN,
+        w - 2 * POINT_COUNT_MARGIN,
+        h - 2 * POINT_COUNT_MARGIN,
+        Align::TopLeft | Align::Inside,
+    );
+}
+
+// Goes in the bottom left corner, across from the point count.
+pub fn draw_chart_stats(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    stats: &ChartStats,
+    decimals: Option<usize>,
+    style: &ChartStyle,
+) {
+    draw::set_font(style.value_text_font.0, style.value_text_font.1);
+    draw::set_draw_color(style.value_text_color);
+    let text = format!(
+        "min {} max {} avg {}",
+        format_value(stats.min, decimals),
+        format_value(stats.max, decimals),
+        format_value(stats.mean, decimals),
+    );
+    draw::draw_text2(
+        &text,
+        x + POINT_COUNT_MARGIN,
+        y + POINT_COUNT_MARGIN,
+        w - 2 * POINT_COUNT_MARGIN,
+        h - 2 * POINT_COUNT_MARGIN,
+        Align::BottomLeft | Align::Inside,
+    );
+}
+
+// A small triangle in the top right corner of the cell, like spreadsheets use for comments.
+pub fn draw_note_marker(x: i32, y: i32, w: i32, style: &ChartStyle) {
+    let right = x + w - 1;
+    draw::set_draw_color(style.note_color);
+    draw::draw_polygon(
+        right - NOTE_MARKER_SIZE,
+        y,
+        right,
+        y,
+        right,
+        y + NOTE_MARKER_SIZE,
+    );
+}
+
+pub fn draw_state_bands(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    time_axis: &TimeAxis,
+    data: &ChartData,
+    style: &ChartStyle,
+) {
+    let xform = CoordTransform::from_time_axis(time_axis, x, w);
+
+    for (idx, pt) in data.iter().enumerate() {
+        if pt.1 == 0.0 {
+            continue;
+        }
+        let start_x = xform.transform(pt.0);
+        let end_x = data
+            .get(idx + 1)
+            .map_or(start_x + 1, |next| xform.transform(next.0));
+        draw::draw_rect_fill(start_x, y, end_x - start_x, h, style.state_band_color);
     }
 
-    let (right_bottom_x, _) = xform.transform(data.last().unwrap());
-    draw::vertex(right_bottom_x as _, xform.value_xform.coord_origin as _);
+    draw::set_draw_color(style.data_line_color);
+    for pair in data.windows(2) {
+        if (pair[0].1 == 0.0) == (pair[1].1 == 0.0) {
+            continue;
+        }
+        let tick_x = xform.transform(pair[1].0);
+        draw::draw_line(tick_x, y, tick_x, y + STATE_TRANSITION_TICK_SIZE - 1);
+        draw::draw_line(
+            tick_x,
+            y + h - STATE_TRANSITION_TICK_SIZE,
+            tick_x,
+            y + h - 1,
+        );
+    }
+}
 
-    draw::end_complex_polygon();
+pub fn draw_extreme_markers(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    time_axis: &TimeAxis,
+    value_axis: &ValueAxis,
+    extremes: &(DataPoint, DataPoint),
+    style: &ChartStyle,
+) {
+    let (max_color, min_color) = match style.extreme_marker_colors {
+        Some(colors) => colors,
+        None => return,
+    };
+
+    let xform = PointTransform::new(x, y, w, h, time_axis, value_axis);
+    let (min, max) = extremes;
+
+    let (max_x, max_y) = xform.transform(max);
+    draw::set_draw_color(max_color);
+    draw::draw_polygon(
+        max_x - EXTREME_MARKER_SIZE,
+        max_y - 2 * EXTREME_MARKER_SIZE,
+        max_x + EXTREME_MARKER_SIZE,
+        max_y - 2 * EXTREME_MARKER_SIZE,
+        max_x,
+        max_y - 1,
+    );
+
+    let (min_x, min_y) = xform.transform(min);
+    draw::set_draw_color(min_color);
+    draw::draw_polygon(
+        min_x - EXTREME_MARKER_SIZE,
+        min_y + 2 * EXTREME_MARKER_SIZE,
+        min_x + EXTREME_MARKER_SIZE,
+        min_y + 2 * EXTREME_MARKER_SIZE,
+        min_x,
+        min_y + 1,
+    );
 }
 
 trait CoordInterpolate: Sub + Copy {
     fn interpolate(self, min: Self, span: Self::Output, coord_origin: i32, coord_span: i32) -> i32;
+
+    // Maps a value into the space the interpolation is done in, where `min` is already mapped.
+    fn scale(self, _scale: AxisScale, _min: Self) -> Self {
+        self
+    }
 }
 
+// A zero-width domain, such as a time range that starts and ends at the same instant, maps
+// everything to the origin.
+
 impl CoordInterpolate for f64 {
     fn interpolate(self, min: Self, span: Self::Output, coord_origin: i32, coord_span: i32) -> i32 {
+        if span == 0.0 {
+            return coord_origin;
+        }
         coord_origin + ((self - min) * coord_span as Self / span) as i32
     }
+
+    // Zero and negative values have no logarithm, so they end up at the bottom of the axis, along
+    // with anything else below it.
+    fn scale(self, scale: AxisScale, min: Self) -> Self {
+        match scale {
+            AxisScale::Linear => self,
+            AxisScale::Log10 if self > 0.0 => self.log10().max(min),
+            AxisScale::Log10 => min,
+        }
+    }
 }
 
 impl CoordInterpolate for i64 {
     fn interpolate(self, min: Self, span: Self::Output, coord_origin: i32, coord_span: i32) -> i32 {
+        if span == 0 {
+            return coord_origin;
+        }
         coord_origin + ((self - min) * coord_span as Self / span) as i32
     }
 }
@@ -189,6 +481,7 @@ where
     domain_span: D::Output,
     coord_origin: i32,
     coord_span: i32,
+    scale: AxisScale,
 }
 
 impl<D: CoordInterpolate> CoordTransform<D>
@@ -196,7 +489,7 @@ where
     D::Output: Copy,
 {
     fn transform(&self, domain_value: D) -> i32 {
-        domain_value.interpolate(
+        domain_value.scale(self.scale, self.domain_min).interpolate(
             self.domain_min,
             self.domain_span,
             self.coord_origin,
@@ -211,17 +504,51 @@ impl CoordTransform<Timestamp> {
         let domain_span = *time_axis.range.end() - domain_min;
         let coord_origin = x;
         let coord_span = w - 1;
-        Self { domain_min, domain_span, coord_origin, coord_span }
+        let scale = AxisScale::Linear;
+        Self {
+            domain_min,
+            domain_span,
+            coord_origin,
+            coord_span,
+            scale,
+        }
+    }
+
+    fn inverse(&self, coord: i32) -> Timestamp {
+        let offset = (coord - self.coord_origin) as i64;
+        let span = std::cmp::max(self.coord_span, 1) as i64;
+        self.domain_min
+            + chrono::Duration::milliseconds(self.domain_span.num_milliseconds() * offset / span)
     }
 }
 
+// The time drawn at `at_x` on a chart drawn at `x` with width `w`.
+pub fn time_at_x(time_axis: &TimeAxis, x: i32, w: i32, at_x: i32) -> Timestamp {
+    CoordTransform::from_time_axis(time_axis, x, w).inverse(at_x)
+}
+
 impl CoordTransform<f64> {
+    // A logarithmic axis interpolates between the logarithms of the values.
     fn from_value_axis(value_axis: &ValueAxis, y: i32, h: i32) -> Self {
-        let domain_min = *value_axis.range.start();
-        let domain_span = *value_axis.range.end() - domain_min;
+        let (start, end) = match value_axis.scale {
+            AxisScale::Linear => (*value_axis.range.start(), *value_axis.range.end()),
+            AxisScale::Log10 => (
+                value_axis.range.start().log10(),
+                value_axis.range.end().log10(),
+            ),
+        };
+        let domain_min = start;
+        let domain_span = end - start;
         let coord_origin = y + h - 1;
         let coord_span = -(h - 1);
-        Self { domain_min, domain_span, coord_origin, coord_span }
+        let scale = value_axis.scale;
+        Self {
+            domain_min,
+            domain_span,
+            coord_origin,
+            coord_span,
+            scale,
+        }
     }
 }
 
@@ -245,3 +572,46 @@ impl PointTransform {
         )
     }
 }
+
+const EXTREME_MARKER_SIZE: i32 = 4;
+const NOTE_MARKER_SIZE: i32 = 6;
+const POINT_COUNT_MARGIN: i32 = 2;
+const RIGHT_VALUE_TICK_SIZE: i32 = 5;
+const STATE_TRANSITION_TICK_SIZE: i32 = 4;
+
+#[cfg(test)]
+mod tests {
+    use crate::metric::unix_millis_to_timestamp;
+
+    use super::*;
+
+    fn points(millis: &[i64]) -> Vec<DataPoint> {
+        millis
+            .iter()
+            .map(|&millis| (unix_millis_to_timestamp(millis), millis as f64))
+            .collect()
+    }
+
+    #[test]
+    fn data_is_split_where_it_crosses_a_gap() {
+        let data = points(&[0, 1000, 2000, 60000, 61000, 120000]);
+        let gaps = [
+            unix_millis_to_timestamp(2000)..=unix_millis_to_timestamp(60000),
+            unix_millis_to_timestamp(61000)..=unix_millis_to_timestamp(120000),
+        ];
+
+        assert_eq!(
+            split_at_gaps(&data, &gaps),
+            [&data[..3], &data[3..5], &data[5..]]
+        );
+    }
+
+    #[test]
+    fn data_without_gaps_is_one_piece() {
+        let data = points(&[0, 1000, 2000]);
+        let gaps = [unix_millis_to_timestamp(5000)..=unix_millis_to_timestamp(9000)];
+
+        assert_eq!(split_at_gaps(&data, &gaps), [&data[..]]);
+        assert!(split_at_gaps(&[], &gaps).is_empty());
+    }
+}
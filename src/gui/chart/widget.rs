@@ -1,30 +1,45 @@
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
 use std::ops::{Range, RangeInclusive};
 use std::rc::Rc;
 
 use chrono::Duration;
-use fltk::app::{event_coords, event_is_click};
+use fltk::app::{event_coords, event_is_click, event_mouse_button, MouseButton};
 use fltk::enums::{Align, Color, Damage, Event, Font, FrameType};
+use fltk::image::RgbImage;
+use fltk::misc::Tooltip;
 use fltk::prelude::*;
+use fltk::surface::ImageSurface;
 use fltk::table::{Table, TableContext};
 use fltk::widget::Widget;
-use thousands::Separable;
 
 use crate::gui::ScopedClip;
-use crate::metric::{Descriptor, Timestamp, TimestampFormat};
+use crate::metric::{
+    Descriptor, DisplayZone, MetricKey, Timestamp, TimestampFormat, KEY_PATH_SEPARATOR,
+};
+use crate::{lookup_value, LookupMode};
 
 use super::{
-    calculate_time_ticks, calculate_value_ticks, draw_data_fill, draw_data_line,
-    draw_time_tick_labels, draw_time_tick_lines, draw_value_tick_labels, draw_value_tick_lines,
-    ChartData, ChartStyle, DataPoint, TimeAxis, ValueAxis,
+    calculate_time_ticks, calculate_value_ticks, draw_chart_stats, draw_data_fill, draw_data_line,
+    draw_extreme_markers, draw_note_marker, draw_point_count, draw_right_value_tick_labels,
+    draw_right_value_tick_lines, draw_state_bands, draw_time_tick_labels, draw_time_tick_lines,
+    draw_value_tick_labels, draw_value_tick_lines, draw_value_zones, exponential_moving_average,
+    format_value, moving_average, time_at_x, AxisScale, ChartData, ChartStats, ChartStyle,
+    DataPoint, LineStyle, TimeAxis, ValueAxis,
 };
 
 #[derive(Clone)]
 pub struct ChartListView {
     table: Table,
     state: Rc<RefCell<ChartListState>>,
+    context_menu_cb: Rc<RefCell<Option<ContextMenuCallback>>>,
+    zoom_cb: Rc<RefCell<Option<ZoomCallback>>>,
 }
 
+type ContextMenuCallback = Box<dyn FnMut(Rc<Descriptor>)>;
+type ZoomCallback = Box<dyn FnMut(RangeInclusive<Timestamp>)>;
+
 pub type ChartListData = Vec<ChartListSection>;
 
 pub struct ChartListSection {
@@ -33,7 +48,7 @@ pub struct ChartListSection {
     pub charts: Vec<(Rc<Descriptor>, Vec<DataPoint>)>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SectionState {
     Expanded,
     Collapsed,
@@ -50,11 +65,41 @@ struct ChartListState {
     value_axis_width: i32,
     value_ticks: usize,
     hover_style: HoverStyle,
+    line_styles: HashMap<usize, LineStyle>,
+    names: HashMap<usize, String>,
+    boolean_keys: HashSet<MetricKey>,
+    gaps: Vec<RangeInclusive<Timestamp>>,
+    moving_averages: HashSet<usize>,
+    moving_average_window: usize,
+    smoothing: HashMap<usize, f64>,
+    point_counts: HashMap<usize, usize>,
+    // Keyed by the dotted path of the metric
+    notes: HashMap<String, String>,
+    // FLTK holds on to the text of a tooltip for as long as it likes, so each one is kept for good.
+    note_tips: HashMap<String, &'static CStr>,
+    secondaries: HashMap<usize, Rc<Descriptor>>,
+    overlays: HashMap<usize, Vec<ChartData>>,
+    show_values_in_key: bool,
+    // Set from the context menu, over what the descriptors say
+    axis_scales: HashMap<usize, AxisScale>,
+    snapshot: Option<HashMap<MetricKey, ChartData>>,
+    zone: DisplayZone,
     time_axis: Option<TimeAxis>,
     charts: Vec<Chart>,
     sections: Vec<Section>,
     rows: Vec<ChartListRow>,
     hover: Option<Hover>,
+    drag: Option<Drag>,
+}
+
+// A selection of a time range, made by dragging across the charts. The coordinates are those of
+// the window, like the hover.
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    start_x: i32,
+    end_x: i32,
+    cell_x: i32,
+    cell_w: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -75,6 +120,21 @@ impl Default for HoverStyle {
 }
 
 struct Chart {
+    desc: Rc<Descriptor>,
+    section_idx: usize,
+    value_axis: ValueAxis,
+    // The main series first, then the overlaid ones
+    series: Vec<ChartData>,
+    extremes: Option<(DataPoint, DataPoint)>,
+    moving_average: Option<ChartData>,
+    smoothed: Option<ChartData>,
+    secondary: Option<SecondarySeries>,
+    snapshot: Option<ChartData>,
+    zones: Vec<(f64, Color)>,
+}
+
+// A series drawn over a chart against its own value axis, on the right.
+struct SecondarySeries {
     desc: Rc<Descriptor>,
     value_axis: ValueAxis,
     data: ChartData,
@@ -101,6 +161,7 @@ impl std::ops::Not for SectionState {
     }
 }
 
+#[derive(PartialEq)]
 struct Hover {
     extent: (i32, i32, i32, i32),
     time_text: String,
@@ -108,6 +169,9 @@ struct Hover {
     value_text: String,
     value_extent: (i32, i32, i32, i32),
     tick_x: Option<i32>,
+    time: Timestamp,
+    chart_idx: usize,
+    scroll_pos: i32,
 }
 
 impl Default for ChartListView {
@@ -137,11 +201,28 @@ impl ChartListView {
             value_axis_width: 100,
             value_ticks: 5,
             hover_style: Default::default(),
+            line_styles: HashMap::new(),
+            names: HashMap::new(),
+            boolean_keys: HashSet::new(),
+            gaps: vec![],
+            moving_averages: HashSet::new(),
+            moving_average_window: 10,
+            smoothing: HashMap::new(),
+            point_counts: HashMap::new(),
+            notes: HashMap::new(),
+            note_tips: HashMap::new(),
+            secondaries: HashMap::new(),
+            overlays: HashMap::new(),
+            show_values_in_key: false,
+            axis_scales: HashMap::new(),
+            snapshot: None,
+            zone: DisplayZone::Utc,
             time_axis: None,
             charts: Vec::new(),
             sections: Vec::new(),
             rows: Vec::new(),
             hover: None,
+            drag: None,
         };
 
         table.set_col_width(0, state.value_axis_width);
@@ -156,19 +237,37 @@ impl ChartListView {
                 draw_cell(table, &state, ctx, row, col, x, y, w, h)
             }
         });
+        let context_menu_cb = Rc::new(RefCell::new(None));
+        let zoom_cb = Rc::new(RefCell::new(None));
         table.handle({
             let state = Rc::clone(&state);
+            let context_menu_cb = Rc::clone(&context_menu_cb);
+            let zoom_cb = Rc::clone(&zoom_cb);
             move |table, event| {
                 match event {
                     Event::Move | Event::MouseWheel => Self::on_mouse(event, table, &state),
-                    Event::Released if event_is_click() => Self::on_click(table, &state),
+                    Event::Push if matches!(event_mouse_button(), MouseButton::Right) => {
+                        return Self::on_context_menu(table, &state, &context_menu_cb);
+                    }
+                    Event::Push if matches!(event_mouse_button(), MouseButton::Left) => {
+                        return Self::on_drag_start(table, &state);
+                    }
+                    Event::Drag => return Self::on_drag(table, &state),
+                    Event::Released => {
+                        if Self::on_drag_end(table, &state, &zoom_cb) {
+                            return true;
+                        }
+                        if event_is_click() {
+                            Self::on_click(table, &state);
+                        }
+                    }
                     _ => (),
                 };
                 false
             }
         });
 
-        Self { table, state }
+        Self { table, state, context_menu_cb, zoom_cb }
     }
 
     pub fn widget(&self) -> Widget {
@@ -198,7 +297,6 @@ impl ChartListView {
         self.table.redraw();
     }
 
-    #[allow(dead_code)]
     pub fn hover_style(&self) -> HoverStyle {
         self.state.borrow().hover_style.clone()
     }
@@ -210,12 +308,249 @@ impl ChartListView {
         self.table.redraw();
     }
 
+    pub fn line_style(&self, desc_id: usize) -> Option<LineStyle> {
+        self.state.borrow().line_styles.get(&desc_id).copied()
+    }
+
+    pub fn set_line_style(&mut self, desc_id: usize, line_style: Option<LineStyle>) {
+        {
+            let mut state = self.state.borrow_mut();
+            match line_style {
+                Some(line_style) => state.line_styles.insert(desc_id, line_style),
+                None => state.line_styles.remove(&desc_id),
+            };
+        }
+        self.table.redraw();
+    }
+
+    pub fn axis_scale(&self, desc: &Descriptor) -> AxisScale {
+        axis_scale_for(&self.state.borrow().axis_scales, desc)
+    }
+
+    pub fn set_axis_scale(&mut self, desc_id: usize, scale: AxisScale) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.axis_scales.insert(desc_id, scale);
+            state.update_value_axes();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_axis_scales(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.axis_scales.clear();
+            state.update_value_axes();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_line_styles(&mut self) {
+        {
+            self.state.borrow_mut().line_styles.clear();
+        }
+        self.table.redraw();
+    }
+
+    pub fn name(&self, desc: &Descriptor) -> String {
+        let state = self.state.borrow();
+        state.chart_name(desc).to_string()
+    }
+
+    pub fn set_name(&mut self, desc_id: usize, name: Option<String>) {
+        {
+            let mut state = self.state.borrow_mut();
+            match name {
+                Some(name) => state.names.insert(desc_id, name),
+                None => state.names.remove(&desc_id),
+            };
+        }
+        self.table.redraw();
+    }
+
+    pub fn set_boolean_keys(&mut self, keys: HashSet<MetricKey>) {
+        {
+            self.state.borrow_mut().boolean_keys = keys;
+        }
+        self.table.redraw();
+    }
+
+    pub fn set_gaps(&mut self, gaps: Vec<RangeInclusive<Timestamp>>) {
+        {
+            let mut state = self.state.borrow_mut();
+            if let Some(time_axis) = state.time_axis.as_mut() {
+                time_axis.gaps = gaps.clone();
+            }
+            state.gaps = gaps;
+        }
+        self.table.redraw();
+    }
+
+    pub fn has_moving_average(&self, desc_id: usize) -> bool {
+        self.state.borrow().moving_averages.contains(&desc_id)
+    }
+
+    pub fn set_moving_average(&mut self, desc_id: usize, enabled: bool) {
+        {
+            let mut state = self.state.borrow_mut();
+            if enabled {
+                state.moving_averages.insert(desc_id);
+            } else {
+                state.moving_averages.remove(&desc_id);
+            }
+            state.update_moving_averages();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_moving_averages(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.moving_averages.clear();
+            state.update_moving_averages();
+        }
+        self.table.redraw();
+    }
+
+    pub fn moving_average_window(&self) -> usize {
+        self.state.borrow().moving_average_window
+    }
+
+    pub fn set_moving_average_window(&mut self, window: usize) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.moving_average_window = window;
+            state.update_moving_averages();
+        }
+        self.table.redraw();
+    }
+
+    pub fn smoothing(&self, desc_id: usize) -> Option<f64> {
+        self.state.borrow().smoothing.get(&desc_id).copied()
+    }
+
+    // `alpha` is the weight of each new point in the exponential moving average, or `None` to turn
+    // smoothing off.
+    pub fn set_smoothing(&mut self, desc_id: usize, alpha: Option<f64>) {
+        {
+            let mut state = self.state.borrow_mut();
+            match alpha {
+                Some(alpha) => state.smoothing.insert(desc_id, alpha),
+                None => state.smoothing.remove(&desc_id),
+            };
+            state.update_smoothing();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_smoothing(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.smoothing.clear();
+            state.update_smoothing();
+        }
+        self.table.redraw();
+    }
+
+    // How many points of each metric the data has in the time range, before sampling. Charts
+    // without one show how many they were given instead.
+    pub fn set_point_counts(&mut self, counts: HashMap<usize, usize>) {
+        self.state.borrow_mut().point_counts = counts;
+        self.table.redraw();
+    }
+
+    // The series overlaid on each chart, by descriptor ID, in the order of the keys in its
+    // descriptor. They take effect with the next data that is set.
+    pub fn set_overlays(&mut self, overlays: HashMap<usize, Vec<ChartData>>) {
+        self.state.borrow_mut().overlays = overlays;
+    }
+
+    // The key column then also shows the value of each chart at the hover, or its last value.
+    pub fn set_show_values_in_key(&mut self, show: bool) {
+        self.state.borrow_mut().show_values_in_key = show;
+        self.table.redraw();
+    }
+
+    pub fn set_notes(&mut self, notes: HashMap<String, String>) {
+        self.state.borrow_mut().notes = notes;
+        self.table.redraw();
+    }
+
+    pub fn secondary(&self, desc_id: usize) -> Option<Rc<Descriptor>> {
+        self.state.borrow().secondaries.get(&desc_id).cloned()
+    }
+
+    // The secondary series comes from the data of the chart list itself, so it has to be one of the
+    // charts, though not necessarily a visible one.
+    pub fn set_secondary(&mut self, desc_id: usize, secondary: Option<Rc<Descriptor>>) {
+        {
+            let mut state = self.state.borrow_mut();
+            match secondary {
+                Some(secondary) => state.secondaries.insert(desc_id, secondary),
+                None => state.secondaries.remove(&desc_id),
+            };
+            state.update_secondaries();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_secondaries(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.secondaries.clear();
+            state.update_secondaries();
+        }
+        self.table.redraw();
+    }
+
+    #[allow(dead_code)]
+    pub fn has_snapshot(&self) -> bool {
+        self.state.borrow().snapshot.is_some()
+    }
+
+    // Descriptor IDs don't survive reloading the data, so the snapshot is kept by metric key, which
+    // leaves out derived charts.
+    pub fn take_snapshot(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            let snapshot = state
+                .charts
+                .iter()
+                .filter(|chart| !chart.desc.key.is_empty())
+                .map(|chart| (chart.desc.key.clone(), chart.data().clone()))
+                .collect();
+            state.snapshot = Some(snapshot);
+            state.update_snapshots();
+        }
+        self.table.redraw();
+    }
+
+    pub fn clear_snapshot(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.snapshot = None;
+            state.update_snapshots();
+        }
+        self.table.redraw();
+    }
+
+    pub fn set_context_menu_callback<F: FnMut(Rc<Descriptor>) + 'static>(&mut self, cb: F) {
+        *self.context_menu_cb.borrow_mut() = Some(Box::new(cb));
+    }
+
+    // Called with the time range selected by dragging across the charts.
+    pub fn set_zoom_callback<F: FnMut(RangeInclusive<Timestamp>) + 'static>(&mut self, cb: F) {
+        *self.zoom_cb.borrow_mut() = Some(Box::new(cb));
+    }
+
     pub fn set_time_range<R: Into<Option<RangeInclusive<Timestamp>>>>(&mut self, time_range: R) {
         let mut state = self.state.borrow_mut();
 
         state.time_axis = time_range.into().map(|range| TimeAxis {
             range: range.clone(),
-            ticks: calculate_time_ticks(range, state.time_ticks),
+            ticks: calculate_time_ticks(range, state.zone, state.time_ticks),
+            zone: state.zone,
+            gaps: state.gaps.clone(),
         });
 
         drop(state);
@@ -245,20 +580,102 @@ impl ChartListView {
                     let chart_idx = state.charts.len();
                     state.rows.push(ChartListRow::Chart(chart_idx));
                 }
-                state.charts.push(Chart::new(desc, points, value_ticks));
+                let mut series = vec![points];
+                series.extend(state.overlays.get(&desc.id).cloned().unwrap_or_default());
+                let scale = axis_scale_for(&state.axis_scales, &desc);
+                state
+                    .charts
+                    .push(Chart::new(desc, section_idx, series, value_ticks, scale));
             }
         }
+        state.update_moving_averages();
+        state.update_smoothing();
+        state.update_secondaries();
+        state.update_snapshots();
 
         drop(state);
         self.update_rows();
     }
 
-    pub fn section_count(&self) -> usize {
-        self.state.borrow().sections.len()
+    // The data as it was last set, sampled for the current time range.
+    pub fn data(&self) -> ChartListData {
+        let state = self.state.borrow();
+        state
+            .sections
+            .iter()
+            .map(|section| ChartListSection {
+                name: section.name.clone(),
+                state: section.state,
+                charts: state.charts[section.chart_idx_range.clone()]
+                    .iter()
+                    .map(|chart| (Rc::clone(&chart.desc), chart.data().clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn capture(&self) -> Option<RgbImage> {
+        let hover = self.state.borrow_mut().hover.take();
+
+        let surface = ImageSurface::new(self.table.w(), self.table.h(), false);
+        ImageSurface::push_current(&surface);
+        surface.draw(&self.table, 0, 0);
+        let image = surface.image();
+        ImageSurface::pop_current();
+
+        self.state.borrow_mut().hover = hover;
+        image
+    }
+
+    // Draws all of the rows, whether they are scrolled into view or not, through the same code that
+    // draws them on the screen.
+    pub fn capture_all(&self) -> Option<RgbImage> {
+        let (hover, drag) = {
+            let mut state = self.state.borrow_mut();
+            (state.hover.take(), state.drag.take())
+        };
+
+        let table = &self.table;
+        let col_widths: Vec<_> = (0..table.cols()).map(|col| table.col_width(col)).collect();
+        let header_h = if table.col_header() { table.col_header_height() } else { 0 };
+        let width = col_widths.iter().sum();
+        let height = header_h
+            + (0..table.rows())
+                .map(|row| table.row_height(row))
+                .sum::<i32>();
+
+        let surface = ImageSurface::new(width, height, false);
+        ImageSurface::push_current(&surface);
+        fltk::draw::draw_rect_fill(0, 0, width, height, Color::Background2);
+        let draw_row = |ctx, row, y, h| {
+            let mut x = 0;
+            for (col, &w) in col_widths.iter().enumerate() {
+                draw_cell(table, &self.state, ctx, row, col as i32, x, y, w, h);
+                x += w;
+            }
+        };
+        if header_h > 0 {
+            draw_row(TableContext::ColHeader, 0, 0, header_h);
+        }
+        let mut y = header_h;
+        for row in 0..table.rows() {
+            let h = table.row_height(row);
+            draw_row(TableContext::Cell, row, y, h);
+            y += h;
+        }
+        let image = surface.image();
+        ImageSurface::pop_current();
+
+        let mut state = self.state.borrow_mut();
+        state.hover = hover;
+        state.drag = drag;
+        image
     }
 
-    pub fn section_state(&self, idx: usize) -> SectionState {
-        self.state.borrow().sections[idx].state
+    pub fn section_state(&self, name: &str) -> Option<SectionState> {
+        let state = self.state.borrow();
+        let section = state.sections.iter().find(|section| section.name == name)?;
+        Some(section.state)
     }
 
     #[allow(dead_code)]
@@ -271,7 +688,6 @@ impl ChartListView {
         self.table.y()
     }
 
-    #[allow(dead_code)]
     pub fn w(&self) -> i32 {
         self.table.w()
     }
@@ -281,6 +697,7 @@ impl ChartListView {
         self.table.h()
     }
 
+    #[allow(dead_code)]
     pub fn value_axis_width(&self) -> i32 {
         self.state.borrow().value_axis_width
     }
@@ -302,7 +719,12 @@ impl ChartListView {
 
         state.value_ticks = ticks;
         for chart in state.charts.iter_mut() {
-            chart.value_axis.ticks = calculate_value_ticks(*chart.value_axis.range.end(), ticks);
+            let axis = &mut chart.value_axis;
+            axis.ticks = calculate_value_ticks(&axis.range, axis.scale, ticks);
+            if let Some(secondary) = chart.secondary.as_mut() {
+                let axis = &mut secondary.value_axis;
+                axis.ticks = calculate_value_ticks(&axis.range, axis.scale, ticks);
+            }
         }
 
         drop(state);
@@ -330,6 +752,27 @@ impl ChartListView {
         self.table.redraw();
     }
 
+    pub fn display_zone(&self) -> DisplayZone {
+        self.state.borrow().zone
+    }
+
+    pub fn set_display_zone(&mut self, zone: DisplayZone) {
+        let mut state = self.state.borrow_mut();
+        if state.zone == zone {
+            return;
+        }
+
+        state.zone = zone;
+        let time_ticks = state.time_ticks;
+        if let Some(time_axis) = state.time_axis.as_mut() {
+            time_axis.ticks = calculate_time_ticks(time_axis.range.clone(), zone, time_ticks);
+            time_axis.zone = zone;
+        }
+
+        drop(state);
+        self.table.redraw();
+    }
+
     #[allow(dead_code)]
     pub fn set_time_ticks(&mut self, ticks: usize) {
         let mut state = self.state.borrow_mut();
@@ -338,8 +781,9 @@ impl ChartListView {
         }
 
         state.time_ticks = ticks;
+        let zone = state.zone;
         if let Some(time_axis) = state.time_axis.as_mut() {
-            time_axis.ticks = calculate_time_ticks(time_axis.range.clone(), ticks);
+            time_axis.ticks = calculate_time_ticks(time_axis.range.clone(), zone, ticks);
         }
 
         drop(state);
@@ -357,12 +801,15 @@ impl ChartListView {
         self.table.col_width(1)
     }
 
-    #[allow(dead_code)]
     pub fn set_chart_width(&mut self, width: i32) {
         self.table.set_col_width(1, width);
         self.table.redraw();
     }
 
+    pub fn chart_height(&self) -> i32 {
+        self.state.borrow().chart_height
+    }
+
     pub fn set_chart_height(&mut self, height: i32) {
         let mut state = self.state.borrow_mut();
         state.chart_height = height;
@@ -408,9 +855,7 @@ impl ChartListView {
             Err(_) => return,
         };
 
-        if let Some(hover) = state.hover.as_ref() {
-            hover.apply_damage(table);
-        }
+        let old_hover = state.hover.take();
 
         state.hover = match event {
             Event::Move => Hover::at_cursor(&table, &state),
@@ -418,11 +863,45 @@ impl ChartListView {
             _ => unreachable!(),
         };
 
-        if let Some(hover) = state.hover.as_ref() {
-            hover.apply_damage(table);
+        Hover::apply_damage(
+            old_hover.as_ref(),
+            state.hover.as_ref(),
+            state.show_values_in_key,
+            table,
+        );
+
+        if let Event::Move = event {
+            Self::show_note_tip(table, &mut state);
         }
     }
 
+    fn show_note_tip(table: &Table, state: &mut ChartListState) {
+        let (row, col) = match table.cursor2rowcol() {
+            Some((TableContext::Cell, row, col, _)) if col == 2 => (row, col),
+            _ => return,
+        };
+        let note = match &state.rows[row as usize] {
+            ChartListRow::Chart(chart_idx) => {
+                let path = state.charts[*chart_idx].desc.key.join(KEY_PATH_SEPARATOR);
+                state.notes.get(&path)
+            }
+            ChartListRow::Section(_) => None,
+        };
+        let note = match note {
+            Some(note) => note.clone(),
+            None => return,
+        };
+        let tip = *state.note_tips.entry(note).or_insert_with_key(|note| {
+            let tip = CString::new(note.replace('\0', "")).unwrap_or_default();
+            Box::leak(tip.into_boxed_c_str())
+        });
+        let (x, y, w, h) = match table.find_cell(TableContext::Cell, row, col) {
+            Some(cell) => cell,
+            None => return,
+        };
+        Tooltip::enter_area(table, x, y, w, h, tip);
+    }
+
     fn on_click(table: &mut Table, state: &Rc<RefCell<ChartListState>>) {
         let (ctx, row, _, _) = match table.cursor2rowcol() {
             Some(tuple) => tuple,
@@ -461,6 +940,105 @@ impl ChartListView {
         Self::update_table_rows(table, &state.borrow());
     }
 
+    // Only a press on a chart starts a selection. Anything else is left to the table, so that
+    // clicking a section heading still expands or collapses it.
+    fn on_drag_start(table: &mut Table, state: &Rc<RefCell<ChartListState>>) -> bool {
+        let (row, col) = match table.cursor2rowcol() {
+            Some((TableContext::Cell, row, col, _)) if col == 1 => (row, col),
+            _ => return false,
+        };
+        let mut state = state.borrow_mut();
+        if state.time_axis.is_none() || !matches!(state.rows[row as usize], ChartListRow::Chart(_))
+        {
+            return false;
+        }
+        let (cell_x, _, cell_w, _) = match table.find_cell(TableContext::Cell, row, col) {
+            Some(cell) => cell,
+            None => return false,
+        };
+        let (x, _) = event_coords();
+        state.drag = Some(Drag { start_x: x, end_x: x, cell_x, cell_w });
+        true
+    }
+
+    fn on_drag(table: &mut Table, state: &Rc<RefCell<ChartListState>>) -> bool {
+        let mut state = state.borrow_mut();
+        let drag = match state.drag.as_mut() {
+            Some(drag) => drag,
+            None => return false,
+        };
+        let (x, _) = event_coords();
+        drag.end_x = x.clamp(drag.cell_x, drag.cell_x + drag.cell_w - 1);
+        table.redraw();
+        true
+    }
+
+    // A drag too short to tell from a click doesn't zoom.
+    fn on_drag_end(
+        table: &mut Table,
+        state: &Rc<RefCell<ChartListState>>,
+        zoom_cb: &Rc<RefCell<Option<ZoomCallback>>>,
+    ) -> bool {
+        let range = {
+            let mut state = state.borrow_mut();
+            let drag = match state.drag.take() {
+                Some(drag) => drag,
+                None => return false,
+            };
+            table.redraw();
+            if (drag.end_x - drag.start_x).abs() < DRAG_ZOOM_THRESHOLD {
+                return false;
+            }
+            let time_axis = match state.time_axis.as_ref() {
+                Some(time_axis) => time_axis,
+                None => return true,
+            };
+            let (start_x, end_x) = if drag.start_x <= drag.end_x {
+                (drag.start_x, drag.end_x)
+            } else {
+                (drag.end_x, drag.start_x)
+            };
+            let start = time_at_x(time_axis, drag.cell_x, drag.cell_w, start_x);
+            let end = time_at_x(time_axis, drag.cell_x, drag.cell_w, end_x);
+            start..=end
+        };
+
+        if let Some(cb) = zoom_cb.borrow_mut().as_mut() {
+            cb(range);
+        }
+        true
+    }
+
+    fn on_context_menu(
+        table: &mut Table,
+        state: &Rc<RefCell<ChartListState>>,
+        context_menu_cb: &Rc<RefCell<Option<ContextMenuCallback>>>,
+    ) -> bool {
+        let (ctx, row, _, _) = match table.cursor2rowcol() {
+            Some(tuple) => tuple,
+            None => return false,
+        };
+        if ctx != TableContext::Cell {
+            return false;
+        }
+
+        let desc = {
+            let state = state.borrow();
+            match state.rows[row as usize] {
+                ChartListRow::Chart(chart_idx) => Rc::clone(&state.charts[chart_idx].desc),
+                ChartListRow::Section(_) => return false,
+            }
+        };
+
+        match context_menu_cb.borrow_mut().as_mut() {
+            Some(cb) => {
+                cb(desc);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn update_table_rows(table: &mut Table, state: &ChartListState) {
         if state.time_axis.is_some() {
             table.set_rows(state.rows.len() as i32);
@@ -481,17 +1059,149 @@ impl ChartListView {
     }
 }
 
+impl ChartListState {
+    fn chart_name<'d>(&'d self, desc: &'d Descriptor) -> &'d str {
+        self.names.get(&desc.id).unwrap_or(&desc.name)
+    }
+
+    fn update_moving_averages(&mut self) {
+        for chart in self.charts.iter_mut() {
+            chart.moving_average = self
+                .moving_averages
+                .contains(&chart.desc.id)
+                .then(|| moving_average(chart.data(), self.moving_average_window));
+        }
+    }
+
+    fn update_smoothing(&mut self) {
+        for chart in self.charts.iter_mut() {
+            chart.smoothed = self
+                .smoothing
+                .get(&chart.desc.id)
+                .map(|alpha| exponential_moving_average(chart.data(), *alpha));
+        }
+    }
+
+    fn update_secondaries(&mut self) {
+        let data: HashMap<_, _> = self
+            .charts
+            .iter()
+            .filter(|chart| {
+                self.secondaries
+                    .values()
+                    .any(|desc| desc.id == chart.desc.id)
+            })
+            .map(|chart| (chart.desc.id, chart.data().clone()))
+            .collect();
+        for chart in self.charts.iter_mut() {
+            chart.secondary = self.secondaries.get(&chart.desc.id).and_then(|desc| {
+                let data = data.get(&desc.id)?.clone();
+                Some(SecondarySeries {
+                    desc: Rc::clone(desc),
+                    value_axis: value_axis_for(
+                        &[&data],
+                        self.value_ticks,
+                        axis_scale_for(&self.axis_scales, desc),
+                    ),
+                    data,
+                })
+            });
+        }
+    }
+
+    // A metric that was higher or lower before shouldn't be cut off, so the value axes follow.
+    fn update_snapshots(&mut self) {
+        for chart in self.charts.iter_mut() {
+            chart.snapshot = self
+                .snapshot
+                .as_ref()
+                .and_then(|snapshot| snapshot.get(&chart.desc.key))
+                .cloned();
+        }
+        self.update_value_axes();
+    }
+
+    // The value axis covers the snapshot and the overlaid series as well, so that none of them is
+    // cut off.
+    fn update_value_axes(&mut self) {
+        for chart in self.charts.iter_mut() {
+            let series: Vec<_> = chart.series.iter().chain(&chart.snapshot).collect();
+            let scale = axis_scale_for(&self.axis_scales, &chart.desc);
+            chart.value_axis = value_axis_for(&series, self.value_ticks, scale);
+            if let Some(secondary) = chart.secondary.as_mut() {
+                let scale = axis_scale_for(&self.axis_scales, &secondary.desc);
+                secondary.value_axis = value_axis_for(&[&secondary.data], self.value_ticks, scale);
+            }
+        }
+    }
+
+    fn is_boolean(&self, chart: &Chart) -> bool {
+        self.boolean_keys.contains(&chart.desc.key)
+    }
+
+    fn chart_line_style(&self, chart: &Chart) -> LineStyle {
+        self.line_styles
+            .get(&chart.desc.id)
+            .copied()
+            .unwrap_or(self.style.line_style)
+    }
+}
+
 impl Chart {
-    fn new(desc: Rc<Descriptor>, points: Vec<DataPoint>, max_ticks: usize) -> Self {
-        let max_value = points
+    fn new(
+        desc: Rc<Descriptor>,
+        section_idx: usize,
+        mut series: Vec<ChartData>,
+        max_ticks: usize,
+        scale: AxisScale,
+    ) -> Self {
+        if series.is_empty() {
+            series.push(vec![]);
+        }
+        let value_axis = value_axis_for(&series.iter().collect::<Vec<_>>(), max_ticks, scale);
+        let extremes = find_extremes(&series[0]);
+        // A color that doesn't parse leaves the zone below it to reach up to the next one.
+        let mut zones: Vec<_> = desc
+            .zones
             .iter()
-            .map(|p| p.1)
-            .max_by(f64::total_cmp)
-            .unwrap_or_default();
-        let ticks = calculate_value_ticks(max_value, max_ticks);
+            .filter_map(|zone| Some((zone.from, Color::from_hex_str(&zone.color).ok()?)))
+            .collect();
+        zones.sort_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0));
+        Self {
+            desc,
+            section_idx,
+            value_axis,
+            series,
+            extremes,
+            moving_average: None,
+            smoothed: None,
+            secondary: None,
+            snapshot: None,
+            zones,
+        }
+    }
 
-        let value_axis = ValueAxis { range: 0f64..=max_value, ticks };
-        Self { desc, value_axis, data: points }
+    fn data(&self) -> &ChartData {
+        &self.series[0]
+    }
+
+    // Of the points sampled for the current time range, which already have the scale of the
+    // descriptor applied. Gaps don't count.
+    fn stats(&self) -> Option<ChartStats> {
+        let mut values = self
+            .data()
+            .iter()
+            .map(|p| p.1)
+            .filter(|value| !value.is_nan());
+        let first = values.next()?;
+        let (mut min, mut max, mut sum, mut count) = (first, first, first, 1);
+        for value in values {
+            min = min.min(value);
+            max = max.max(value);
+            sum += value;
+            count += 1;
+        }
+        Some(ChartStats { min, max, mean: sum / count as f64 })
     }
 }
 
@@ -501,10 +1211,11 @@ impl Hover {
         if (ctx != TableContext::Cell) || (col != 1) {
             return None;
         }
-        let chart = match &state.rows[row as usize] {
+        let chart_idx = match &state.rows[row as usize] {
             ChartListRow::Section(_) => return None,
-            ChartListRow::Chart(chart_idx) => &state.charts[*chart_idx],
+            ChartListRow::Chart(chart_idx) => *chart_idx,
         };
+        let chart = &state.charts[chart_idx];
         let time_range = &state.time_axis.as_ref()?.range;
 
         let (x, _) = event_coords();
@@ -513,21 +1224,31 @@ impl Hover {
         let time_span = (*time_range.end() - *time_range.start()).num_milliseconds();
         let x_millis = ((x - cx) as i64) * time_span / ((cw - 1) as i64);
         let x_time = *time_range.start() + Duration::milliseconds(x_millis);
-        let time_text = x_time.to_timestamp_string();
+        let time_text = x_time.to_timestamp_string_in(state.zone);
 
-        let closest = match chart.data.binary_search_by_key(&x_time, |point| point.0) {
-            Ok(idx) => Some(&chart.data[idx]),
-            Err(idx) => chart.data[idx.saturating_sub(1)..]
-                .iter()
-                .take(2)
-                .min_by_key(|&point| (point.0 - x_time).abs()),
-        };
-        let value_text = match closest {
+        // With overlaid series, there is a line for each, labelled with the last element of its key.
+        let decimals = chart.desc.decimals.or(state.style.value_decimals);
+        let value_at = |data: &ChartData| match nearest_value(data, x_time) {
             None => "".to_string(),
-            Some((_, value)) => {
-                let value = (value * 1000.0).round() / 1000.0;
-                format!("{} ", value).separate_with_commas()
-            }
+            Some(value) => format_value(value, decimals),
+        };
+        let value_text = if chart.series.len() == 1 {
+            value_at(chart.data())
+        } else {
+            let keys = std::iter::once(&chart.desc.key).chain(&chart.desc.overlay);
+            chart
+                .series
+                .iter()
+                .zip(keys)
+                .map(|(data, key)| {
+                    format!(
+                        "{}: {}",
+                        key.iter().last().unwrap_or_default(),
+                        value_at(data)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
         };
 
         fltk::draw::set_font(state.hover_style.font.0, state.hover_style.font.1);
@@ -554,19 +1275,114 @@ impl Hover {
             value_text,
             value_extent: (value_x, value_y, value_w, value_h),
             tick_x,
+            time: x_time,
+            chart_idx,
+            scroll_pos: scroll_pos(table),
         })
     }
 
-    fn apply_damage(&self, table: &mut Table) {
-        let (x, y, w, h) = self.extent;
-        table.set_damage_area(Damage::All, x, y, w, h);
+    // Damages only what changed between the two hovers. In particular, the tick spans the whole
+    // height of the table, so moving the cursor vertically shouldn't repaint it. Moving it
+    // horizontally changes the readouts in every chart, and the values in the key column if it
+    // shows them, so then the whole table is repainted.
+    fn apply_damage(
+        old: Option<&Self>,
+        new: Option<&Self>,
+        values_in_key: bool,
+        table: &mut Table,
+    ) {
+        if old == new {
+            return;
+        }
+
+        for hover in [old, new].into_iter().flatten() {
+            let (x, y, w, h) = hover.extent;
+            table.set_damage_area(Damage::All, x, y, w, h);
+        }
 
-        if let Some(tick_x) = self.tick_x {
-            table.set_damage_area(Damage::All, tick_x, table.y(), 1, table.h());
+        let old_tick = old.and_then(|hover| Some((hover.tick_x?, hover.scroll_pos)));
+        let new_tick = new.and_then(|hover| Some((hover.tick_x?, hover.scroll_pos)));
+        let time_changed = old.map(|hover| hover.time) != new.map(|hover| hover.time);
+        if (old_tick != new_tick) || (values_in_key && time_changed) {
+            table.set_damage_area(Damage::All, table.x(), table.y(), table.w(), table.h());
         }
     }
 }
 
+fn nearest_value(data: &ChartData, time: Timestamp) -> Option<f64> {
+    lookup_value(
+        data,
+        time,
+        LookupMode::Nearest,
+        |point| point.0,
+        |idx| data[idx].1,
+    )
+}
+
+// Next to the tick in every chart but the hovered one, which has the hover box, goes the value of
+// that chart at the time of the cursor. Since the key column may be scrolled far from it, the value
+// is labelled with a short form of the chart name.
+fn draw_crosshair_readout(
+    table: &Table,
+    state: &ChartListState,
+    hover: &Hover,
+    chart_idx: usize,
+    (x, y, w, h): (i32, i32, i32, i32),
+) {
+    let tick_x = match hover.tick_x {
+        Some(tick_x) if chart_idx != hover.chart_idx => tick_x,
+        _ => return,
+    };
+    let chart = &state.charts[chart_idx];
+    let value = match nearest_value(chart.data(), hover.time) {
+        Some(value) => value,
+        None => return,
+    };
+    let text = format!(
+        "{}: {}",
+        short_chart_label(state.chart_name(&chart.desc)),
+        format_value(value, chart.desc.decimals.or(state.style.value_decimals)).trim_end(),
+    );
+
+    fltk::draw::set_font(state.hover_style.font.0, state.hover_style.font.1);
+    let (text_w, text_h) = fltk::draw::measure(&text, false);
+    let frame = FrameType::FlatBox;
+    let box_w = text_w + 2 * CROSSHAIR_READOUT_PADDING;
+    let box_h = text_h;
+    // It goes to the right of the tick, unless there is no room for it there.
+    let box_x = if tick_x + box_w + CROSSHAIR_READOUT_PADDING <= x + w {
+        tick_x + CROSSHAIR_READOUT_PADDING
+    } else {
+        tick_x - CROSSHAIR_READOUT_PADDING - box_w
+    };
+    let box_y = y + CROSSHAIR_READOUT_PADDING;
+
+    let _clip = ScopedClip::new(x, y, w, h);
+    fltk::draw::draw_box(frame, box_x, box_y, box_w, box_h, Color::Background2);
+    fltk::draw::set_draw_color(table.label_color());
+    fltk::draw::draw_text2(
+        &text,
+        box_x + CROSSHAIR_READOUT_PADDING,
+        box_y,
+        text_w,
+        text_h,
+        Align::Left,
+    );
+}
+
+// Keeps the end of the name, which is what tells apart charts in the same section.
+fn short_chart_label(name: &str) -> String {
+    let chars = name.chars().count();
+    if chars <= CROSSHAIR_LABEL_MAX_CHARS {
+        return name.to_string();
+    }
+    let tail: String = name
+        .chars()
+        .skip(chars - (CROSSHAIR_LABEL_MAX_CHARS - 1))
+        .collect();
+    format!("\u{2026}{}", tail)
+}
+
 fn draw_cell(
     table: &Table,
     state: &Rc<RefCell<ChartListState>>,
@@ -584,11 +1400,37 @@ fn draw_cell(
 
     let state = state.borrow();
     let chart_y = y + state.chart_spacing / 2;
+
+    // The hover is positioned in window coordinates, so once the rows scroll underneath it, it no
+    // longer matches what is drawn. Leave it out until the next mouse move replaces it, so that the
+    // header and the rows never disagree about where the hover tick is.
+    let hover = state
+        .hover
+        .as_ref()
+        .filter(|hover| hover.scroll_pos == scroll_pos(table));
     let chart_h = h - state.chart_spacing;
 
     let _clip = ScopedClip::new(x, y, w, h);
     if let TableContext::ColHeader | TableContext::Cell = ctx {
-        fltk::draw::draw_rect_fill(x, y, w, h, Color::Background2);
+        let color = match (ctx, col, state.rows.get(row as usize)) {
+            (TableContext::Cell, 1, Some(ChartListRow::Chart(chart_idx))) => {
+                section_band_color(&state.style, state.charts[*chart_idx].section_idx)
+            }
+            _ => Color::Background2,
+        };
+        fltk::draw::draw_rect_fill(x, y, w, h, color);
+
+        // Drawn under everything else in the cell, since FLTK has no way to blend it over.
+        let on_chart = match (ctx, state.rows.get(row as usize)) {
+            (TableContext::ColHeader, _) => true,
+            (_, Some(ChartListRow::Chart(_))) => true,
+            _ => false,
+        };
+        if let (1, true, Some(drag)) = (col, on_chart, state.drag.as_ref()) {
+            let band_x = std::cmp::min(drag.start_x, drag.end_x);
+            let band_w = (drag.end_x - drag.start_x).abs() + 1;
+            fltk::draw::draw_rect_fill(band_x, y, band_w, h, state.style.selection_color);
+        }
     }
 
     let time_axis = match state.time_axis.as_ref() {
@@ -599,7 +1441,7 @@ fn draw_cell(
     match ctx {
         TableContext::ColHeader if col == 1 => {
             draw_time_tick_lines(x, y, w, h, time_axis, &state.style);
-            if let Some(hover) = state.hover.as_ref() {
+            if let Some(hover) = hover {
                 if let Some(tick_x) = hover.tick_x {
                     fltk::draw::set_draw_color(state.style.time_tick_color);
                     fltk::draw::draw_line(tick_x, y, tick_x, y + h - 1);
@@ -611,14 +1453,39 @@ fn draw_cell(
         TableContext::Cell if col == 0 => match &state.rows[row as usize] {
             ChartListRow::Chart(chart_idx) => {
                 let chart = &state.charts[*chart_idx];
-                draw_value_tick_labels(x, chart_y, w, chart_h, &chart.value_axis, &state.style);
+                draw_value_tick_labels(
+                    x,
+                    chart_y,
+                    w,
+                    chart_h,
+                    &chart.value_axis,
+                    chart.desc.decimals.or(state.style.value_decimals),
+                    &state.style,
+                );
             }
             ChartListRow::Section(section_idx) => {
-                draw_section_heading(table, row, &state.sections[*section_idx]);
+                draw_section_heading(table, col, x, y, &state.sections[*section_idx]);
             }
         },
         TableContext::Cell if col == 1 => {
             match &state.rows[row as usize] {
+                ChartListRow::Chart(chart_idx) if state.is_boolean(&state.charts[*chart_idx]) => {
+                    let chart = &state.charts[*chart_idx];
+                    draw_state_bands(
+                        x,
+                        chart_y,
+                        w,
+                        chart_h,
+                        time_axis,
+                        chart.data(),
+                        &state.style,
+                    );
+                }
+                // The fill would hide the zones under the data.
+                ChartListRow::Chart(chart_idx) if !state.charts[*chart_idx].zones.is_empty() => {
+                    let chart = &state.charts[*chart_idx];
+                    draw_value_zones(x, chart_y, w, chart_h, &chart.value_axis, &chart.zones);
+                }
                 ChartListRow::Chart(chart_idx) => {
                     let chart = &state.charts[*chart_idx];
                     draw_data_fill(
@@ -628,15 +1495,18 @@ fn draw_cell(
                         chart_h,
                         time_axis,
                         &chart.value_axis,
-                        &chart.data,
+                        &chart.series,
+                        state.chart_line_style(chart),
                         &state.style,
                     );
                 }
                 ChartListRow::Section { .. } => (),
             };
 
-            draw_time_tick_lines(x, y, w, h, time_axis, &state.style);
-            if let Some(hover) = state.hover.as_ref() {
+            if state.style.cell_time_ticks {
+                draw_time_tick_lines(x, y, w, h, time_axis, &state.style);
+            }
+            if let Some(hover) = hover {
                 if let Some(tick_x) = hover.tick_x {
                     fltk::draw::set_draw_color(state.style.time_tick_color);
                     fltk::draw::draw_line(tick_x, y, tick_x, y + h - 1);
@@ -644,9 +1514,29 @@ fn draw_cell(
             }
 
             match &state.rows[row as usize] {
+                ChartListRow::Chart(chart_idx) if state.is_boolean(&state.charts[*chart_idx]) => {
+                    let chart = &state.charts[*chart_idx];
+                    draw_value_tick_lines(x, chart_y, w, chart_h, &chart.value_axis, &state.style);
+                }
                 ChartListRow::Chart(chart_idx) => {
                     let chart = &state.charts[*chart_idx];
                     draw_value_tick_lines(x, chart_y, w, chart_h, &chart.value_axis, &state.style);
+                    if let Some(snapshot) = chart.snapshot.as_ref() {
+                        draw_data_line(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            time_axis,
+                            &chart.value_axis,
+                            std::slice::from_ref(snapshot),
+                            state.chart_line_style(chart),
+                            &ChartStyle {
+                                data_line_color: state.style.snapshot_color,
+                                ..state.style.clone()
+                            },
+                        );
+                    }
                     draw_data_line(
                         x,
                         chart_y,
@@ -654,35 +1544,194 @@ fn draw_cell(
                         chart_h,
                         time_axis,
                         &chart.value_axis,
-                        &chart.data,
+                        &chart.series,
+                        state.chart_line_style(chart),
                         &state.style,
                     );
+                    if let Some(smoothed) = chart.smoothed.as_ref() {
+                        draw_data_line(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            time_axis,
+                            &chart.value_axis,
+                            std::slice::from_ref(smoothed),
+                            LineStyle::Linear,
+                            &ChartStyle {
+                                data_line_color: state.style.smoothing_color,
+                                ..state.style.clone()
+                            },
+                        );
+                    }
+                    if let Some(average) = chart.moving_average.as_ref() {
+                        draw_data_line(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            time_axis,
+                            &chart.value_axis,
+                            std::slice::from_ref(average),
+                            LineStyle::Linear,
+                            &ChartStyle {
+                                data_line_color: state.style.moving_average_color,
+                                ..state.style.clone()
+                            },
+                        );
+                    }
+                    if let Some(secondary) = chart.secondary.as_ref() {
+                        draw_data_line(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            time_axis,
+                            &secondary.value_axis,
+                            std::slice::from_ref(&secondary.data),
+                            state.chart_line_style(chart),
+                            &ChartStyle {
+                                data_line_color: state.style.secondary_axis_color,
+                                ..state.style.clone()
+                            },
+                        );
+                        draw_right_value_tick_lines(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            &secondary.value_axis,
+                            &state.style,
+                        );
+                        draw_right_value_tick_labels(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            &secondary.value_axis,
+                            secondary.desc.decimals.or(state.style.value_decimals),
+                            &state.style,
+                        );
+                    }
+                    if let Some(extremes) = chart.extremes.as_ref() {
+                        draw_extreme_markers(
+                            x,
+                            chart_y,
+                            w,
+                            chart_h,
+                            time_axis,
+                            &chart.value_axis,
+                            extremes,
+                            &state.style,
+                        );
+                    }
                 }
                 ChartListRow::Section(section_idx) => {
-                    draw_section_heading(table, row, &state.sections[*section_idx]);
+                    draw_section_heading(table, col, x, y, &state.sections[*section_idx]);
                 }
             };
+
+            if let ChartListRow::Chart(chart_idx) = &state.rows[row as usize] {
+                if state.style.point_counts {
+                    let chart = &state.charts[*chart_idx];
+                    let count = state
+                        .point_counts
+                        .get(&chart.desc.id)
+                        .copied()
+                        .unwrap_or(chart.data().len());
+                    draw_point_count(x, chart_y, w, chart_h, count, &state.style);
+                }
+                let chart = &state.charts[*chart_idx];
+                if state.style.chart_stats && !state.is_boolean(chart) {
+                    if let Some(stats) = chart.stats() {
+                        let decimals = chart.desc.decimals.or(state.style.value_decimals);
+                        draw_chart_stats(x, chart_y, w, chart_h, &stats, decimals, &state.style);
+                    }
+                }
+                if let Some(hover) = hover {
+                    draw_crosshair_readout(
+                        table,
+                        &state,
+                        hover,
+                        *chart_idx,
+                        (x, chart_y, w, chart_h),
+                    );
+                }
+            }
         }
         TableContext::Cell if col == 2 => match &state.rows[row as usize] {
             ChartListRow::Chart(chart_idx) => {
-                let text = &state.charts[*chart_idx].desc.name;
+                let chart = &state.charts[*chart_idx];
+                if state
+                    .notes
+                    .contains_key(&chart.desc.key.join(KEY_PATH_SEPARATOR))
+                {
+                    draw_note_marker(x, y, w, &state.style);
+                }
+                let text = key_text(state.chart_name(&chart.desc), &chart.desc);
                 fltk::draw::set_font(table.label_font(), table.label_size());
                 fltk::draw::set_draw_color(table.label_color());
+                let key_value = |data: &ChartData, desc: &Descriptor| {
+                    if !state.show_values_in_key {
+                        return None;
+                    }
+                    let value = match hover {
+                        Some(hover) => nearest_value(data, hover.time),
+                        None => data.last().map(|(_, value)| *value),
+                    };
+                    let decimals = desc.decimals.or(state.style.value_decimals);
+                    value.map(|value| format_value(value, decimals))
+                };
+                let secondary = match chart.secondary.as_ref() {
+                    Some(secondary) => secondary,
+                    None => {
+                        fltk::draw::draw_text2(
+                            &text,
+                            x + state.key_margin,
+                            y,
+                            w - state.key_margin,
+                            h,
+                            Align::Left,
+                        );
+                        if let Some(value) = key_value(chart.data(), &chart.desc) {
+                            fltk::draw::draw_text2(&value, x, y, w, h, Align::Right);
+                        }
+                        return;
+                    }
+                };
+
+                // With a secondary series, the key has a line for each, the second one in the color
+                // of the secondary axis.
+                let line_h = fltk::draw::height();
+                let text_y = y + h / 2 - line_h;
+                let text_x = x + state.key_margin;
+                let text_w = w - state.key_margin;
+                fltk::draw::draw_text2(&text, text_x, text_y, text_w, line_h, Align::Left);
+                if let Some(value) = key_value(chart.data(), &chart.desc) {
+                    fltk::draw::draw_text2(&value, x, text_y, w, line_h, Align::Right);
+                }
+                fltk::draw::set_draw_color(state.style.secondary_axis_color);
                 fltk::draw::draw_text2(
-                    text,
-                    x + state.key_margin,
-                    y,
-                    w - state.key_margin,
-                    h,
+                    &format!(
+                        "{} (right)",
+                        key_text(state.chart_name(&secondary.desc), &secondary.desc)
+                    ),
+                    text_x,
+                    text_y + line_h,
+                    text_w,
+                    line_h,
                     Align::Left,
                 );
+                if let Some(value) = key_value(&secondary.data, &secondary.desc) {
+                    fltk::draw::draw_text2(&value, x, text_y + line_h, w, line_h, Align::Right);
+                }
             }
             ChartListRow::Section(section_idx) => {
-                draw_section_heading(table, row, &state.sections[*section_idx]);
+                draw_section_heading(table, col, x, y, &state.sections[*section_idx]);
             }
         },
         TableContext::EndPage => {
-            if let Some(hover) = state.hover.as_ref() {
+            if let Some(hover) = hover {
                 let (hx, hy, hw, hh) = hover.extent;
                 let (tx, ty, tw, th) = hover.time_extent;
                 let (vx, vy, vw, vh) = hover.value_extent;
@@ -706,13 +1755,92 @@ fn draw_cell(
     }
 }
 
-fn draw_section_heading(table: &Table, row: i32, section: &Section) {
+fn value_axis_for(series: &[&ChartData], max_ticks: usize, scale: AxisScale) -> ValueAxis {
+    let points = || series.iter().flat_map(|data| data.iter());
+    let max_value = points()
+        .map(|p| p.1)
+        .max_by(f64::total_cmp)
+        .unwrap_or_default();
+    let range = match scale {
+        // Values are usually non-negative, but transforms like relative values can go below zero.
+        AxisScale::Linear => {
+            let min_value = points()
+                .map(|p| p.1)
+                .min_by(f64::total_cmp)
+                .unwrap_or_default()
+                .min(0.0);
+            min_value..=max_value
+        }
+        // The axis starts at the power of ten just below the lowest positive value, and spans at
+        // least one decade.
+        AxisScale::Log10 => {
+            let floor = points()
+                .map(|p| p.1)
+                .filter(|value| *value > 0.0)
+                .min_by(f64::total_cmp)
+                .map_or(1.0, |min_value| 10f64.powf(min_value.log10().floor()));
+            floor..=max_value.max(floor * 10.0)
+        }
+    };
+    let ticks = calculate_value_ticks(&range, scale, max_ticks);
+
+    ValueAxis { range, ticks, scale }
+}
+
+fn axis_scale_for(overrides: &HashMap<usize, AxisScale>, desc: &Descriptor) -> AxisScale {
+    match overrides.get(&desc.id) {
+        Some(scale) => *scale,
+        None if desc.log_scale => AxisScale::Log10,
+        None => AxisScale::Linear,
+    }
+}
+
+// Returns the first lowest and the first highest point, or nothing if the data is empty or flat, in
+// which case there are no extremes worth pointing out.
+fn find_extremes(data: &ChartData) -> Option<(DataPoint, DataPoint)> {
+    let mut points = data.iter();
+    let first = *points.next()?;
+    let (min, max) = points.fold((first, first), |(min, max), point| {
+        (
+            if point.1 < min.1 { *point } else { min },
+            if point.1 > max.1 { *point } else { max },
+        )
+    });
+    if min.1 < max.1 {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+fn key_text(name: &str, desc: &Descriptor) -> String {
+    match desc.unit.as_ref() {
+        Some(unit) => format!("{} [{}]", name, unit),
+        None => name.to_string(),
+    }
+}
+
+fn scroll_pos(table: &Table) -> i32 {
+    table.scrollbar().value() as i32
+}
+
+fn section_band_color(style: &ChartStyle, section_idx: usize) -> Color {
+    match style.section_band_colors {
+        Some((even, _)) if section_idx % 2 == 0 => even,
+        Some((_, odd)) => odd,
+        None => Color::Background2,
+    }
+}
+
+// The heading runs across the columns, from the start of the row, and each cell draws its part of it.
+// The start goes by the cell, rather than by where the table is, so that it also works off-screen.
+fn draw_section_heading(table: &Table, col: i32, cell_x: i32, y: i32, section: &Section) {
     let glyph = match section.state {
         SectionState::Expanded => "@2>",
         SectionState::Collapsed => "@>",
     };
     let text = format!("{} {}", glyph, &section.name);
-    let (x, y, _, _) = table.find_cell(TableContext::Cell, row, 0).unwrap();
+    let x = cell_x - (0..col).map(|col| table.col_width(col)).sum::<i32>();
 
     fltk::draw::set_font(table.label_font(), table.label_size());
     fltk::draw::set_draw_color(table.label_color());
@@ -720,3 +1848,29 @@ fn draw_section_heading(table: &Table, row: i32, section: &Section) {
 
     fltk::draw::draw_text2(&text, x, y, w, h, Align::Left);
 }
+
+const DRAG_ZOOM_THRESHOLD: i32 = 4;
+const CROSSHAIR_READOUT_PADDING: i32 = 3;
+const CROSSHAIR_LABEL_MAX_CHARS: usize = 24;
+
+#[cfg(test)]
+mod tests {
+    use crate::metric::unix_millis_to_timestamp;
+
+    use super::*;
+
+    #[test]
+    fn hover_value_is_that_of_the_nearest_point_within_the_data() {
+        let data: ChartData = [(1000, 1.0), (2000, 2.0)]
+            .into_iter()
+            .map(|(millis, value)| (unix_millis_to_timestamp(millis), value))
+            .collect();
+        let at = unix_millis_to_timestamp;
+
+        assert_eq!(nearest_value(&data, at(1000)), Some(1.0));
+        assert_eq!(nearest_value(&data, at(1400)), Some(1.0));
+        assert_eq!(nearest_value(&data, at(1600)), Some(2.0));
+        assert_eq!(nearest_value(&data, at(999)), None);
+        assert_eq!(nearest_value(&data, at(2001)), None);
+    }
+}
@@ -1,81 +1,185 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
 use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use anyhow::{bail, Context};
-use chrono::DateTime;
+use bson::Document;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, NaiveTime};
 use fltk::app::{self, Sender};
-use fltk::button::Button;
-use fltk::dialog::{FileDialogType, NativeFileChooser};
-use fltk::enums::Shortcut;
+use fltk::button::{Button, CheckButton};
+use fltk::dialog::{FileDialogOptions, FileDialogType, NativeFileChooser};
+use fltk::enums::{Align, Color, FrameType, Key, Shortcut};
 use fltk::frame::Frame;
+use fltk::group::Group;
+use fltk::image::RgbImage;
 use fltk::input::Input;
-use fltk::menu::MenuBar;
+use fltk::menu::{MenuBar, MenuFlag, MenuItem};
 use fltk::misc::InputChoice;
 use fltk::prelude::*;
 use fltk::window::Window;
 use fltk_float::grid::{CellAlign, Grid};
 use fltk_float::{SimpleWrapper, Size};
 
+use crate::config::{Config, WindowGeometry};
+use crate::export::html::{write_html, HtmlChart, HtmlSection};
+use crate::export::samples::{write_samples_csv, SampleColumn};
+use crate::ftdc::ParseWarning;
 use crate::gui::menu::MenuConvenienceExt;
-use crate::metric::{Descriptor, Section, Timestamp, TimestampFormat};
-use crate::Message;
-
-use super::chart::{ChartListSection, ChartListView, SectionState};
+use crate::metric::{
+    Descriptor, DisplayZone, MetricKey, Section, Timestamp, TimestampFormat, KEY_PATH_SEPARATOR,
+};
+use crate::{Message, SampleOptions, SamplingMode, SpikeThreshold};
+
+use super::chart::{
+    AxisScale, ChartData, ChartListData, ChartListSection, ChartListView, ChartStyle, HoverStyle,
+    LineStyle, SectionState,
+};
 use super::layout::wrapper_factory;
+use super::metadata::show_metadata;
+use super::notes::{show_notes, Notes};
+use super::parse_report::show_parse_report;
+use super::png::write_png;
+use super::raw_values::show_raw_values;
 use super::weak_cb;
+use super::workspace::{recovery_path, write_atomically, ViewSettings, Workspace};
 
 pub struct MainWindow {
     window: Window,
+    // What the window goes back to when the settings are reset
+    default_size: (i32, i32),
     tx: Sender<Message>,
+    menu: MenuBar,
+    work_area: Group,
+    status_bar: Frame,
+    warnings_button: Button,
     start_input: Input,
     end_input: Input,
     set_zoom_button: Button,
     reset_zoom_button: Button,
+    chart_size_choice: InputChoice,
+    value_ticks_choice: InputChoice,
+    values_in_key_check: CheckButton,
+    counter_deltas_item: MenuItem,
+    relative_values_item: MenuItem,
+    envelope_sampling_item: MenuItem,
+    section_banding_item: MenuItem,
+    extreme_markers_item: MenuItem,
+    point_counts_item: MenuItem,
+    chart_stats_item: MenuItem,
+    dark_mode_item: MenuItem,
+    sparkline_item: MenuItem,
+    step_lines_item: MenuItem,
+    hover_tick_item: MenuItem,
+    report_on_load_item: MenuItem,
+    auto_save_item: MenuItem,
+    zone_items: Vec<MenuItem>,
+    scale_items: Vec<MenuItem>,
+    auto_scales: Vec<f32>,
+    chart_menu: MenuItem,
+    derived_chart_menu: MenuItem,
     chart: ChartListView,
     state: RefCell<State>,
 }
 
 pub enum Update {
     DataSetLoaded {
+        path: PathBuf,
+        // Only present when the data came with descriptors of its own, or the previous one did
+        sections: Option<Vec<Section>>,
         start: Timestamp,
         end: Timestamp,
         transients: Vec<Rc<Descriptor>>,
+        booleans: HashSet<MetricKey>,
+        warnings: Vec<ParseWarning>,
+        // Where the charts are broken, between files that are far apart
+        gaps: Vec<RangeInclusive<Timestamp>>,
     },
     DescriptorsLoaded {
+        // None when going back to the descriptors that aren't from a file
+        path: Option<PathBuf>,
         sections: Vec<Section>,
         transients: Vec<Rc<Descriptor>>,
     },
-    MetricsSampled(HashMap<usize, Vec<(Timestamp, f64)>>),
+    MetricsSampled(
+        HashMap<usize, Vec<(Timestamp, f64)>>,
+        Vec<Vec<(Timestamp, f64)>>,
+        HashMap<usize, usize>,
+        HashMap<usize, Vec<Vec<(Timestamp, f64)>>>,
+    ),
+    // Sent after DataSetLoaded when some of the chunks couldn't be read
+    ChunksSkipped {
+        loaded: usize,
+        skipped: usize,
+    },
+    // Sent after DataSetLoaded when the data has metadata
+    // Every metadata document with the time it was written, the primary one first, and a
+    // description of the FTDC format of the data
+    MetadataLoaded(Vec<(Timestamp, Document)>, String),
+    // Bytes read so far, out of the total
+    LoadProgress(u64, u64),
+    SpikeFound(Option<Timestamp>),
+    RawValues(usize, Vec<(Timestamp, f64)>),
+    LoadFailed,
+}
+
+// What the next chart picked from the context menu is for, after the first one was picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChartPick {
+    Compare,
+    Secondary,
 }
 
 #[derive(Debug, Default)]
 struct State {
     sections: Vec<Section>,
-    sections_dirty: DirtyFlag,
+    // Sections whose metrics changed since the charts were last shown, and so start out expanded
+    dirty_sections: HashSet<String>,
     transients: Vec<Rc<Descriptor>>,
+    pinned: Vec<MetricKey>,
+    differences: Vec<(Rc<Descriptor>, Rc<Descriptor>)>,
+    chart_pick: Option<(ChartPick, Rc<Descriptor>)>,
+    file_path: Option<PathBuf>,
+    descriptors_path: Option<PathBuf>,
+    parse_warnings: Vec<ParseWarning>,
+    metadata: Option<(Vec<(Timestamp, Document)>, String)>,
     data_time_range: Option<RangeInclusive<Timestamp>>,
     zoom_time_range: Option<RangeInclusive<Timestamp>>,
+    zoom_history: ZoomHistory,
+    spike_threshold: Option<SpikeThreshold>,
+    sample_options: SampleOptions,
+    auto_saved: Option<String>,
+    notes: Notes,
+    // Where the file dialogs for data start out
+    last_dir: Option<PathBuf>,
+    busy: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum DirtyFlag {
-    Dirty,
-    Clean,
-}
-
-impl Default for DirtyFlag {
-    fn default() -> Self {
-        Self::Dirty
-    }
+#[derive(Debug, Default)]
+struct ZoomHistory {
+    back: Vec<Option<RangeInclusive<Timestamp>>>,
+    forward: Vec<Option<RangeInclusive<Timestamp>>>,
 }
 
 impl MainWindow {
     pub fn new(width: i32, height: i32, tx: Sender<Message>) -> Rc<Self> {
-        let (screen_x, screen_y, screen_w, screen_h) = app::Screen::work_area_mouse().tup();
-        let x = screen_x + (screen_w - width) / 2;
-        let y = screen_y + (screen_h - height) / 2;
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(err) => {
+                fltk::dialog::alert_default(&format!("Error loading the configuration: {}", err));
+                Config::default()
+            }
+        };
+
+        // A window saved on a screen that is no longer there opens centered instead.
+        let default_size = (width, height);
+        let (x, y, width, height) = match config.window.filter(is_on_screen) {
+            Some(geometry) => (geometry.x, geometry.y, geometry.w, geometry.h),
+            None => centered_geometry(default_size),
+        };
 
         let mut window = Window::default()
             .with_label("r2t2")
@@ -90,8 +194,89 @@ impl MainWindow {
         root.row().add();
         let mut menu = root.cell().unwrap().wrap(MenuBar::default());
         let open_item_id = menu.add_item("&File/&Open...\t\t", Shortcut::Ctrl | 'o');
-        let load_descriptors_id = menu.add_item("&File/_&Load Descriptors...", Shortcut::None);
+        let open_dir_id = menu.add_item("&File/Open &Directory...", Shortcut::None);
+        let open_range_id = menu.add_item("&File/Open &Range...", Shortcut::None);
+        let show_metadata_id = menu.add_item("&File/Show &Metadata...", Shortcut::None);
+        let open_workspace_id = menu.add_item("&File/Open Wor&kspace...", Shortcut::None);
+        let save_workspace_id = menu.add_item("&File/_Save &Workspace...", Shortcut::None);
+        let load_descriptors_id = menu.add_item("&File/&Load Descriptors...", Shortcut::None);
+        let export_template_id =
+            menu.add_item("&File/&Export Descriptor Template...", Shortcut::None);
+        #[cfg(feature = "parquet")]
+        let export_parquet_id = menu.add_item("&File/Export to &Parquet...", Shortcut::None);
+        let export_stats_id = menu.add_item("&File/Export S&tats CSV...", Shortcut::None);
+        let export_html_id = menu.add_item("&File/Export &HTML...", Shortcut::None);
+        let export_csv_id = menu.add_item("&File/Export &CSV...", Shortcut::None);
+        let save_screenshot_id = menu.add_item("&File/Save &Screenshot...", Shortcut::None);
+        let export_image_id = menu.add_item("&File/_Export &Image...", Shortcut::None);
+        let auto_save_id = menu.add_toggle_item("&File/A&uto-Save Workspace", Shortcut::None, true);
+        let report_on_load_id = menu.add_toggle_item(
+            "&File/_Show Parse Report &After Load",
+            Shortcut::None,
+            false,
+        );
         let exit_item_id = menu.add_item("&File/E&xit\t\t", Shortcut::None);
+        let counter_deltas_id =
+            menu.add_toggle_item("&View/Show Counter &Deltas\t\t", Shortcut::None, false);
+        let relative_values_id =
+            menu.add_toggle_item("&View/Values Relati&ve to Start\t\t", Shortcut::None, false);
+        let envelope_sampling_id =
+            menu.add_toggle_item("&View/E&nvelope Sampling\t\t", Shortcut::None, false);
+        let section_banding_id =
+            menu.add_toggle_item("&View/Section &Banding\t\t", Shortcut::None, true);
+        let extreme_markers_id =
+            menu.add_toggle_item("&View/Show &Extremes\t\t", Shortcut::None, false);
+        let point_counts_id =
+            menu.add_toggle_item("&View/Show &Point Counts\t\t", Shortcut::None, false);
+        let chart_stats_id =
+            menu.add_toggle_item("&View/Show Chart Stat&istics\t\t", Shortcut::None, false);
+        let sparkline_id = menu.add_toggle_item("&View/&Sparklines\t\t", Shortcut::None, false);
+        let step_lines_id = menu.add_toggle_item("&View/S&tep Lines\t\t", Shortcut::None, false);
+        let dark_mode_id = menu.add_toggle_item("&View/D&ark Mode\t\t", Shortcut::None, false);
+        let hover_tick_id =
+            menu.add_toggle_item("&View/_Show &Hover Tick\t\t", Shortcut::Ctrl | 't', true);
+        let larger_charts_id = menu.add_item("&View/&Larger Charts\t\t", Shortcut::Ctrl | '=');
+        let smaller_charts_id = menu.add_item("&View/_Smalle&r Charts\t\t", Shortcut::Ctrl | '-');
+        let notes_id = menu.add_item("&View/_N&otes...", Shortcut::None);
+        let moving_average_window_id =
+            menu.add_item("&View/_&Moving Average Window...", Shortcut::None);
+        let take_snapshot_id = menu.add_item("&View/Ta&ke Snapshot", Shortcut::None);
+        let clear_snapshot_id = menu.add_item("&View/_C&lear Snapshot", Shortcut::None);
+        let zone_ids: Vec<_> = ZONE_LABELS
+            .iter()
+            .enumerate()
+            .map(|(idx, label)| {
+                menu.add_radio_item(
+                    &format!("&View/Time &Zone/{}", label),
+                    Shortcut::None,
+                    idx == 0,
+                )
+            })
+            .collect();
+        let scale_ids: Vec<_> = UI_SCALES
+            .iter()
+            .enumerate()
+            .map(|(idx, (label, _))| {
+                menu.add_radio_item(
+                    &format!("&View/UI S&cale/{}", label),
+                    Shortcut::None,
+                    idx == 0,
+                )
+            })
+            .collect();
+        let reset_settings_id = menu.add_item("&View/&Reset to Defaults...", Shortcut::None);
+        let zoom_in_id = menu.add_item("&Zoom/Zoom &In\t\t", Shortcut::None | '+');
+        let zoom_out_id = menu.add_item("&Zoom/Zoom &Out\t\t", Shortcut::None | '-');
+        let pan_left_id = menu.add_item("&Zoom/Pan &Left\t\t", Shortcut::from_key(Key::Left));
+        let pan_right_id = menu.add_item("&Zoom/Pan &Right\t\t", Shortcut::from_key(Key::Right));
+        let reset_zoom_id = menu.add_item("&Zoom/_Reset &Zoom\t\t", Shortcut::from_key(Key::Home));
+        let zoom_back_id = menu.add_item("&Zoom/&Back\t\t", Shortcut::Alt | Key::Left);
+        let zoom_forward_id = menu.add_item("&Zoom/_&Forward\t\t", Shortcut::Alt | Key::Right);
+        let go_to_time_id = menu.add_item("&Zoom/&Go to Time...\t\t", Shortcut::Ctrl | 'g');
+        let copy_view_id = menu.add_item("&Zoom/_&Copy View as Arguments", Shortcut::None);
+        let find_spike_id = menu.add_item("&Zoom/Find &Spike...", Shortcut::None);
+        let find_next_spike_id =
+            menu.add_item("&Zoom/Find &Next Spike\t\t", Shortcut::from_key(Key::F3));
         menu.end();
 
         root.row()
@@ -139,12 +324,27 @@ impl MainWindow {
             .unwrap()
             .with_horz_align(CellAlign::End)
             .wrap(Frame::default().with_label("Chart Size:"));
-        let mut chart_size_choice = work_area.span(1, 5).unwrap().wrap(InputChoice::default());
+        let mut chart_size_choice = work_area.cell().unwrap().wrap(InputChoice::default());
         chart_size_choice.input().set_readonly(true);
-        chart_size_choice.add("Small");
-        chart_size_choice.add("Medium");
-        chart_size_choice.add("Large");
+        for label in CHART_SIZES {
+            chart_size_choice.add(label);
+        }
         chart_size_choice.set_value_index(0);
+        work_area
+            .cell()
+            .unwrap()
+            .with_horz_align(CellAlign::End)
+            .wrap(Frame::default().with_label("Max Value Ticks:"));
+        let mut value_ticks_choice = work_area.cell().unwrap().wrap(InputChoice::default());
+        value_ticks_choice.input().set_readonly(true);
+        for ticks in VALUE_TICK_CHOICES {
+            value_ticks_choice.add(&ticks.to_string());
+        }
+        value_ticks_choice.set_value_index(DEFAULT_VALUE_TICKS_IDX);
+        let mut values_in_key_check = work_area
+            .span(1, 2)
+            .unwrap()
+            .wrap(CheckButton::default().with_label("Values in Key"));
 
         work_area
             .row()
@@ -157,29 +357,96 @@ impl MainWindow {
             .unwrap()
             .add(SimpleWrapper::new(chart.widget(), Size::default()));
 
-        root.cell().unwrap().add(work_area.end());
+        let work_area = work_area.end();
+        let work_area_group = work_area.group();
+        root.cell().unwrap().add(work_area);
+
+        root.row().with_default_align(CellAlign::Stretch).add();
+        let mut status_bar_grid =
+            Grid::builder_with_factory(wrapper_factory()).with_padding(10, 0, 10, 5);
+        status_bar_grid.col().with_stretch(1).add();
+        status_bar_grid.col().add();
+        status_bar_grid
+            .row()
+            .with_default_align(CellAlign::Stretch)
+            .add();
+        let status_bar = Frame::default().with_align(Align::Left | Align::Inside);
+        fltk::draw::set_font(status_bar.label_font(), status_bar.label_size());
+        let status_bar_size = Size { width: 0, height: fltk::draw::height() };
+        status_bar_grid
+            .cell()
+            .unwrap()
+            .add(SimpleWrapper::new(status_bar.clone(), status_bar_size));
+        let mut warnings_button = Button::default().with_align(Align::Right | Align::Inside);
+        warnings_button.set_frame(FrameType::NoBox);
+        warnings_button.set_down_frame(FrameType::NoBox);
+        warnings_button.set_label_color(Color::DarkRed);
+        warnings_button.deactivate();
+        let (warnings_width, _) = fltk::draw::measure(&warnings_label(999_999), false);
+        status_bar_grid.cell().unwrap().add(SimpleWrapper::new(
+            warnings_button.clone(),
+            Size {
+                width: warnings_width,
+                height: status_bar_size.height,
+            },
+        ));
+        root.cell().unwrap().add(status_bar_grid.end());
 
         let root = root.end();
         root.layout_children();
 
-        window.resize_callback(move |_, _, _, _, _| root.layout_children());
-
         let style = chart.style();
         fltk::draw::set_font(style.value_text_font.0, style.value_text_font.1);
         let (max_val_w, _) = fltk::draw::measure("9,223,372,036,854,775,808 ", false);
-        chart.set_value_axis_width(max_val_w);
-        chart.set_key_width(chart.w() - chart.chart_width() - chart.value_axis_width() - 2);
+        fit_chart_columns(&mut chart, max_val_w);
+
+        window.resize_callback({
+            let mut chart = chart.clone();
+            move |_, _, _, _, _| {
+                root.layout_children();
+                fit_chart_columns(&mut chart, max_val_w);
+            }
+        });
+
         chart.set_chart_height(20);
-        chart.set_chart_spacing(40);
+        chart.set_chart_spacing(CHART_SPACING);
         chart.set_value_ticks(0);
 
         let this = Rc::new(Self {
             window,
+            default_size,
             tx,
+            menu: menu.clone(),
+            work_area: work_area_group,
+            status_bar,
+            warnings_button: warnings_button.clone(),
             start_input,
             end_input,
             set_zoom_button: set_zoom_button.clone(),
             reset_zoom_button: reset_zoom_button.clone(),
+            chart_size_choice: chart_size_choice.clone(),
+            value_ticks_choice: value_ticks_choice.clone(),
+            values_in_key_check: values_in_key_check.clone(),
+            counter_deltas_item: menu.at(counter_deltas_id).unwrap(),
+            relative_values_item: menu.at(relative_values_id).unwrap(),
+            envelope_sampling_item: menu.at(envelope_sampling_id).unwrap(),
+            section_banding_item: menu.at(section_banding_id).unwrap(),
+            extreme_markers_item: menu.at(extreme_markers_id).unwrap(),
+            point_counts_item: menu.at(point_counts_id).unwrap(),
+            chart_stats_item: menu.at(chart_stats_id).unwrap(),
+            dark_mode_item: menu.at(dark_mode_id).unwrap(),
+            sparkline_item: menu.at(sparkline_id).unwrap(),
+            step_lines_item: menu.at(step_lines_id).unwrap(),
+            hover_tick_item: menu.at(hover_tick_id).unwrap(),
+            report_on_load_item: menu.at(report_on_load_id).unwrap(),
+            auto_save_item: menu.at(auto_save_id).unwrap(),
+            zone_items: zone_ids.iter().map(|&id| menu.at(id).unwrap()).collect(),
+            scale_items: scale_ids.iter().map(|&id| menu.at(id).unwrap()).collect(),
+            // FLTK detects the scale of each screen on startup. Everything it draws, including the
+            // fixed sizes of the charts, is in units that it multiplies by that scale.
+            auto_scales: (0..app::screen_count()).map(app::screen_scale).collect(),
+            chart_menu: build_chart_menu(),
+            derived_chart_menu: MenuItem::new(&[DERIVED_CHART_MENU_REMOVE]),
             chart: chart.clone(),
             state: Default::default(),
         });
@@ -187,23 +454,161 @@ impl MainWindow {
         menu.at(open_item_id)
             .unwrap()
             .set_callback(weak_cb!(|this, _| this.on_open_file()));
+        menu.at(open_dir_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_open_dir()));
+        menu.at(open_range_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_open_file_range()));
+        menu.at(show_metadata_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_show_metadata()));
+        menu.at(open_workspace_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_open_workspace()));
+        menu.at(save_workspace_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_save_workspace()));
         menu.at(load_descriptors_id)
             .unwrap()
             .set_callback(weak_cb!(|this, _| this.on_load_descriptors()));
+        menu.at(export_template_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_descriptor_template()));
+        #[cfg(feature = "parquet")]
+        menu.at(export_parquet_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_parquet()));
+        menu.at(export_stats_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_stats()));
+        menu.at(export_csv_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_csv()));
+        menu.at(export_html_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_html()));
+        menu.at(save_screenshot_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_save_screenshot()));
+        menu.at(export_image_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_export_image()));
+        menu.at(larger_charts_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_step_chart_size(1)));
+        menu.at(smaller_charts_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_step_chart_size(-1)));
+        menu.at(take_snapshot_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.chart.clone().take_snapshot()));
+        menu.at(clear_snapshot_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.chart.clone().clear_snapshot()));
+        menu.at(auto_save_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_auto_save(this.auto_save_item.value())
+        ));
         menu.at(exit_item_id).unwrap().set_callback(|_| app::quit());
+        menu.at(counter_deltas_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_counter_deltas(this.counter_deltas_item.value())
+        ));
+        menu.at(relative_values_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_relative_values(this.relative_values_item.value())
+        ));
+        menu.at(envelope_sampling_id)
+            .unwrap()
+            .set_callback(weak_cb!(
+                |this, _| this.on_toggle_envelope_sampling(this.envelope_sampling_item.value())
+            ));
+        menu.at(section_banding_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_section_banding(this.section_banding_item.value())
+        ));
+        menu.at(extreme_markers_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_extreme_markers(this.extreme_markers_item.value())
+        ));
+        menu.at(point_counts_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_point_counts(this.point_counts_item.value())
+        ));
+        menu.at(chart_stats_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_chart_stats(this.chart_stats_item.value())
+        ));
+        menu.at(dark_mode_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_dark_mode(this.dark_mode_item.value())
+        ));
+        menu.at(sparkline_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_sparkline(this.sparkline_item.value())
+        ));
+        menu.at(go_to_time_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_go_to_time()));
+        menu.at(copy_view_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_copy_view_args()));
+        menu.at(find_spike_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_find_spike()));
+        menu.at(find_next_spike_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_find_next_spike()));
+        menu.at(step_lines_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_step_lines(this.step_lines_item.value())
+        ));
+        menu.at(hover_tick_id).unwrap().set_callback(weak_cb!(
+            |this, _| this.on_toggle_hover_tick(this.hover_tick_item.value())
+        ));
+        menu.at(notes_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_show_notes()));
+        menu.at(moving_average_window_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_set_moving_average_window()));
+        for (idx, &id) in zone_ids.iter().enumerate() {
+            menu.at(id)
+                .unwrap()
+                .set_callback(weak_cb!(|this, _| this.on_set_display_zone(idx)));
+        }
+        for (&id, &(_, scale)) in scale_ids.iter().zip(UI_SCALES.iter()) {
+            menu.at(id)
+                .unwrap()
+                .set_callback(weak_cb!(|this, _| this.on_set_ui_scale(scale)));
+        }
+        menu.at(reset_settings_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_reset_settings()));
+        menu.at(zoom_in_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_scale_zoom(3, 4)));
+        menu.at(zoom_out_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_scale_zoom(4, 3)));
+        menu.at(pan_left_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_pan(-1)));
+        menu.at(pan_right_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_pan(1)));
+        menu.at(reset_zoom_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_reset_zoom()));
+        menu.at(zoom_back_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_zoom_back()));
+        menu.at(zoom_forward_id)
+            .unwrap()
+            .set_callback(weak_cb!(|this, _| this.on_zoom_forward()));
 
-        chart_size_choice.set_callback({
-            let mut chart = chart.clone();
-            move |input| {
-                let size = input.menu_button().value() * 50 + 20;
-                chart.set_chart_height(size);
-                if size >= 70 {
-                    chart.set_value_ticks(5);
-                } else {
-                    chart.set_value_ticks(0);
-                }
-            }
-        });
+        chart_size_choice.set_callback(weak_cb!(|this, _| this.on_chart_size_changed()));
+        value_ticks_choice.set_callback(weak_cb!(|this, _| this.on_chart_size_changed()));
+        values_in_key_check.set_callback(weak_cb!(|this, check| this
+            .chart
+            .clone()
+            .set_show_values_in_key(check.is_checked())));
+
+        chart.set_context_menu_callback(weak_cb!(|this, desc| this.on_chart_context_menu(desc)));
+        chart.set_zoom_callback(weak_cb!(|this, range| this.change_zoom(Some(range))));
+
+        warnings_button.set_callback(weak_cb!(|this, _| this.on_show_parse_report()));
 
         set_zoom_button.deactivate();
         set_zoom_button.set_callback(weak_cb!(|this, _| this.on_set_zoom()));
@@ -211,6 +616,21 @@ impl MainWindow {
         reset_zoom_button.set_callback(weak_cb!(|this, _| this.on_reset_zoom()));
         reset_zoom_button.deactivate();
 
+        app::add_timeout3(
+            AUTO_SAVE_INTERVAL,
+            weak_cb!(|this, handle| {
+                this.on_auto_save();
+                app::repeat_timeout3(AUTO_SAVE_INTERVAL, handle);
+            }),
+        );
+
+        match Notes::load() {
+            Ok(notes) => this.state.borrow_mut().notes = notes,
+            Err(err) => fltk::dialog::alert_default(&format!("Error loading notes: {}", err)),
+        }
+        this.update_chart_notes();
+        this.restore_config(config);
+
         this
     }
 
@@ -218,31 +638,75 @@ impl MainWindow {
         self.window.clone().show();
     }
 
+    pub fn open_file(&self, path: PathBuf, zoom: Option<RangeInclusive<Timestamp>>) {
+        self.state.borrow_mut().zoom_time_range = zoom;
+        self.set_busy(Some("Loading..."));
+        self.send(Message::OpenFile(path, None));
+    }
+
+    pub fn load_descriptors(&self, path: PathBuf) {
+        self.set_busy(Some("Loading..."));
+        self.send(Message::LoadDescriptors(path));
+    }
+
+    // Updates arrive from the event loop, which also runs while a dialog is open, so none of the
+    // handlers may hold on to the state while doing anything that can show one or resample. The
+    // handlers copy what they need out of the state and drop it first.
     pub fn update(&self, update: Update) {
+        debug_assert!(
+            self.state.try_borrow_mut().is_ok(),
+            "update received while the window state is borrowed"
+        );
         match update {
-            Update::DataSetLoaded { start, end, transients } => {
+            Update::DataSetLoaded {
+                path,
+                sections,
+                start,
+                end,
+                transients,
+                booleans,
+                warnings,
+                gaps,
+            } => {
+                self.set_busy(None);
+                self.set_parse_warnings(warnings);
+                let mut chart = self.chart.clone();
+                chart.set_boolean_keys(booleans);
+                chart.set_gaps(gaps);
+                chart.clear_secondaries();
                 let mut state = self.state.borrow_mut();
 
+                if let Some(sections) = sections {
+                    state.set_sections(sections);
+                }
                 state.set_transients(transients);
+                state.file_path = Some(path);
+                state.metadata = None;
                 state.data_time_range = Some(start..=end);
+                state.zoom_history.clear();
 
-                if let Some(zoom) = state.zoom_time_range.as_mut() {
+                if let Some(zoom) = state.zoom_time_range.take() {
                     let zoom_start = std::cmp::max(start, *zoom.start());
-                    let zoom_end = std::cmp::max(end, *zoom.end());
-                    *zoom = zoom_start..=zoom_end;
+                    let zoom_end = std::cmp::min(end, *zoom.end());
+                    if zoom_start <= zoom_end {
+                        state.zoom_time_range = Some(zoom_start..=zoom_end);
+                    }
                 }
 
-                let sample_range = state.sample_range().unwrap();
+                drop(state);
 
-                self.populate_zoom(&sample_range);
                 self.set_zoom_button.clone().activate();
+                self.apply_zoom();
 
-                drop(state);
-
-                self.request_metrics_sample();
+                if self.report_on_load_item.value() {
+                    self.on_show_parse_report();
+                }
             }
-            Update::DescriptorsLoaded { sections, transients } => {
+            Update::DescriptorsLoaded { path, sections, transients } => {
+                self.set_busy(None);
+                self.chart.clone().clear_secondaries();
                 let mut state = self.state.borrow_mut();
+                state.descriptors_path = path;
                 state.set_sections(sections);
                 state.set_transients(transients);
 
@@ -254,51 +718,30 @@ impl MainWindow {
 
                 self.request_metrics_sample();
             }
-            Update::MetricsSampled(samples) => {
+            Update::MetricsSampled(samples, difference_samples, point_counts, overlays) => {
+                self.set_busy(None);
                 let mut state = self.state.borrow_mut();
 
-                let mut chart_data = Vec::with_capacity(state.sections.len() + 1);
-                for (idx, section) in state.sections.iter().enumerate() {
-                    let section_state = if let DirtyFlag::Dirty = state.sections_dirty {
-                        SectionState::Expanded
-                    } else {
-                        self.chart.section_state(idx)
-                    };
-                    chart_data.push(ChartListSection {
-                        name: section.name.clone(),
-                        state: section_state,
-                        charts: section
-                            .metrics
-                            .iter()
-                            .map(|desc| {
-                                (
-                                    Rc::clone(desc),
-                                    samples.get(&desc.id).cloned().unwrap_or_default(),
-                                )
-                            })
-                            .collect(),
-                    });
-                }
-                let transients_state = if let DirtyFlag::Dirty = state.sections_dirty {
-                    SectionState::Expanded
-                } else {
-                    self.chart.section_state(self.chart.section_count() - 1)
+                // Sections are remembered by name, since which ones are shown, and so their
+                // positions in the list, depends on what is in them.
+                let section_state = |name: &str| {
+                    self.chart
+                        .section_state(name)
+                        .unwrap_or(SectionState::Expanded)
                 };
-                chart_data.push(ChartListSection {
-                    name: UNKNOWN_SECTION.to_string(),
-                    state: transients_state,
-                    charts: state
-                        .transients
-                        .iter()
-                        .map(|desc| {
-                            (
-                                Rc::clone(desc),
-                                samples.get(&desc.id).cloned().unwrap_or_default(),
-                            )
-                        })
-                        .collect(),
-                });
-                state.sections_dirty = DirtyFlag::Clean;
+                let derived = state
+                    .differences
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (lhs, rhs))| {
+                        (
+                            Rc::new(difference_descriptor(idx, lhs, rhs, &self.chart)),
+                            difference_samples.get(idx).cloned().unwrap_or_default(),
+                        )
+                    })
+                    .collect();
+                let chart_data = state.chart_sections(&samples, derived, section_state);
+                state.dirty_sections.clear();
 
                 let sample_range = state.sample_range().unwrap();
 
@@ -306,133 +749,2196 @@ impl MainWindow {
 
                 let mut chart = self.chart.clone();
                 chart.set_time_range(sample_range);
+                chart.set_point_counts(point_counts);
+                chart.set_overlays(overlays);
                 chart.set_data(chart_data);
             }
+            Update::MetadataLoaded(metadata, format) => {
+                self.state.borrow_mut().metadata = Some((metadata, format))
+            }
+            Update::ChunksSkipped { loaded, skipped } => {
+                fltk::dialog::message_default(&format!(
+                    "Loaded {} chunks, skipped {} that couldn't be read.",
+                    loaded, skipped
+                ));
+            }
+            Update::LoadProgress(bytes_read, len) => {
+                // Without a length, like for stdin, there is only how much was read so far.
+                let label = if len > 0 {
+                    format!("Loading... {}%", (bytes_read * 100 / len).min(100))
+                } else {
+                    format!("Loading... {} KiB", bytes_read / 1024)
+                };
+                self.status_bar.clone().set_label(&label);
+                // Loading blocks the event loop, so the label has to be drawn right away.
+                app::flush();
+            }
+            Update::SpikeFound(spike) => {
+                self.set_busy(None);
+                let spike = match spike {
+                    Some(spike) => spike,
+                    None => {
+                        fltk::dialog::message_default("No more spikes found.");
+                        return;
+                    }
+                };
+
+                let state = self.state.borrow();
+                let data_time_range = state.data_time_range.clone().unwrap();
+                let view_range = state.sample_range().unwrap();
+                drop(state);
+
+                self.change_zoom(Some(center_range(&view_range, spike, &data_time_range)));
+            }
+            Update::RawValues(id, values) => {
+                self.set_busy(None);
+                let state = self.state.borrow();
+                let desc = match state.descriptors().find(|desc| desc.id == id) {
+                    Some(desc) => Rc::clone(desc),
+                    None => return,
+                };
+                drop(state);
+
+                let key = desc.key.join(KEY_PATH_SEPARATOR);
+                let title = format!("{} ({})", self.chart.name(&desc), key);
+                show_raw_values(&title, &values);
+            }
+            Update::LoadFailed => self.set_busy(None),
         }
     }
 
     fn on_open_file(&self) {
-        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseFile);
+        if self.state.borrow().busy {
+            return;
+        }
+
+        let mut dialog = self.data_file_chooser(FileDialogType::BrowseFile);
         dialog.show();
 
         if let Some(filename) = dialog.filenames().first() {
-            self.tx.send(Message::OpenFile(filename.clone()));
+            self.remember_dir(filename);
+            self.set_busy(Some("Loading..."));
+            self.send(Message::OpenFile(filename.clone(), None));
         }
     }
 
-    fn on_load_descriptors(&self) {
+    // For a diagnostic.data directory, which is loaded as a whole.
+    fn on_open_dir(&self) {
+        if self.state.borrow().busy {
+            return;
+        }
+
+        let mut dialog = self.data_file_chooser(FileDialogType::BrowseDir);
+        dialog.show();
+
+        if let Some(dirname) = dialog.filenames().first() {
+            self.remember_dir(dirname);
+            self.set_busy(Some("Loading..."));
+            self.send(Message::OpenFile(dirname.clone(), None));
+        }
+    }
+
+    fn data_file_chooser(&self, kind: FileDialogType) -> NativeFileChooser {
+        let mut dialog = NativeFileChooser::new(kind);
+        if let Some(dir) = self.state.borrow().last_dir.as_ref() {
+            let _ = dialog.set_directory(dir);
+        }
+        dialog
+    }
+
+    // For a directory of data, that is the directory that holds it, so that another one next to it
+    // is easy to pick.
+    fn remember_dir(&self, path: &Path) {
+        self.state.borrow_mut().last_dir = path.parent().map(Path::to_path_buf);
+    }
+
+    fn on_show_metadata(&self) {
+        let metadata = self.state.borrow().metadata.clone();
+        match metadata {
+            Some((metadata, format)) => show_metadata(&metadata, &format),
+            None if self.state.borrow().file_path.is_some() => {
+                fltk::dialog::message_default("The data has no metadata.")
+            }
+            None => (),
+        }
+    }
+
+    fn on_open_file_range(&self) {
+        if self.state.borrow().busy {
+            return;
+        }
+
+        let mut dialog = self.data_file_chooser(FileDialogType::BrowseFile);
+        dialog.show();
+
+        let filename = match dialog.filenames().first() {
+            Some(filename) => filename.clone(),
+            None => return,
+        };
+        self.remember_dir(&filename);
+
+        let start =
+            match prompt_open_time("Load data from (leave empty to start at the beginning):") {
+                Ok(Some(start)) => start,
+                Ok(None) => Timestamp::MIN_UTC,
+                Err(()) => return,
+            };
+        let end = match prompt_open_time("Load data until (leave empty to load to the end):") {
+            Ok(Some(end)) => end,
+            Ok(None) => Timestamp::MAX_UTC,
+            Err(()) => return,
+        };
+
+        self.set_busy(Some("Loading..."));
+        self.send(Message::OpenFile(filename, Some(start..=end)));
+    }
+
+    fn on_open_workspace(&self) {
+        if self.state.borrow().busy {
+            return;
+        }
+
         let mut dialog = NativeFileChooser::new(FileDialogType::BrowseFile);
-        dialog.set_filter("JSON Files\t*.json");
+        dialog.set_filter(WORKSPACE_FILTER);
         dialog.show();
 
         if let Some(filename) = dialog.filenames().first() {
-            self.tx.send(Message::LoadDescriptors(filename.clone()));
+            self.open_workspace(filename);
         }
     }
 
-    fn on_set_zoom(&self) {
-        let zoom_range = match self.parse_zoom() {
-            Ok(range) => Some(range),
+    fn open_workspace(&self, path: &Path) {
+        let workspace = match Workspace::load(path) {
+            Ok(workspace) => workspace,
             Err(err) => {
-                fltk::dialog::alert_default(&err.to_string());
+                fltk::dialog::alert_default(&format!("Error loading workspace: {}", err));
                 return;
             }
         };
 
-        let mut state = self.state.borrow_mut();
-        let can_reset = state.data_time_range != zoom_range;
-        state.zoom_time_range = zoom_range;
+        let data_file = if workspace.data_file.exists() {
+            workspace.data_file
+        } else {
+            let choice = fltk::dialog::choice2_default(
+                &format!(
+                    "The data file {} doesn't exist anymore.",
+                    workspace.data_file.display()
+                ),
+                "Cancel",
+                "Locate...",
+                "",
+            );
+            if choice != Some(1) {
+                return;
+            }
+            let mut dialog = NativeFileChooser::new(FileDialogType::BrowseFile);
+            dialog.show();
+            match dialog.filenames().first() {
+                Some(filename) => filename.clone(),
+                None => return,
+            }
+        };
 
-        drop(state);
+        // A broken zoom shouldn't keep the rest of the workspace from loading, and opening the file
+        // clamps it to the data anyway.
+        let zoom = workspace.zoom.and_then(|(start, end)| {
+            let start = DateTime::parse_from_rfc3339(&start).ok()?.into();
+            let end = DateTime::parse_from_rfc3339(&end).ok()?.into();
+            Some(start..=end)
+        });
 
-        if can_reset {
-            self.reset_zoom_button.clone().activate();
-        } else {
-            self.reset_zoom_button.clone().deactivate();
+        self.apply_view_settings(&workspace.view);
+        self.state.borrow_mut().pinned = workspace.pinned;
+
+        if let Some(descriptors_file) = workspace.descriptors_file {
+            self.set_busy(Some("Loading..."));
+            self.send(Message::LoadDescriptors(descriptors_file));
         }
-        self.request_metrics_sample();
+        self.open_file(data_file, zoom);
     }
 
-    fn on_reset_zoom(&self) {
-        let mut state = self.state.borrow_mut();
-
-        state.zoom_time_range = None;
-        self.populate_zoom(state.data_time_range.as_ref().unwrap());
+    fn on_save_workspace(&self) {
+        let workspace = match self.current_workspace() {
+            Some(workspace) => workspace,
+            None => return,
+        };
 
-        drop(state);
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter(WORKSPACE_FILTER);
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
 
-        self.reset_zoom_button.clone().deactivate();
-        self.request_metrics_sample();
+        if let Some(filename) = dialog.filenames().first() {
+            if let Err(err) = workspace.save(&filename.with_extension("json")) {
+                fltk::dialog::alert_default(&format!("Error saving workspace: {}", err));
+            }
+        }
     }
 
-    fn request_metrics_sample(&self) {
-        let state = self.state.borrow();
-        self.tx.send(Message::SampleMetrics(
-            state.descriptors().map(|desc| desc.id).collect(),
-            state.sample_range().unwrap(),
-            self.chart.chart_width() as _,
-        ));
+    // Only writes the recovery file when the workspace changed since it was last written, so that
+    // an idle session doesn't keep touching the disk.
+    fn on_auto_save(&self) {
+        if !self.auto_save_item.value() || self.state.borrow().busy {
+            return;
+        }
+        let (path, workspace) = match (recovery_path(), self.current_workspace()) {
+            (Some(path), Some(workspace)) => (path, workspace),
+            _ => return,
+        };
+        let contents = match workspace.to_json() {
+            Ok(contents) => contents,
+            Err(_) => return,
+        };
+        if self.state.borrow().auto_saved.as_ref() == Some(&contents) {
+            return;
+        }
+        // There's no one to tell about a failure in the background, so just try again next time.
+        if write_atomically(&path, &contents).is_ok() {
+            self.state.borrow_mut().auto_saved = Some(contents);
+        }
     }
 
-    fn populate_zoom(&self, zoom_time_range: &RangeInclusive<Timestamp>) {
-        self.start_input
-            .clone()
-            .set_value(&zoom_time_range.start().to_timestamp_string());
-        self.end_input
-            .clone()
-            .set_value(&zoom_time_range.end().to_timestamp_string());
+    fn on_toggle_auto_save(&self, enabled: bool) {
+        if !enabled {
+            self.clear_recovery();
+        }
     }
 
-    fn parse_zoom(&self) -> anyhow::Result<RangeInclusive<Timestamp>> {
-        let start = DateTime::parse_from_rfc3339(&self.start_input.value())
-            .context("error parsing start time")?
-            .into();
-        let end = DateTime::parse_from_rfc3339(&self.end_input.value())
-            .context("error parsing end time")?
-            .into();
+    /// Offers to restore the workspace that was auto-saved by a session that didn't exit cleanly.
+    /// The recovery file is removed if the offer is declined.
+    pub fn offer_recovery(&self) {
+        let path = match recovery_path() {
+            Some(path) if path.exists() => path,
+            _ => return,
+        };
+        let choice = fltk::dialog::choice2_default(
+            "The previous session didn't exit cleanly. Restore its workspace?",
+            "Discard",
+            "Restore",
+            "",
+        );
+        if choice == Some(1) {
+            self.open_workspace(&path);
+        } else {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
 
+    pub fn save_config(&self) {
+        let ui_scale = self
+            .scale_items
+            .iter()
+            .position(|item| item.value())
+            .and_then(|idx| UI_SCALES[idx].1);
         let state = self.state.borrow();
-        let data_time_range = state.data_time_range.as_ref().unwrap();
+        let config = Config {
+            window: Some(WindowGeometry {
+                x: self.window.x(),
+                y: self.window.y(),
+                w: self.window.w(),
+                h: self.window.h(),
+            }),
+            ui_scale,
+            chart_size: self.chart_size_choice.menu_button().value(),
+            descriptors_file: state.descriptors_path.clone(),
+            last_dir: state.last_dir.clone(),
+            dark_mode: self.dark_mode_item.value(),
+        };
+        if let Err(err) = config.save() {
+            eprintln!("Error saving the configuration: {}", err);
+        }
+    }
+
+    // A descriptors file that has since gone away is left out, rather than reported on every start.
+    fn restore_config(&self, config: Config) {
+        if let Some(idx) = UI_SCALES
+            .iter()
+            .position(|(_, scale)| *scale == config.ui_scale)
+        {
+            for (item_idx, item) in self.scale_items.iter().enumerate() {
+                set_toggle(item, item_idx == idx);
+            }
+            self.on_set_ui_scale(config.ui_scale);
+        }
 
-        if !data_time_range.contains(&start) {
-            bail!("start time out of bounds");
+        if (0..CHART_SIZES.len() as i32).contains(&config.chart_size) {
+            self.chart_size_choice
+                .clone()
+                .set_value_index(config.chart_size);
+            self.on_chart_size_changed();
         }
 
-        if !data_time_range.contains(&end) {
-            bail!("end time out of bounds");
+        self.state.borrow_mut().last_dir = config.last_dir;
+
+        if config.dark_mode {
+            set_toggle(&self.dark_mode_item, true);
+            self.on_toggle_dark_mode(true);
         }
 
-        Ok(start..=end)
+        if let Some(path) = config.descriptors_file.filter(|path| path.exists()) {
+            self.load_descriptors(path);
+        }
     }
-}
 
-impl State {
-    fn descriptors(&self) -> impl Iterator<Item = &Rc<Descriptor>> {
-        self.sections
-            .iter()
-            .flat_map(|section| section.metrics.iter())
-            .chain(self.transients.iter())
+    /// Removes the recovery file, which is only meant to outlive a session that didn't exit cleanly.
+    pub fn clear_recovery(&self) {
+        if let Some(path) = recovery_path() {
+            let _ = std::fs::remove_file(path);
+        }
+        self.state.borrow_mut().auto_saved = None;
     }
 
-    fn sample_range(&self) -> Option<RangeInclusive<Timestamp>> {
-        self.zoom_time_range
-            .as_ref()
-            .or_else(|| self.data_time_range.as_ref())
-            .cloned()
+    fn current_workspace(&self) -> Option<Workspace> {
+        let state = self.state.borrow();
+        let data_file = state.file_path.clone()?;
+        Some(Workspace {
+            data_file,
+            descriptors_file: state.descriptors_path.clone(),
+            zoom: state.zoom_time_range.as_ref().map(|zoom| {
+                (
+                    zoom.start().to_timestamp_string(),
+                    zoom.end().to_timestamp_string(),
+                )
+            }),
+            view: self.view_settings(),
+            pinned: state.pinned.clone(),
+        })
     }
 
-    fn set_sections(&mut self, sections: Vec<Section>) {
-        self.sections = sections;
-        self.sections_dirty = DirtyFlag::Dirty;
-        for section in self.sections.iter_mut() {
-            section.metrics.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    fn view_settings(&self) -> ViewSettings {
+        ViewSettings {
+            counter_deltas: self.counter_deltas_item.value(),
+            relative_values: self.relative_values_item.value(),
+            envelope_sampling: self.envelope_sampling_item.value(),
+            section_banding: self.section_banding_item.value(),
+            extreme_markers: self.extreme_markers_item.value(),
+            point_counts: self.point_counts_item.value(),
+            chart_stats: self.chart_stats_item.value(),
+            sparklines: self.sparkline_item.value(),
+            step_lines: self.step_lines_item.value(),
+            hover_tick: self.hover_tick_item.value(),
+            chart_size: self.chart_size_choice.menu_button().value(),
+            max_value_ticks: VALUE_TICK_CHOICES
+                [self.value_ticks_choice.menu_button().value() as usize],
         }
     }
 
-    fn set_transients(&mut self, transients: Vec<Rc<Descriptor>>) {
-        self.transients = transients;
-        self.transients.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    // The data is about to be loaded, so this only updates the settings without resampling.
+    fn apply_view_settings(&self, view: &ViewSettings) {
+        set_toggle(&self.counter_deltas_item, view.counter_deltas);
+        set_toggle(&self.relative_values_item, view.relative_values);
+        set_toggle(&self.envelope_sampling_item, view.envelope_sampling);
+        self.state.borrow_mut().sample_options = SampleOptions {
+            counter_deltas: view.counter_deltas,
+            relative_to_start: view.relative_values,
+            sampling: if view.envelope_sampling {
+                SamplingMode::Envelope
+            } else {
+                SamplingMode::First
+            },
+        };
+
+        set_toggle(&self.section_banding_item, view.section_banding);
+        self.on_toggle_section_banding(view.section_banding);
+        set_toggle(&self.extreme_markers_item, view.extreme_markers);
+        self.on_toggle_extreme_markers(view.extreme_markers);
+        set_toggle(&self.point_counts_item, view.point_counts);
+        self.on_toggle_point_counts(view.point_counts);
+        set_toggle(&self.chart_stats_item, view.chart_stats);
+        self.on_toggle_chart_stats(view.chart_stats);
+        set_toggle(&self.step_lines_item, view.step_lines);
+        self.on_toggle_step_lines(view.step_lines);
+        set_toggle(&self.hover_tick_item, view.hover_tick);
+        self.on_toggle_hover_tick(view.hover_tick);
+
+        let value_ticks_idx = VALUE_TICK_CHOICES
+            .iter()
+            .position(|&ticks| ticks == view.max_value_ticks)
+            .unwrap_or(DEFAULT_VALUE_TICKS_IDX as _);
+        self.value_ticks_choice
+            .clone()
+            .set_value_index(value_ticks_idx as _);
+        self.chart_size_choice
+            .clone()
+            .set_value_index(view.chart_size.clamp(0, CHART_SIZES.len() as i32 - 1));
+        set_toggle(&self.sparkline_item, view.sparklines);
+        self.on_toggle_sparkline(view.sparklines);
     }
-}
 
-const UNKNOWN_SECTION: &str = "UNKNOWN";
+    fn on_load_descriptors(&self) {
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseFile);
+        dialog.set_filter(
+            "Descriptor Files\t*.{json,yaml,yml}\nJSON Files\t*.json\nYAML Files\t*.{yaml,yml}",
+        );
+        dialog.show();
+
+        if let Some(filename) = dialog.filenames().first() {
+            self.set_busy(Some("Loading..."));
+            self.send(Message::LoadDescriptors(filename.clone()));
+        }
+    }
+
+    fn on_export_descriptor_template(&self) {
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter("JSON Files\t*.json");
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
+
+        if let Some(filename) = dialog.filenames().first() {
+            self.send(Message::ExportDescriptorTemplate(
+                filename.with_extension("json"),
+            ));
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    fn on_export_parquet(&self) {
+        let state = self.state.borrow();
+        if state.data_time_range.is_none() {
+            return;
+        }
+        let zoom_range = state.zoom_time_range.clone();
+        drop(state);
+
+        let range = match zoom_range {
+            Some(zoom_range) => {
+                match fltk::dialog::choice2_default(
+                    "Export the zoomed range or the whole data set?",
+                    "Cancel",
+                    "Zoomed Range",
+                    "Whole Data Set",
+                ) {
+                    Some(1) => Some(zoom_range),
+                    Some(2) => None,
+                    _ => return,
+                }
+            }
+            None => None,
+        };
+
+        let num_rows = match fltk::dialog::input_default(
+            "Number of evenly spaced rows (leave empty to export every data point):",
+            "",
+        ) {
+            Some(text) if text.trim().is_empty() => None,
+            Some(text) => match text.trim().parse() {
+                Ok(num_rows) if num_rows > 0 => Some(num_rows),
+                _ => {
+                    fltk::dialog::alert_default(&format!("Invalid number of rows: {}", text));
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter("Parquet Files\t*.parquet");
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
+
+        if let Some(filename) = dialog.filenames().first() {
+            self.send(Message::ExportParquet(
+                filename.with_extension("parquet"),
+                range,
+                num_rows,
+            ));
+        }
+    }
+
+    // The stats cover the charts as they are shown: the current view, in the same order, with the
+    // same names and transformations.
+    fn on_export_stats(&self) {
+        let state = self.state.borrow();
+        let range = match state.sample_range() {
+            Some(range) => range,
+            None => return,
+        };
+        let metrics = state
+            .descriptors()
+            .map(|desc| (desc.id, self.chart.name(desc)))
+            .collect();
+        let options = state.sample_options;
+        drop(state);
+
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter("CSV Files\t*.csv");
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
+
+        if let Some(filename) = dialog.filenames().first() {
+            self.send(Message::ExportStats(
+                filename.with_extension("csv"),
+                metrics,
+                range,
+                options,
+            ));
+        }
+    }
+
+    // Exports what the chart list shows, so collapsed sections are left out.
+    fn on_export_html(&self) {
+        let state = self.state.borrow();
+        let (path, range) = match (state.file_path.as_ref(), state.sample_range()) {
+            (Some(path), Some(range)) => (path, range),
+            _ => return,
+        };
+        let title = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        drop(state);
+
+        let sections: Vec<_> = self
+            .chart
+            .data()
+            .into_iter()
+            .filter(|section| matches!(section.state, SectionState::Expanded))
+            .map(|section| HtmlSection {
+                name: section.name,
+                charts: section
+                    .charts
+                    .into_iter()
+                    .map(|(desc, points)| HtmlChart {
+                        name: self.chart.name(&desc),
+                        unit: desc.unit.clone(),
+                        points,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter("HTML Files\t*.html");
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
+
+        let filename = match dialog.filenames().first() {
+            Some(filename) => filename.with_extension("html"),
+            None => return,
+        };
+        let result = File::create(filename)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_html(&mut BufWriter::new(file), &title, &range, &sections));
+        if let Err(err) = result {
+            fltk::dialog::alert_default(&format!("Error exporting HTML: {}", err));
+        }
+    }
+
+    // Like the HTML export, the samples are those the chart list shows, over the zoomed range.
+    fn on_export_csv(&self) {
+        if self.state.borrow().sample_range().is_none() {
+            return;
+        }
+
+        let columns: Vec<_> = self
+            .chart
+            .data()
+            .into_iter()
+            .filter(|section| matches!(section.state, SectionState::Expanded))
+            .flat_map(|section| section.charts)
+            .map(|(desc, points)| SampleColumn { name: self.chart.name(&desc), points })
+            .collect();
+
+        let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+        dialog.set_filter("CSV Files\t*.csv");
+        dialog.set_option(FileDialogOptions::SaveAsConfirm);
+        dialog.show();
+
+        let filename = match dialog.filenames().first() {
+            Some(filename) => filename.with_extension("csv"),
+            None => return,
+        };
+        let result = File::create(filename)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_samples_csv(BufWriter::new(file), &columns));
+        if let Err(err) = result {
+            fltk::dialog::alert_default(&format!("Error exporting CSV: {}", err));
+        }
+    }
+
+    fn on_save_screenshot(&self) {
+        match self.chart.capture() {
+            Some(image) => save_png(&image, "screenshot"),
+            None => fltk::dialog::alert_default("Error capturing the charts"),
+        }
+    }
+
+    // Renders all of the charts, not just those in view, with each chart at the size asked for, and
+    // then puts the size back.
+    fn on_export_image(&self) {
+        if self.state.borrow().data_time_range.is_none() {
+            return;
+        }
+
+        let mut chart = self.chart.clone();
+        let (chart_w, chart_h) = (chart.chart_width(), chart.chart_height());
+        let mut text = format!("{}x{}", chart_w, chart_h);
+        let (width, height) = loop {
+            text = match fltk::dialog::input_default("Size of each chart (width x height):", &text)
+            {
+                Some(text) => text,
+                None => return,
+            };
+            match parse_image_size(&text) {
+                Some(size) => break size,
+                None => fltk::dialog::alert_default(&format!("Invalid size: {}", text)),
+            }
+        };
+
+        chart.set_chart_width(width);
+        chart.set_chart_height(height);
+        let image = chart.capture_all();
+        chart.set_chart_width(chart_w);
+        chart.set_chart_height(chart_h);
+
+        match image {
+            Some(image) => save_png(&image, "image"),
+            None => fltk::dialog::alert_default("Error rendering the charts"),
+        }
+    }
+
+    fn on_show_parse_report(&self) {
+        // The report runs its own event loop, so it gets a copy rather than a borrow of the state.
+        let warnings = self.state.borrow().parse_warnings.clone();
+        if warnings.is_empty() {
+            return;
+        }
+        show_parse_report(&warnings);
+    }
+
+    fn on_toggle_counter_deltas(&self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.sample_options.counter_deltas = enabled;
+        let loaded = state.data_time_range.is_some();
+
+        drop(state);
+
+        if loaded {
+            self.request_metrics_sample();
+        }
+    }
+
+    fn on_toggle_relative_values(&self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.sample_options.relative_to_start = enabled;
+        let loaded = state.data_time_range.is_some();
+
+        drop(state);
+
+        if loaded {
+            self.request_metrics_sample();
+        }
+    }
+
+    fn on_toggle_envelope_sampling(&self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.sample_options.sampling =
+            if enabled { SamplingMode::Envelope } else { SamplingMode::First };
+        let loaded = state.data_time_range.is_some();
+
+        drop(state);
+
+        if loaded {
+            self.request_metrics_sample();
+        }
+    }
+
+    fn on_reset_settings(&self) {
+        let choice = fltk::dialog::choice2_default(
+            "Reset all settings to their defaults?",
+            "Cancel",
+            "Reset",
+            "",
+        );
+        if choice != Some(1) {
+            return;
+        }
+
+        // The settings are saved again on exit, so the window and the state that the saved ones
+        // come from are reset too, rather than just the file.
+        if let Err(err) = Config::default().save() {
+            fltk::dialog::alert_default(&format!("Error saving the configuration: {}", err));
+        }
+        let (x, y, width, height) = centered_geometry(self.default_size);
+        self.window.clone().resize(x, y, width, height);
+        self.state.borrow_mut().last_dir = None;
+        if self.state.borrow().descriptors_path.is_some() {
+            self.set_busy(Some("Loading..."));
+            self.send(Message::ResetDescriptors);
+        }
+
+        let mut chart = self.chart.clone();
+        chart.set_style(ChartStyle::default());
+        chart.set_hover_style(HoverStyle::default());
+        chart.clear_line_styles();
+        chart.clear_axis_scales();
+        chart.set_show_values_in_key(false);
+        self.values_in_key_check.set_checked(false);
+        chart.clear_moving_averages();
+        chart.set_moving_average_window(DEFAULT_MOVING_AVERAGE_WINDOW);
+        chart.clear_smoothing();
+        chart.clear_secondaries();
+        self.step_lines_item.clone().clear();
+        self.hover_tick_item.clone().set();
+
+        self.sparkline_item.clone().clear();
+        self.on_toggle_sparkline(false);
+
+        self.value_ticks_choice
+            .clone()
+            .set_value_index(DEFAULT_VALUE_TICKS_IDX);
+        let mut chart_size_choice = self.chart_size_choice.clone();
+        chart_size_choice.set_value_index(0);
+        chart_size_choice.do_callback();
+
+        self.counter_deltas_item.clone().clear();
+        self.relative_values_item.clone().clear();
+        self.envelope_sampling_item.clone().clear();
+        self.state.borrow_mut().sample_options = SampleOptions::default();
+        self.on_toggle_counter_deltas(false);
+
+        self.section_banding_item.clone().set();
+        self.extreme_markers_item.clone().clear();
+        self.point_counts_item.clone().clear();
+        self.chart_stats_item.clone().clear();
+        self.dark_mode_item.clone().clear();
+        self.on_toggle_dark_mode(false);
+        self.report_on_load_item.clone().clear();
+        self.auto_save_item.clone().set();
+
+        set_toggle(&self.scale_items[0], true);
+        for item in self.scale_items[1..].iter() {
+            set_toggle(item, false);
+        }
+        self.on_set_ui_scale(None);
+
+        for (idx, item) in self.zone_items.iter().enumerate() {
+            set_toggle(item, idx == 0);
+        }
+        self.on_set_display_zone(0);
+    }
+
+    fn on_chart_size_changed(&self) {
+        let mut chart = self.chart.clone();
+        let size = self.chart_size_choice.menu_button().value() * 50 + 20;
+        let max_ticks = VALUE_TICK_CHOICES[self.value_ticks_choice.menu_button().value() as usize];
+        chart.set_chart_height(size);
+        chart.set_value_ticks(value_ticks_for_height(size, max_ticks));
+    }
+
+    // Goes through the same callback as picking a size from the choice, and stops at either end.
+    fn on_step_chart_size(&self, step: i32) {
+        let mut chart_size_choice = self.chart_size_choice.clone();
+        if !chart_size_choice.active() {
+            return;
+        }
+        let current = chart_size_choice.menu_button().value();
+        let next = (current + step).clamp(0, CHART_SIZES.len() as i32 - 1);
+        if next == current {
+            return;
+        }
+        chart_size_choice.set_value_index(next);
+        chart_size_choice.do_callback();
+    }
+
+    // Backing out of entering an offset leaves the zone as it was, and puts the check mark back.
+    fn on_set_display_zone(&self, choice: usize) {
+        let current = self.chart.display_zone();
+        let zone = match choice {
+            0 => Some(DisplayZone::Utc),
+            1 => Some(DisplayZone::Local),
+            _ => ask_zone_offset(current).map(DisplayZone::Offset),
+        };
+        let zone = match zone {
+            Some(zone) => zone,
+            None => {
+                for (idx, item) in self.zone_items.iter().enumerate() {
+                    set_toggle(item, idx == zone_choice(current));
+                }
+                return;
+            }
+        };
+
+        self.chart.clone().set_display_zone(zone);
+        let sample_range = self.state.borrow().sample_range();
+        if let Some(range) = sample_range {
+            self.populate_zoom(&range);
+        }
+    }
+
+    fn on_set_ui_scale(&self, scale: Option<f32>) {
+        for (screen, auto_scale) in self.auto_scales.iter().enumerate() {
+            app::set_screen_scale(screen as _, scale.unwrap_or(*auto_scale));
+        }
+    }
+
+    fn on_toggle_section_banding(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let mut style = chart.style();
+        style.section_band_colors = if enabled { self.palette().section_band_colors } else { None };
+        chart.set_style(style);
+    }
+
+    fn on_toggle_extreme_markers(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let mut style = chart.style();
+        style.extreme_marker_colors = if enabled { Some(EXTREME_MARKER_COLORS) } else { None };
+        chart.set_style(style);
+    }
+
+    fn on_toggle_point_counts(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let style = chart.style();
+        chart.set_style(ChartStyle { point_counts: enabled, ..style });
+    }
+
+    fn on_toggle_chart_stats(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let style = chart.style();
+        chart.set_style(ChartStyle { chart_stats: enabled, ..style });
+    }
+
+    // The app colors go with the chart ones, so that the charts don't stand out from the rest.
+    fn on_toggle_dark_mode(&self, enabled: bool) {
+        let colors = if enabled { DARK_APP_COLORS } else { LIGHT_APP_COLORS };
+        let [background, background2, foreground] = colors;
+        app::background(background.0, background.1, background.2);
+        app::background2(background2.0, background2.1, background2.2);
+        app::foreground(foreground.0, foreground.1, foreground.2);
+
+        let mut chart = self.chart.clone();
+        let style = chart.style();
+        chart.set_style(style.with_colors_of(self.palette()));
+        app::redraw();
+    }
+
+    fn palette(&self) -> ChartStyle {
+        if self.dark_mode_item.value() {
+            ChartStyle::dark()
+        } else {
+            ChartStyle::default()
+        }
+    }
+
+    fn on_toggle_sparkline(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let mut chart_size_choice = self.chart_size_choice.clone();
+        let style = chart.style();
+        if enabled {
+            chart_size_choice.deactivate();
+            self.value_ticks_choice.clone().deactivate();
+            chart.set_style(style.sparkline());
+            chart.set_chart_height(SPARKLINE_CHART_HEIGHT);
+            chart.set_chart_spacing(SPARKLINE_CHART_SPACING);
+            chart.set_value_ticks(0);
+        } else {
+            let defaults = ChartStyle::default();
+            chart.set_style(ChartStyle {
+                fill_data: defaults.fill_data,
+                cell_time_ticks: defaults.cell_time_ticks,
+                ..style
+            });
+            chart.set_chart_spacing(CHART_SPACING);
+            chart_size_choice.activate();
+            self.value_ticks_choice.clone().activate();
+            chart_size_choice.do_callback();
+        }
+    }
+
+    fn on_toggle_step_lines(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let mut style = chart.style();
+        style.line_style = if enabled { LineStyle::Step } else { LineStyle::Linear };
+        chart.set_style(style);
+    }
+
+    fn on_toggle_hover_tick(&self, enabled: bool) {
+        let mut chart = self.chart.clone();
+        let mut hover_style = chart.hover_style();
+        hover_style.draw_tick = enabled;
+        chart.set_hover_style(hover_style);
+    }
+
+    fn on_chart_context_menu(&self, desc: Rc<Descriptor>) {
+        if let Some(idx) = difference_idx(desc.id) {
+            self.on_derived_chart_context_menu(idx);
+            return;
+        }
+
+        let mut chart = self.chart.clone();
+        let current = chart.line_style(desc.id);
+        for (idx, (_, line_style)) in CHART_MENU_LINE_STYLES.iter().enumerate() {
+            let mut item = self.chart_menu.at(idx as _).unwrap();
+            if *line_style == current {
+                item.set();
+            } else {
+                item.clear();
+            }
+        }
+
+        let mut pin_item = self
+            .chart_menu
+            .at(CHART_MENU_LINE_STYLES.len() as _)
+            .unwrap();
+        if self.state.borrow().pinned.contains(&desc.key) {
+            pin_item.set();
+        } else {
+            pin_item.clear();
+        }
+
+        let mut moving_average_item = self
+            .chart_menu
+            .at(CHART_MENU_LINE_STYLES.len() as i32 + 1)
+            .unwrap();
+        if chart.has_moving_average(desc.id) {
+            moving_average_item.set();
+        } else {
+            moving_average_item.clear();
+        }
+
+        let mut smoothing_item = self
+            .chart_menu
+            .at(CHART_MENU_LINE_STYLES.len() as i32 + 2)
+            .unwrap();
+        if chart.smoothing(desc.id).is_some() {
+            smoothing_item.set();
+        } else {
+            smoothing_item.clear();
+        }
+
+        let mut secondary_item = self
+            .chart_menu
+            .at(CHART_MENU_LINE_STYLES.len() as i32 + 4)
+            .unwrap();
+        if chart.secondary(desc.id).is_some() {
+            secondary_item.set();
+        } else {
+            secondary_item.clear();
+        }
+
+        let mut log_scale_item = self
+            .chart_menu
+            .at(CHART_MENU_LINE_STYLES.len() as i32 + 5)
+            .unwrap();
+        if chart.axis_scale(&desc) == AxisScale::Log10 {
+            log_scale_item.set();
+        } else {
+            log_scale_item.clear();
+        }
+
+        let (x, y) = app::event_coords();
+        let label = match self.chart_menu.popup(x, y).and_then(|item| item.label()) {
+            Some(label) => label,
+            None => return,
+        };
+        if label == CHART_MENU_PIN {
+            self.on_toggle_pin(&desc.key);
+        } else if label == CHART_MENU_MOVING_AVERAGE {
+            let enabled = !chart.has_moving_average(desc.id);
+            chart.set_moving_average(desc.id, enabled);
+        } else if label == CHART_MENU_SMOOTHING {
+            if chart.smoothing(desc.id).is_some() {
+                chart.set_smoothing(desc.id, None);
+            } else {
+                self.on_set_smoothing(&desc);
+            }
+        } else if label == CHART_MENU_COMPARE {
+            self.on_pick_chart(ChartPick::Compare, desc);
+        } else if label == CHART_MENU_SECONDARY {
+            if chart.secondary(desc.id).is_some() {
+                chart.set_secondary(desc.id, None);
+            } else {
+                self.on_pick_chart(ChartPick::Secondary, desc);
+            }
+        } else if label == CHART_MENU_LOG_SCALE {
+            let scale = match chart.axis_scale(&desc) {
+                AxisScale::Linear => AxisScale::Log10,
+                AxisScale::Log10 => AxisScale::Linear,
+            };
+            chart.set_axis_scale(desc.id, scale);
+        } else if label == CHART_MENU_INSPECT {
+            self.on_inspect_raw_values(&desc);
+        } else if label == CHART_MENU_NOTE {
+            self.on_edit_note(&desc);
+        } else if label == CHART_MENU_RENAME {
+            self.on_rename_chart(&desc);
+        } else if let Some((_, line_style)) = CHART_MENU_LINE_STYLES
+            .iter()
+            .find(|(item_label, _)| *item_label == label)
+        {
+            chart.set_line_style(desc.id, *line_style);
+        }
+    }
+
+    fn on_derived_chart_context_menu(&self, idx: usize) {
+        let (x, y) = app::event_coords();
+        if self.derived_chart_menu.popup(x, y).is_none() {
+            return;
+        }
+
+        self.state.borrow_mut().differences.remove(idx);
+        self.request_metrics_sample();
+    }
+
+    // Comparing and adding a secondary axis take two steps: the first chart picked is the one the
+    // second is subtracted from, or drawn over.
+    fn on_pick_chart(&self, pick: ChartPick, desc: Rc<Descriptor>) {
+        let mut state = self.state.borrow_mut();
+        let base = match state.chart_pick.take() {
+            Some((base_pick, base)) if base_pick == pick && base.id != desc.id => base,
+            _ => {
+                let message = match pick {
+                    ChartPick::Compare => format!(
+                        "Choose \"{}\" on another chart to subtract it from {}",
+                        CHART_MENU_COMPARE,
+                        self.chart.name(&desc),
+                    ),
+                    ChartPick::Secondary => format!(
+                        "Choose \"{}\" on another chart to draw it over {}",
+                        CHART_MENU_SECONDARY,
+                        self.chart.name(&desc),
+                    ),
+                };
+                state.chart_pick = Some((pick, desc));
+                drop(state);
+                self.status_bar.clone().set_label(&message);
+                return;
+            }
+        };
+
+        match pick {
+            ChartPick::Compare => {
+                state.differences.push((base, desc));
+                drop(state);
+                self.request_metrics_sample();
+            }
+            ChartPick::Secondary => {
+                drop(state);
+                self.status_bar.clone().set_label("");
+                self.chart.clone().set_secondary(base.id, Some(desc));
+            }
+        }
+    }
+
+    fn on_set_moving_average_window(&self) {
+        let mut chart = self.chart.clone();
+        let text = match fltk::dialog::input_default(
+            "Number of samples in the moving average:",
+            &chart.moving_average_window().to_string(),
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+        match text.trim().parse() {
+            Ok(window) if window > 0 => chart.set_moving_average_window(window),
+            _ => fltk::dialog::alert_default(&format!("Invalid number of samples: {}", text)),
+        }
+    }
+
+    fn on_set_smoothing(&self, desc: &Descriptor) {
+        let mut chart = self.chart.clone();
+        let text = match fltk::dialog::input_default(
+            "Smoothing factor, from 0 (smoothest) to 1 (none):",
+            &DEFAULT_SMOOTHING_ALPHA.to_string(),
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+        match text.trim().parse::<f64>() {
+            Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => chart.set_smoothing(desc.id, Some(alpha)),
+            _ => fltk::dialog::alert_default(&format!("Invalid smoothing factor: {}", text)),
+        }
+    }
+
+    // Only the values in view are shown, which keeps the list to a size that can be read through.
+    fn on_inspect_raw_values(&self, desc: &Descriptor) {
+        let range = match self.state.borrow().sample_range() {
+            Some(range) => range,
+            None => return,
+        };
+
+        self.set_busy(Some("Loading..."));
+        self.send(Message::InspectRawValues(desc.id, range));
+    }
+
+    fn on_edit_note(&self, desc: &Descriptor) {
+        let note = self
+            .state
+            .borrow()
+            .notes
+            .get(&desc.key)
+            .unwrap_or_default()
+            .to_string();
+        let note = match fltk::dialog::input_default(
+            &format!(
+                "Note on {} (leave empty to remove it):",
+                desc.key.join(KEY_PATH_SEPARATOR)
+            ),
+            &note,
+        ) {
+            Some(note) => note,
+            None => return,
+        };
+        self.state.borrow_mut().notes.set(&desc.key, &note);
+        self.save_notes();
+    }
+
+    fn on_show_notes(&self) {
+        let mut notes = self.state.borrow().notes.clone();
+        if show_notes(&mut notes) {
+            self.state.borrow_mut().notes = notes;
+            self.save_notes();
+        }
+    }
+
+    // Notes are saved as soon as they change, since they don't belong to any workspace that could
+    // be saved with them.
+    fn save_notes(&self) {
+        if let Err(err) = self.state.borrow().notes.save() {
+            fltk::dialog::alert_default(&format!("Error saving notes: {}", err));
+        }
+        self.update_chart_notes();
+    }
+
+    fn update_chart_notes(&self) {
+        let notes = self
+            .state
+            .borrow()
+            .notes
+            .by_path()
+            .clone()
+            .into_iter()
+            .collect();
+        self.chart.clone().set_notes(notes);
+    }
+
+    fn on_rename_chart(&self, desc: &Descriptor) {
+        let mut chart = self.chart.clone();
+        let name = match fltk::dialog::input_default(
+            "Chart name (leave empty to use the descriptor's name):",
+            &chart.name(desc),
+        ) {
+            Some(name) => name,
+            None => return,
+        };
+        let name = name.trim();
+        let name = if name.is_empty() || name == desc.name { None } else { Some(name.to_string()) };
+        chart.set_name(desc.id, name);
+    }
+
+    fn on_toggle_pin(&self, key: &MetricKey) {
+        let mut state = self.state.borrow_mut();
+        match state.pinned.iter().position(|pinned| pinned == key) {
+            Some(idx) => {
+                state.pinned.remove(idx);
+            }
+            None => state.pinned.push(key.clone()),
+        }
+
+        drop(state);
+
+        self.request_metrics_sample();
+    }
+
+    fn on_copy_view_args(&self) {
+        let state = self.state.borrow();
+        let (path, range) = match (state.file_path.as_ref(), state.sample_range()) {
+            (Some(path), Some(range)) => (path, range),
+            _ => return,
+        };
+
+        app::copy(&view_args(path, &range));
+    }
+
+    fn on_go_to_time(&self) {
+        let state = self.state.borrow();
+        let data_time_range = match state.data_time_range.clone() {
+            Some(range) => range,
+            None => return,
+        };
+        let view_range = state.sample_range().unwrap();
+
+        drop(state);
+
+        let center = *view_range.start() + (*view_range.end() - *view_range.start()) / 2;
+        let zone = self.chart.display_zone();
+        let mut text = center.to_timestamp_string_in(zone);
+        let time = loop {
+            text = match fltk::dialog::input_default(
+                "Time to center the view on (RFC 3339, a date and time in the display time zone, \
+                 or just a time of day):",
+                &text,
+            ) {
+                Some(text) => text,
+                None => return,
+            };
+            match parse_go_to_time(&text, &data_time_range, zone) {
+                Some(time) if data_time_range.contains(&time) => break time,
+                Some(time) => fltk::dialog::alert_default(&out_of_bounds_message(
+                    "requested",
+                    &time,
+                    &data_time_range,
+                )),
+                None => fltk::dialog::alert_default(&format!("Invalid time: {}", text)),
+            }
+        };
+
+        self.change_zoom(Some(center_range(&view_range, time, &data_time_range)));
+    }
+
+    fn on_find_spike(&self) {
+        if self.state.borrow().data_time_range.is_none() {
+            return;
+        }
+
+        let text = match fltk::dialog::input_default(
+            "Spike threshold (a value, or a number of standard deviations above the mean, e.g. \
+             3sd):",
+            "3sd",
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+        let threshold = match parse_spike_threshold(&text) {
+            Some(threshold) => threshold,
+            None => {
+                fltk::dialog::alert_default(&format!("Invalid spike threshold: {}", text));
+                return;
+            }
+        };
+
+        self.state.borrow_mut().spike_threshold = Some(threshold);
+        self.on_find_next_spike();
+    }
+
+    fn on_find_next_spike(&self) {
+        let state = self.state.borrow();
+        if state.data_time_range.is_none() {
+            return;
+        }
+        let threshold = match state.spike_threshold {
+            Some(threshold) => threshold,
+            None => {
+                drop(state);
+                self.on_find_spike();
+                return;
+            }
+        };
+
+        // Searching from the middle of the view skips a spike that the view was centered on by a
+        // previous search.
+        let view_range = state.sample_range().unwrap();
+        let after = *view_range.start() + (*view_range.end() - *view_range.start()) / 2;
+        let ids = state.descriptors().map(|desc| desc.id).collect();
+        let options = state.sample_options;
+
+        drop(state);
+
+        self.set_busy(Some("Searching..."));
+        self.send(Message::FindSpike(ids, after, threshold, options));
+    }
+
+    fn on_set_zoom(&self) {
+        let zoom_range = match self.parse_zoom() {
+            Ok(range) => Some(range),
+            Err(err) => {
+                fltk::dialog::alert_default(&err.to_string());
+                return;
+            }
+        };
+
+        self.change_zoom(zoom_range);
+    }
+
+    fn on_reset_zoom(&self) {
+        self.change_zoom(None);
+    }
+
+    // Scales the span of the view by `num / den`, keeping its center where it is as far as the data
+    // range allows.
+    fn on_scale_zoom(&self, num: i32, den: i32) {
+        let state = self.state.borrow();
+        let data_time_range = match state.data_time_range.clone() {
+            Some(range) => range,
+            None => return,
+        };
+        let view_range = state.sample_range().unwrap();
+
+        drop(state);
+
+        let span = (*view_range.end() - *view_range.start()) * num / den;
+        if span < chrono::Duration::milliseconds(MIN_ZOOM_SPAN_MILLIS) {
+            return;
+        }
+        let center = *view_range.start() + (*view_range.end() - *view_range.start()) / 2;
+        let zoom_range = center_range(&(center..=center + span), center, &data_time_range);
+        self.change_zoom(Some(zoom_range));
+    }
+
+    // Moves the view by a tenth of its span for each step, stopping at the ends of the data.
+    fn on_pan(&self, steps: i32) {
+        let state = self.state.borrow();
+        let data_time_range = match state.data_time_range.clone() {
+            Some(range) => range,
+            None => return,
+        };
+        let view_range = state.sample_range().unwrap();
+
+        drop(state);
+
+        let span = *view_range.end() - *view_range.start();
+        let center = *view_range.start() + span / 2 + span * steps / PAN_STEPS_PER_VIEW;
+        let zoom_range = center_range(&view_range, center, &data_time_range);
+        if zoom_range != view_range {
+            self.change_zoom(Some(zoom_range));
+        }
+    }
+
+    fn on_zoom_back(&self) {
+        let mut state = self.state.borrow_mut();
+        let current = state.zoom_time_range.clone();
+        let zoom_range = match state.zoom_history.back(current) {
+            Some(range) => range,
+            None => return,
+        };
+        state.zoom_time_range = zoom_range;
+
+        drop(state);
+
+        self.apply_zoom();
+    }
+
+    fn on_zoom_forward(&self) {
+        let mut state = self.state.borrow_mut();
+        let current = state.zoom_time_range.clone();
+        let zoom_range = match state.zoom_history.forward(current) {
+            Some(range) => range,
+            None => return,
+        };
+        state.zoom_time_range = zoom_range;
+
+        drop(state);
+
+        self.apply_zoom();
+    }
+
+    fn change_zoom(&self, zoom_range: Option<RangeInclusive<Timestamp>>) {
+        let mut state = self.state.borrow_mut();
+        let prev_range = std::mem::replace(&mut state.zoom_time_range, zoom_range);
+        if prev_range != state.zoom_time_range {
+            state.zoom_history.push(prev_range);
+        }
+
+        drop(state);
+
+        self.apply_zoom();
+    }
+
+    fn apply_zoom(&self) {
+        let state = self.state.borrow();
+        let can_reset =
+            state.zoom_time_range.is_some() && state.zoom_time_range != state.data_time_range;
+        let sample_range = state.sample_range().unwrap();
+
+        drop(state);
+
+        self.populate_zoom(&sample_range);
+        if can_reset {
+            self.reset_zoom_button.clone().activate();
+        } else {
+            self.reset_zoom_button.clone().deactivate();
+        }
+        self.request_metrics_sample();
+    }
+
+    fn set_busy(&self, status: Option<&str>) {
+        self.state.borrow_mut().busy = status.is_some();
+
+        let mut menu = self.menu.clone();
+        let mut work_area = self.work_area.clone();
+        let mut status_bar = self.status_bar.clone();
+        match status {
+            Some(status) => {
+                menu.deactivate();
+                work_area.deactivate();
+                status_bar.set_label(status);
+            }
+            None => {
+                menu.activate();
+                work_area.activate();
+                status_bar.set_label("");
+            }
+        }
+
+        // Loading and sampling block the event loop, so make sure the busy state is visible
+        // before that happens.
+        app::flush();
+    }
+
+    fn set_parse_warnings(&self, warnings: Vec<ParseWarning>) {
+        let mut warnings_button = self.warnings_button.clone();
+        if warnings.is_empty() {
+            warnings_button.set_label("");
+            warnings_button.deactivate();
+        } else {
+            warnings_button.set_label(&warnings_label(warnings.len()));
+            warnings_button.activate();
+        }
+        self.state.borrow_mut().parse_warnings = warnings;
+    }
+
+    // The reply comes back as an update through the event loop, which also runs while a dialog is
+    // open, so nothing may hold on to the state once a message is on its way.
+    fn send(&self, message: Message) {
+        debug_assert!(
+            self.state.try_borrow_mut().is_ok(),
+            "message sent while the window state is borrowed"
+        );
+        self.tx.send(message);
+    }
+
+    fn request_metrics_sample(&self) {
+        let state = self.state.borrow();
+        let message = Message::SampleMetrics(
+            state.descriptors().map(|desc| desc.id).collect(),
+            state
+                .differences
+                .iter()
+                .map(|(lhs, rhs)| (lhs.id, rhs.id))
+                .collect(),
+            state.sample_range().unwrap(),
+            self.chart.chart_width() as _,
+            state.sample_options,
+        );
+
+        drop(state);
+
+        self.set_busy(Some("Sampling..."));
+        self.send(message);
+    }
+
+    fn populate_zoom(&self, zoom_time_range: &RangeInclusive<Timestamp>) {
+        let zone = self.chart.display_zone();
+        self.start_input
+            .clone()
+            .set_value(&zoom_time_range.start().to_timestamp_string_in(zone));
+        self.end_input
+            .clone()
+            .set_value(&zoom_time_range.end().to_timestamp_string_in(zone));
+    }
+
+    fn parse_zoom(&self) -> anyhow::Result<RangeInclusive<Timestamp>> {
+        let data_time_range = &self.state.borrow().data_time_range.clone().unwrap();
+        let zone = self.chart.display_zone();
+        let start = parse_zoom_time(&self.start_input.value(), data_time_range, zone)
+            .context("error parsing start time")?;
+        let end = parse_zoom_time(&self.end_input.value(), data_time_range, zone)
+            .context("error parsing end time")?;
+        zoom_range(start, end, data_time_range)
+    }
+}
+
+impl State {
+    // The sections of the chart list, with the pinned charts first and the derived ones last. A
+    // section that was shown before keeps its `section_state`, unless its metrics changed.
+    fn chart_sections<F: Fn(&str) -> SectionState>(
+        &self,
+        samples: &HashMap<usize, ChartData>,
+        derived: Vec<(Rc<Descriptor>, ChartData)>,
+        section_state: F,
+    ) -> ChartListData {
+        let section_state = |name: &str| {
+            if self.dirty_sections.contains(name) {
+                SectionState::Expanded
+            } else {
+                section_state(name)
+            }
+        };
+        let chart_entry = |desc: &Rc<Descriptor>| {
+            (
+                Rc::clone(desc),
+                samples.get(&desc.id).cloned().unwrap_or_default(),
+            )
+        };
+        let is_pinned = |desc: &&Rc<Descriptor>| self.pinned.contains(&desc.key);
+
+        let mut chart_data = Vec::with_capacity(self.sections.len() + 3);
+        chart_data.push(ChartListSection {
+            name: PINNED_SECTION.to_string(),
+            state: section_state(PINNED_SECTION),
+            charts: self
+                .pinned
+                .iter()
+                .flat_map(|key| self.descriptors().filter(move |desc| desc.key == *key))
+                .map(chart_entry)
+                .collect(),
+        });
+        for section in self.sections.iter() {
+            chart_data.push(ChartListSection {
+                name: section.name.clone(),
+                state: section_state(&section.name),
+                charts: section
+                    .metrics
+                    .iter()
+                    .filter(|desc| !is_pinned(desc))
+                    .map(chart_entry)
+                    .collect(),
+            });
+        }
+        chart_data.push(ChartListSection {
+            name: UNKNOWN_SECTION.to_string(),
+            state: section_state(UNKNOWN_SECTION),
+            charts: self
+                .transients
+                .iter()
+                .filter(|desc| !is_pinned(desc))
+                .map(chart_entry)
+                .collect(),
+        });
+        chart_data.push(ChartListSection {
+            name: DERIVED_SECTION.to_string(),
+            state: section_state(DERIVED_SECTION),
+            charts: derived,
+        });
+        // A heading with nothing under it, such as a section whose metrics are all pinned, only
+        // takes up space.
+        chart_data.retain(|section| !section.charts.is_empty());
+        chart_data
+    }
+
+    fn descriptors(&self) -> impl Iterator<Item = &Rc<Descriptor>> {
+        self.sections
+            .iter()
+            .flat_map(|section| section.metrics.iter())
+            .chain(self.transients.iter())
+    }
+
+    fn sample_range(&self) -> Option<RangeInclusive<Timestamp>> {
+        self.zoom_time_range
+            .as_ref()
+            .or_else(|| self.data_time_range.as_ref())
+            .cloned()
+    }
+
+    // Only the sections whose metrics changed lose how they were expanded or collapsed.
+    fn set_sections(&mut self, mut sections: Vec<Section>) {
+        for section in sections.iter_mut() {
+            section.metrics.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        }
+        for section in sections.iter() {
+            let prev = self.sections.iter().find(|prev| prev.name == section.name);
+            if !prev.map_or(false, |prev| same_metrics(&prev.metrics, &section.metrics)) {
+                self.dirty_sections.insert(section.name.clone());
+            }
+        }
+        self.sections = sections;
+    }
+
+    fn set_transients(&mut self, transients: Vec<Rc<Descriptor>>) {
+        // Descriptor IDs change whenever the descriptors or the data do.
+        if !self.differences.is_empty() {
+            self.dirty_sections.insert(DERIVED_SECTION.to_string());
+        }
+        self.differences.clear();
+        self.chart_pick = None;
+        if !same_metrics(&self.transients, &transients) {
+            self.dirty_sections.insert(UNKNOWN_SECTION.to_string());
+        }
+        self.transients = transients;
+        self.transients.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    }
+}
+
+impl ZoomHistory {
+    fn push(&mut self, zoom_range: Option<RangeInclusive<Timestamp>>) {
+        if self.back.len() == ZOOM_HISTORY_LIMIT {
+            self.back.remove(0);
+        }
+        self.back.push(zoom_range);
+        self.forward.clear();
+    }
+
+    fn back(
+        &mut self,
+        current: Option<RangeInclusive<Timestamp>>,
+    ) -> Option<Option<RangeInclusive<Timestamp>>> {
+        let zoom_range = self.back.pop()?;
+        self.forward.push(current);
+        Some(zoom_range)
+    }
+
+    fn forward(
+        &mut self,
+        current: Option<RangeInclusive<Timestamp>>,
+    ) -> Option<Option<RangeInclusive<Timestamp>>> {
+        let zoom_range = self.forward.pop()?;
+        self.back.push(current);
+        Some(zoom_range)
+    }
+
+    fn clear(&mut self) {
+        self.back.clear();
+        self.forward.clear();
+    }
+}
+
+// Returns `Err` if the user cancelled, and `Ok(None)` if they left the time empty.
+fn prompt_open_time(message: &str) -> Result<Option<Timestamp>, ()> {
+    let mut text = String::new();
+    loop {
+        text = fltk::dialog::input_default(message, &text).ok_or(())?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        match DateTime::parse_from_rfc3339(text.trim()) {
+            Ok(time) => return Ok(Some(time.into())),
+            Err(err) => fltk::dialog::alert_default(&format!("Error parsing time: {}", err)),
+        }
+    }
+}
+
+// RFC 3339, or a date and time without an offset, which is in `zone`.
+fn parse_time_in(text: &str, zone: DisplayZone) -> Option<Timestamp> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.into());
+    }
+    GO_TO_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .and_then(|time| zone.from_local(&time))
+}
+
+// Besides what `parse_time_in` takes, takes just the time of day in `zone`, which is on the first
+// day of the data that it falls in.
+fn parse_go_to_time(
+    text: &str,
+    data_time_range: &RangeInclusive<Timestamp>,
+    zone: DisplayZone,
+) -> Option<Timestamp> {
+    if let Some(time) = parse_time_in(text, zone) {
+        return Some(time);
+    }
+    let time_of_day = GO_TO_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(text.trim(), format).ok())?;
+    let start = *data_time_range.start();
+    let local_time = zone.to_local(&start).date_naive().and_time(time_of_day);
+    match zone.from_local(&local_time)? {
+        time if time < start => zone.from_local(&(local_time + chrono::Duration::days(1))),
+        time => Some(time),
+    }
+}
+
+// Besides what `parse_go_to_time` takes, takes a time before the end of the data, like "-15m" or
+// "-2h".
+fn parse_zoom_time(
+    text: &str,
+    data_time_range: &RangeInclusive<Timestamp>,
+    zone: DisplayZone,
+) -> Option<Timestamp> {
+    if let Some(time) = parse_go_to_time(text, data_time_range, zone) {
+        return Some(time);
+    }
+    let text = text.trim().strip_prefix('-')?;
+    let unit = text.chars().last()?;
+    let amount: i64 = text[..text.len() - unit.len_utf8()].trim().parse().ok()?;
+    let unit_secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    // Too far back to be a time at all is no different from any other text that isn't one.
+    let millis = amount.checked_mul(unit_secs)?.checked_mul(1000)?;
+    data_time_range
+        .end()
+        .checked_sub_signed(chrono::Duration::milliseconds(millis))
+}
+
+// Like "+02:00" or "-05:30", starting from the current one, if it is an offset.
+fn ask_zone_offset(current: DisplayZone) -> Option<FixedOffset> {
+    let mut text = match current {
+        DisplayZone::Offset(offset) => offset.to_string(),
+        _ => "+00:00".to_string(),
+    };
+    loop {
+        text = fltk::dialog::input_default("Offset from UTC (e.g. +02:00 or -05:30):", &text)?;
+        match text.trim().parse() {
+            Ok(offset) => return Some(offset),
+            Err(_) => fltk::dialog::alert_default(&format!("Invalid offset: {}", text)),
+        }
+    }
+}
+
+fn parse_image_size(text: &str) -> Option<(i32, i32)> {
+    let (width, height) = text.split_once(['x', 'X', '\u{d7}'])?;
+    let width = width.trim().parse().ok().filter(|&width| width > 0)?;
+    let height = height.trim().parse().ok().filter(|&height| height > 0)?;
+    Some((width, height))
+}
+
+// `what` is for the error message.
+fn save_png(image: &RgbImage, what: &str) {
+    let mut dialog = NativeFileChooser::new(FileDialogType::BrowseSaveFile);
+    dialog.set_filter("PNG Files\t*.png");
+    dialog.set_option(FileDialogOptions::SaveAsConfirm);
+    dialog.show();
+
+    let filename = match dialog.filenames().first() {
+        Some(filename) => filename.with_extension("png"),
+        None => return,
+    };
+    let result =
+        File::create(filename).and_then(|file| write_png(&mut BufWriter::new(file), image));
+    if let Err(err) = result {
+        fltk::dialog::alert_default(&format!("Error saving {}: {}", what, err));
+    }
+}
+
+fn zone_choice(zone: DisplayZone) -> usize {
+    match zone {
+        DisplayZone::Utc => 0,
+        DisplayZone::Local => 1,
+        DisplayZone::Offset(_) => 2,
+    }
+}
+
+fn parse_spike_threshold(text: &str) -> Option<SpikeThreshold> {
+    let text = text.trim();
+    match text.strip_suffix("sd") {
+        Some(num_devs) => num_devs.trim().parse().ok().map(SpikeThreshold::StdDevs),
+        None => text.parse().ok().map(SpikeThreshold::Absolute),
+    }
+}
+
+// Descriptors are compared by key, since their IDs change whenever the descriptors or the data do.
+fn same_metrics(lhs: &[Rc<Descriptor>], rhs: &[Rc<Descriptor>]) -> bool {
+    let keys = |descs: &[Rc<Descriptor>]| -> HashSet<MetricKey> {
+        descs.iter().map(|desc| desc.key.clone()).collect()
+    };
+    lhs.len() == rhs.len() && keys(lhs) == keys(rhs)
+}
+
+// Returns a range of the same length as `view_range`, centered on `center` as far as the data range
+// allows.
+fn center_range(
+    view_range: &RangeInclusive<Timestamp>,
+    center: Timestamp,
+    data_time_range: &RangeInclusive<Timestamp>,
+) -> RangeInclusive<Timestamp> {
+    let span = *view_range.end() - *view_range.start();
+    let mut start = center - span / 2;
+    if start + span > *data_time_range.end() {
+        start = *data_time_range.end() - span;
+    }
+    let start = std::cmp::max(start, *data_time_range.start());
+    let end = std::cmp::min(start + span, *data_time_range.end());
+    start..=end
+}
+
+fn build_chart_menu() -> MenuItem {
+    let mut labels: Vec<_> = CHART_MENU_LINE_STYLES
+        .iter()
+        .map(|(label, _)| *label)
+        .collect();
+    labels.push(CHART_MENU_PIN);
+    labels.push(CHART_MENU_MOVING_AVERAGE);
+    labels.push(CHART_MENU_SMOOTHING);
+    labels.push(CHART_MENU_COMPARE);
+    labels.push(CHART_MENU_SECONDARY);
+    labels.push(CHART_MENU_LOG_SCALE);
+    labels.push(CHART_MENU_INSPECT);
+    labels.push(CHART_MENU_NOTE);
+    labels.push(CHART_MENU_RENAME);
+
+    let menu = MenuItem::new(&labels);
+    let last_style_idx = CHART_MENU_LINE_STYLES.len() - 1;
+    for idx in 0..last_style_idx {
+        menu.at(idx as _).unwrap().set_flag(MenuFlag::Radio);
+    }
+    menu.at(last_style_idx as _)
+        .unwrap()
+        .set_flag(MenuFlag::Radio | MenuFlag::MenuDivider);
+    menu.at(CHART_MENU_LINE_STYLES.len() as _)
+        .unwrap()
+        .set_flag(MenuFlag::Toggle);
+    menu.at(CHART_MENU_LINE_STYLES.len() as i32 + 1)
+        .unwrap()
+        .set_flag(MenuFlag::Toggle);
+    menu.at(CHART_MENU_LINE_STYLES.len() as i32 + 2)
+        .unwrap()
+        .set_flag(MenuFlag::Toggle);
+    menu.at(CHART_MENU_LINE_STYLES.len() as i32 + 4)
+        .unwrap()
+        .set_flag(MenuFlag::Toggle);
+    menu.at(CHART_MENU_LINE_STYLES.len() as i32 + 5)
+        .unwrap()
+        .set_flag(MenuFlag::Toggle);
+    menu
+}
+
+// Short charts have no room for value ticks. Taller ones get as many as fit with some spacing, up to
+// the configured maximum.
+fn value_ticks_for_height(height: i32, max_ticks: usize) -> usize {
+    if height < MIN_VALUE_TICKS_HEIGHT {
+        return 0;
+    }
+    std::cmp::min(max_ticks, (height / VALUE_TICK_SPACING) as usize)
+}
+
+// Splits the width of the chart list between its columns, so that each of them stays usable however
+// narrow the window gets. Once the minimums don't fit anymore, the table scrolls horizontally.
+fn fit_chart_columns(chart: &mut ChartListView, value_axis_width: i32) {
+    let available = chart.w() - 2;
+    let value_axis_width = std::cmp::max(
+        MIN_VALUE_AXIS_WIDTH,
+        std::cmp::min(value_axis_width, available / 4),
+    );
+    let rest = available - value_axis_width;
+    let chart_width = std::cmp::max(MIN_CHART_WIDTH, (rest as f64 * CHART_COLUMN_RATIO) as i32);
+    let key_width = std::cmp::max(MIN_KEY_WIDTH, rest - chart_width);
+
+    chart.set_value_axis_width(value_axis_width);
+    chart.set_chart_width(chart_width);
+    chart.set_key_width(key_width);
+}
+
+// Derived charts aren't backed by descriptors, so they get IDs from the top of the range, which no
+// descriptor will ever reach.
+fn difference_descriptor(
+    idx: usize,
+    lhs: &Descriptor,
+    rhs: &Descriptor,
+    chart: &ChartListView,
+) -> Descriptor {
+    Descriptor {
+        id: usize::MAX - idx,
+        key: MetricKey::new(),
+        name: format!("{} \u{2212} {}", chart.name(lhs), chart.name(rhs)),
+        pattern: None,
+        scale: 1.0,
+        offset: 0.0,
+        decimals: lhs.decimals.or(rhs.decimals),
+        unit: (lhs.unit == rhs.unit).then(|| lhs.unit.clone()).flatten(),
+        convert: None,
+        histogram: None,
+        zones: Vec::new(),
+        overlay: Vec::new(),
+        log_scale: false,
+        transform: None,
+    }
+}
+
+fn difference_idx(desc_id: usize) -> Option<usize> {
+    let idx = usize::MAX - desc_id;
+    (idx < MAX_DIFFERENCES).then_some(idx)
+}
+
+// The position and size of a window of the given size in the middle of the screen with the mouse.
+fn centered_geometry((width, height): (i32, i32)) -> (i32, i32, i32, i32) {
+    let (screen_x, screen_y, screen_w, screen_h) = app::Screen::work_area_mouse().tup();
+    let x = screen_x + (screen_w - width) / 2;
+    let y = screen_y + (screen_h - height) / 2;
+    (x, y, width, height)
+}
+
+fn is_on_screen(geometry: &WindowGeometry) -> bool {
+    (0..app::screen_count()).any(|screen| {
+        let (x, y, w, h) = app::screen_xywh(screen);
+        (x..x + w).contains(&geometry.x) && (y..y + h).contains(&geometry.y)
+    })
+}
+
+fn set_toggle(item: &MenuItem, value: bool) {
+    let mut item = item.clone();
+    if value {
+        item.set();
+    } else {
+        item.clear();
+    }
+}
+
+fn warnings_label(count: usize) -> String {
+    match count {
+        1 => "1 warning".to_string(),
+        _ => format!("{} warnings", count),
+    }
+}
+
+// The command line arguments that open the file at the range, ready to be pasted into a shell.
+fn view_args(path: &Path, range: &RangeInclusive<Timestamp>) -> String {
+    format!(
+        "--file {} --from {} --to {}",
+        shell_quoted(&path.to_string_lossy()),
+        range.start().to_timestamp_string(),
+        range.end().to_timestamp_string(),
+    )
+}
+
+// Nothing is special within single quotes, except for the quote itself, which has to be closed,
+// escaped and reopened.
+fn shell_quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn zoom_range(
+    start: Timestamp,
+    end: Timestamp,
+    data_time_range: &RangeInclusive<Timestamp>,
+) -> anyhow::Result<RangeInclusive<Timestamp>> {
+    // Inputs can carry any offset, so report the bounds in UTC, which is what both the parsed
+    // value and the data range are normalized to.
+    if !data_time_range.contains(&start) {
+        bail!(out_of_bounds_message("start", &start, data_time_range));
+    }
+
+    if !data_time_range.contains(&end) {
+        bail!(out_of_bounds_message("end", &end, data_time_range));
+    }
+
+    if start > end {
+        bail!(
+            "start time {} is after end time {}",
+            start.to_timestamp_string(),
+            end.to_timestamp_string(),
+        );
+    }
+
+    Ok(start..=end)
+}
+
+fn out_of_bounds_message(
+    which: &str,
+    time: &Timestamp,
+    data_time_range: &RangeInclusive<Timestamp>,
+) -> String {
+    format!(
+        "{} time out of bounds: {} is outside of the data range {} to {}",
+        which,
+        time.to_timestamp_string(),
+        data_time_range.start().to_timestamp_string(),
+        data_time_range.end().to_timestamp_string(),
+    )
+}
+
+const UNKNOWN_SECTION: &str = "UNKNOWN";
+const GO_TO_DATE_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+const GO_TO_TIME_FORMATS: [&str; 2] = ["%H:%M:%S%.f", "%H:%M"];
+const ZONE_LABELS: [&str; 3] = ["&UTC", "&Local", "&Fixed Offset..."];
+const UI_SCALES: [(&str, Option<f32>); 4] = [
+    ("&Auto", None),
+    ("&100%", Some(1.0)),
+    ("1&50%", Some(1.5)),
+    ("&200%", Some(2.0)),
+];
+const CHART_SIZES: [&str; 3] = ["Small", "Medium", "Large"];
+const AUTO_SAVE_INTERVAL: f64 = 60.0;
+const WORKSPACE_FILTER: &str = "Workspace Files\t*.json";
+const PINNED_SECTION: &str = "PINNED";
+const ZOOM_HISTORY_LIMIT: usize = 50;
+const CHART_MENU_LINE_STYLES: [(&str, Option<LineStyle>); 3] = [
+    ("Linear", Some(LineStyle::Linear)),
+    ("Step", Some(LineStyle::Step)),
+    ("Use Global Style", None),
+];
+const CHART_MENU_PIN: &str = "Pinned";
+const CHART_MENU_MOVING_AVERAGE: &str = "Moving Average";
+const CHART_MENU_SMOOTHING: &str = "Smoothing (EMA)...";
+const CHART_MENU_COMPARE: &str = "Compare with...";
+const CHART_MENU_SECONDARY: &str = "Secondary Axis...";
+const CHART_MENU_LOG_SCALE: &str = "Log Scale";
+const CHART_MENU_INSPECT: &str = "Inspect Raw Values...";
+const CHART_MENU_NOTE: &str = "Note...";
+const CHART_MENU_RENAME: &str = "Rename...";
+const DERIVED_CHART_MENU_REMOVE: &str = "Remove";
+const DERIVED_SECTION: &str = "DERIVED";
+const MAX_DIFFERENCES: usize = 1024;
+const DEFAULT_MOVING_AVERAGE_WINDOW: usize = 10;
+const DEFAULT_SMOOTHING_ALPHA: f64 = 0.2;
+const CHART_SPACING: i32 = 40;
+const CHART_COLUMN_RATIO: f64 = 0.4;
+const MIN_VALUE_AXIS_WIDTH: i32 = 40;
+const MIN_ZOOM_SPAN_MILLIS: i64 = 1000;
+const PAN_STEPS_PER_VIEW: i32 = 10;
+const MIN_CHART_WIDTH: i32 = 100;
+const MIN_KEY_WIDTH: i32 = 80;
+const VALUE_TICK_CHOICES: [usize; 6] = [3, 4, 5, 6, 7, 8];
+const DEFAULT_VALUE_TICKS_IDX: i32 = 2;
+const MIN_VALUE_TICKS_HEIGHT: i32 = 70;
+const VALUE_TICK_SPACING: i32 = 14;
+const SPARKLINE_CHART_HEIGHT: i32 = 16;
+const SPARKLINE_CHART_SPACING: i32 = 4;
+const EXTREME_MARKER_COLORS: (Color, Color) = (Color::Red, Color::Blue);
+// Background, Background2 and Foreground, the first ones being what FLTK starts with
+const LIGHT_APP_COLORS: [(u8, u8, u8); 3] = [(192, 192, 192), (255, 255, 255), (0, 0, 0)];
+const DARK_APP_COLORS: [(u8, u8, u8); 3] = [(50, 52, 58), (32, 34, 38), (220, 222, 226)];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> Timestamp {
+        DateTime::parse_from_rfc3339(text).unwrap().into()
+    }
+
+    fn data_time_range() -> RangeInclusive<Timestamp> {
+        time("2024-01-02T10:00:00Z")..=time("2024-01-02T12:00:00Z")
+    }
+
+    fn descriptor(id: usize, path: &[&str]) -> Rc<Descriptor> {
+        let mut desc = Descriptor::default_for_key(MetricKey::from(path), " ");
+        desc.id = id;
+        Rc::new(desc)
+    }
+
+    #[test]
+    fn sections_left_without_charts_are_omitted() {
+        let (memory, ops) = (
+            descriptor(0, &["mem", "resident"]),
+            descriptor(1, &["ops", "insert"]),
+        );
+        let state = State {
+            sections: vec![
+                Section {
+                    name: "Memory".to_string(),
+                    metrics: vec![Rc::clone(&memory)],
+                },
+                Section {
+                    name: "Operations".to_string(),
+                    metrics: vec![Rc::clone(&ops)],
+                },
+            ],
+            dirty_sections: HashSet::from(["Memory".to_string()]),
+            pinned: vec![ops.key.clone()],
+            ..State::default()
+        };
+        let samples = HashMap::from([(0, vec![]), (1, vec![])]);
+
+        let sections = state.chart_sections(&samples, vec![], |_| SectionState::Collapsed);
+        let sections: Vec<_> = sections
+            .iter()
+            .map(|section| {
+                let ids: Vec<_> = section.charts.iter().map(|(desc, _)| desc.id).collect();
+                (section.name.as_str(), section.state, ids)
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (PINNED_SECTION, SectionState::Collapsed, vec![1]),
+                ("Memory", SectionState::Expanded, vec![0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell_quoting_survives_quotes_and_specials() {
+        assert_eq!(shell_quoted("plain"), "'plain'");
+        assert_eq!(shell_quoted("a \"b\" $c `d`"), "'a \"b\" $c `d`'");
+        assert_eq!(shell_quoted("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn view_args_quote_the_path() {
+        let path = Path::new("/data/bob's \"metrics\"");
+        assert_eq!(
+            view_args(path, &data_time_range()),
+            format!(
+                "--file '/data/bob'\\''s \"metrics\"' --from {} --to {}",
+                data_time_range().start().to_timestamp_string(),
+                data_time_range().end().to_timestamp_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn zoom_time_takes_shorthand_formats() {
+        let range = data_time_range();
+        let parse = |text| parse_zoom_time(text, &range, DisplayZone::Utc);
+        assert_eq!(
+            parse("2024-01-02T11:00:00Z"),
+            Some(time("2024-01-02T11:00:00Z"))
+        );
+        assert_eq!(
+            parse("2024-01-02 11:15"),
+            Some(time("2024-01-02T11:15:00Z"))
+        );
+        assert_eq!(parse("11:30:15"), Some(time("2024-01-02T11:30:15Z")));
+        assert_eq!(parse(" 11:45 "), Some(time("2024-01-02T11:45:00Z")));
+    }
+
+    #[test]
+    fn zoom_time_with_an_offset_is_compared_in_utc() {
+        let range = data_time_range();
+        let parse = |text| parse_zoom_time(text, &range, DisplayZone::Utc).unwrap();
+
+        assert!(range.contains(&parse("2024-01-02T05:00:00-05:00")));
+        assert!(range.contains(&parse("2024-01-02T14:00:00+02:00")));
+        assert!(!range.contains(&parse("2024-01-02T04:59:59-05:00")));
+        assert!(!range.contains(&parse("2024-01-02T14:00:01+02:00")));
+    }
+
+    #[test]
+    fn out_of_bounds_message_shows_the_time_in_utc() {
+        let range = data_time_range();
+        let time = parse_zoom_time("2024-01-02T04:59:59-05:00", &range, DisplayZone::Utc);
+
+        assert_eq!(
+            out_of_bounds_message("start", &time.unwrap(), &range),
+            "start time out of bounds: 2024-01-02T09:59:59.000Z is outside of the data range \
+             2024-01-02T10:00:00.000Z to 2024-01-02T12:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn zoom_range_rejects_start_after_end() {
+        let range = data_time_range();
+        let start = time("2024-01-02T11:00:00Z");
+        let end = time("2024-01-02T10:30:00Z");
+
+        assert_eq!(
+            zoom_range(start, end, &range).unwrap_err().to_string(),
+            "start time 2024-01-02T11:00:00.000Z is after end time 2024-01-02T10:30:00.000Z"
+        );
+        assert_eq!(zoom_range(end, start, &range).unwrap(), end..=start);
+        assert_eq!(zoom_range(start, start, &range).unwrap(), start..=start);
+    }
+
+    #[test]
+    fn zoom_time_takes_times_before_the_end_of_the_data() {
+        let range = data_time_range();
+        let parse = |text| parse_zoom_time(text, &range, DisplayZone::Utc);
+        assert_eq!(parse("-30s"), Some(time("2024-01-02T11:59:30Z")));
+        assert_eq!(parse("-15m"), Some(time("2024-01-02T11:45:00Z")));
+        assert_eq!(parse("-2h"), Some(time("2024-01-02T10:00:00Z")));
+        assert_eq!(parse("-1d"), Some(time("2024-01-01T12:00:00Z")));
+        assert_eq!(parse("-15"), None);
+        assert_eq!(parse("-15w"), None);
+        assert_eq!(parse("15m"), None);
+        assert_eq!(parse("-m"), None);
+    }
+
+    #[test]
+    fn zoom_time_too_far_back_is_not_a_time() {
+        let range = data_time_range();
+        let parse = |text| parse_zoom_time(text, &range, DisplayZone::Utc);
+        assert_eq!(parse("-99999999999999999s"), None);
+        assert_eq!(parse("-9223372036854775807d"), None);
+        assert_eq!(parse("-100000000d"), None);
+        assert_eq!(
+            parse_zoom_time(
+                "-1s",
+                &(Timestamp::MIN_UTC..=Timestamp::MIN_UTC),
+                DisplayZone::Utc
+            ),
+            None
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use fltk::enums::Shortcut;
+use fltk::menu::MenuFlag;
 
 pub trait MenuConvenienceExt {
     fn add_item(&mut self, text: &str, shortcut: Shortcut) -> i32;
+    fn add_toggle_item(&mut self, text: &str, shortcut: Shortcut, checked: bool) -> i32;
+    fn add_radio_item(&mut self, text: &str, shortcut: Shortcut, checked: bool) -> i32;
 }
 
 impl<M: fltk::prelude::MenuExt> MenuConvenienceExt for M {
@@ -11,4 +14,24 @@ impl<M: fltk::prelude::MenuExt> MenuConvenienceExt for M {
         item.set_shortcut(shortcut);
         idx
     }
+
+    fn add_toggle_item(&mut self, text: &str, shortcut: Shortcut, checked: bool) -> i32 {
+        let idx = self.add_item(text, shortcut);
+        let mut item = self.at(idx).unwrap();
+        item.set_flag(MenuFlag::Toggle);
+        if checked {
+            item.set();
+        }
+        idx
+    }
+
+    fn add_radio_item(&mut self, text: &str, shortcut: Shortcut, checked: bool) -> i32 {
+        let idx = self.add_item(text, shortcut);
+        let mut item = self.at(idx).unwrap();
+        item.set_flag(MenuFlag::Radio);
+        if checked {
+            item.set();
+        }
+        idx
+    }
 }
@@ -0,0 +1,124 @@
+use bson::{Bson, Document};
+use fltk::app;
+use fltk::button::Button;
+use fltk::enums::Font;
+use fltk::prelude::*;
+use fltk::text::{TextBuffer, TextDisplay};
+use fltk::window::Window;
+
+use crate::metric::{Timestamp, TimestampFormat};
+
+// Relaxed extended JSON keeps numbers and dates readable, while still telling the BSON types apart
+// where plain JSON can't.
+pub fn format_metadata(doc: &Document) -> String {
+    let json = Bson::Document(doc.clone()).into_relaxed_extjson();
+    serde_json::to_string_pretty(&json).unwrap_or_else(|_| json.to_string())
+}
+
+// A restart in the middle of a capture writes the metadata again, so each document is headed by
+// when it was written, for telling what changed.
+pub fn format_metadata_history(docs: &[(Timestamp, Document)]) -> String {
+    match docs {
+        [(_, doc)] => format_metadata(doc),
+        docs => docs
+            .iter()
+            .map(|(timestamp, doc)| {
+                format!(
+                    "// As of {}\n{}",
+                    timestamp.to_timestamp_string(),
+                    format_metadata(doc)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    }
+}
+
+// The format goes above the metadata, but only the metadata is copied.
+pub fn show_metadata(docs: &[(Timestamp, Document)], format: &str) {
+    let text = format_metadata_history(docs);
+
+    let mut window = Window::default()
+        .with_label("Metadata")
+        .with_size(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .center_screen();
+    window.make_modal(true);
+
+    let mut buffer = TextBuffer::default();
+    buffer.set_text(&format!("FTDC format: {}\n\n{}", format, text));
+    let mut display = TextDisplay::new(
+        WINDOW_PADDING,
+        WINDOW_PADDING,
+        WINDOW_WIDTH - 2 * WINDOW_PADDING,
+        WINDOW_HEIGHT - 3 * WINDOW_PADDING - BUTTON_HEIGHT,
+        None,
+    );
+    display.set_buffer(buffer);
+    display.set_text_font(Font::Courier);
+
+    let button_y = WINDOW_HEIGHT - WINDOW_PADDING - BUTTON_HEIGHT;
+    let mut copy_button = Button::new(
+        WINDOW_WIDTH - 2 * (WINDOW_PADDING + BUTTON_WIDTH),
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Copy",
+    );
+    let mut close_button = Button::new(
+        WINDOW_WIDTH - WINDOW_PADDING - BUTTON_WIDTH,
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Close",
+    );
+    window.end();
+    window.resizable(&display);
+
+    copy_button.set_callback(move |_| app::copy(&text));
+    close_button.set_callback({
+        let mut window = window.clone();
+        move |_| window.hide()
+    });
+
+    window.show();
+    while window.shown() {
+        app::wait();
+    }
+}
+
+const WINDOW_WIDTH: i32 = 640;
+const WINDOW_HEIGHT: i32 = 560;
+const WINDOW_PADDING: i32 = 10;
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 25;
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::*;
+    use crate::metric::unix_millis_to_timestamp;
+
+    #[test]
+    fn single_document_has_no_heading() {
+        let docs = [(unix_millis_to_timestamp(0), doc! { "a": 1 })];
+        assert_eq!(format_metadata_history(&docs), format_metadata(&docs[0].1));
+    }
+
+    #[test]
+    fn each_document_is_headed_by_its_time() {
+        let docs = [
+            (unix_millis_to_timestamp(0), doc! { "a": 1 }),
+            (unix_millis_to_timestamp(60_000), doc! { "a": 2 }),
+        ];
+        let text = format_metadata_history(&docs);
+        let first = text.find(&unix_millis_to_timestamp(0).to_timestamp_string());
+        let second = text.find(&unix_millis_to_timestamp(60_000).to_timestamp_string());
+        assert!(
+            first.is_some() && second.is_some() && first < second,
+            "{}",
+            text
+        );
+        assert!(text.find("\"a\": 1") < text.find("\"a\": 2"), "{}", text);
+    }
+}
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use fltk::app;
+use fltk::browser::HoldBrowser;
+use fltk::button::Button;
+use fltk::prelude::*;
+use fltk::window::Window;
+use serde::{Deserialize, Serialize};
+
+use crate::metric::{MetricKey, KEY_PATH_SEPARATOR};
+
+use super::workspace::{app_data_dir, write_atomically};
+
+// Notes belong to the app rather than to a workspace, and are keyed by the path of the metric, so
+// that they turn up with the metric in whatever data it is in.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Notes(BTreeMap<String, String>);
+
+impl Notes {
+    // No file just means no notes yet.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = match notes_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = notes_path().ok_or_else(|| anyhow::anyhow!("no directory for app data"))?;
+        write_atomically(&path, &serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn get(&self, key: &MetricKey) -> Option<&str> {
+        self.0
+            .get(&key.join(KEY_PATH_SEPARATOR))
+            .map(String::as_str)
+    }
+
+    // An empty note removes the one there was.
+    pub fn set(&mut self, key: &MetricKey, note: &str) {
+        self.set_path(key.join(KEY_PATH_SEPARATOR), note);
+    }
+
+    pub fn by_path(&self) -> &BTreeMap<String, String> {
+        &self.0
+    }
+
+    fn set_path(&mut self, path: String, note: &str) {
+        let note = note.trim();
+        if note.is_empty() {
+            self.0.remove(&path);
+        } else {
+            self.0.insert(path, note.to_string());
+        }
+    }
+}
+
+// Lists all the notes, where they can be edited and removed. Returns whether any of them changed.
+pub fn show_notes(notes: &mut Notes) -> bool {
+    let edited = Rc::new(RefCell::new(notes.clone()));
+    let changed = Rc::new(RefCell::new(false));
+
+    let mut window = Window::default()
+        .with_label("Notes")
+        .with_size(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .center_screen();
+    window.make_modal(true);
+
+    let mut browser = HoldBrowser::new(
+        WINDOW_PADDING,
+        WINDOW_PADDING,
+        WINDOW_WIDTH - 2 * WINDOW_PADDING,
+        WINDOW_HEIGHT - 3 * WINDOW_PADDING - BUTTON_HEIGHT,
+        None,
+    );
+    browser.set_column_widths(&[KEY_COLUMN_WIDTH]);
+    populate(&mut browser, &edited.borrow());
+
+    let button_y = WINDOW_HEIGHT - WINDOW_PADDING - BUTTON_HEIGHT;
+    let button_x = |idx: i32| WINDOW_WIDTH - (4 - idx) * (WINDOW_PADDING + BUTTON_WIDTH);
+    let mut edit_button = Button::new(
+        button_x(0),
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Edit...",
+    );
+    let mut remove_button =
+        Button::new(button_x(1), button_y, BUTTON_WIDTH, BUTTON_HEIGHT, "Remove");
+    let mut clear_button = Button::new(
+        button_x(2),
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Clear All",
+    );
+    let mut close_button = Button::new(button_x(3), button_y, BUTTON_WIDTH, BUTTON_HEIGHT, "Close");
+    window.end();
+    window.resizable(&browser);
+
+    // The browser shows the notes in the order of their paths, which is also that of the map.
+    let selected_path = {
+        let edited = Rc::clone(&edited);
+        let browser = browser.clone();
+        move || {
+            let line = browser.value();
+            if line < 1 {
+                return None;
+            }
+            edited.borrow().0.keys().nth(line as usize - 1).cloned()
+        }
+    };
+
+    edit_button.set_callback({
+        let edited = Rc::clone(&edited);
+        let changed = Rc::clone(&changed);
+        let selected_path = selected_path.clone();
+        let mut browser = browser.clone();
+        move |_| {
+            let path = match selected_path() {
+                Some(path) => path,
+                None => return,
+            };
+            let note = edited.borrow().0[&path].clone();
+            let note = match fltk::dialog::input_default(
+                &format!("Note on {} (leave empty to remove it):", path),
+                &note,
+            ) {
+                Some(note) => note,
+                None => return,
+            };
+            edited.borrow_mut().set_path(path, &note);
+            *changed.borrow_mut() = true;
+            populate(&mut browser, &edited.borrow());
+        }
+    });
+    remove_button.set_callback({
+        let edited = Rc::clone(&edited);
+        let changed = Rc::clone(&changed);
+        let mut browser = browser.clone();
+        move |_| {
+            if let Some(path) = selected_path() {
+                edited.borrow_mut().0.remove(&path);
+                *changed.borrow_mut() = true;
+                populate(&mut browser, &edited.borrow());
+            }
+        }
+    });
+    clear_button.set_callback({
+        let edited = Rc::clone(&edited);
+        let changed = Rc::clone(&changed);
+        let mut browser = browser.clone();
+        move |_| {
+            if edited.borrow().0.is_empty() {
+                return;
+            }
+            let choice =
+                fltk::dialog::choice2_default("Remove all notes?", "Cancel", "Remove All", "");
+            if choice == Some(1) {
+                edited.borrow_mut().0.clear();
+                *changed.borrow_mut() = true;
+                populate(&mut browser, &edited.borrow());
+            }
+        }
+    });
+    close_button.set_callback({
+        let mut window = window.clone();
+        move |_| window.hide()
+    });
+
+    window.show();
+    while window.shown() {
+        app::wait();
+    }
+
+    let changed = *changed.borrow();
+    if changed {
+        *notes = edited.take();
+    }
+    changed
+}
+
+fn populate(browser: &mut HoldBrowser, notes: &Notes) {
+    browser.clear();
+    for (path, note) in notes.0.iter() {
+        // "@." keeps the browser from taking anything at the start of the line for formatting.
+        browser.add(&format!("@.{}\t@.{}", path, note.replace('\n', " ")));
+    }
+}
+
+fn notes_path() -> Option<PathBuf> {
+    Some(app_data_dir()?.join(NOTES_FILE_NAME))
+}
+
+const NOTES_FILE_NAME: &str = "notes.json";
+const WINDOW_WIDTH: i32 = 640;
+const WINDOW_HEIGHT: i32 = 400;
+const WINDOW_PADDING: i32 = 10;
+const KEY_COLUMN_WIDTH: i32 = 300;
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 25;
@@ -0,0 +1,80 @@
+use fltk::app;
+use fltk::button::Button;
+use fltk::prelude::*;
+use fltk::text::{TextBuffer, TextDisplay};
+use fltk::window::Window;
+
+use crate::ftdc::{summarize_warnings, ParseWarning};
+
+pub fn format_parse_report(warnings: &[ParseWarning]) -> String {
+    let (categories, messages) = summarize_warnings(warnings);
+
+    let mut report = String::new();
+    for (category, count) in categories {
+        report.push_str(&format!("{}: {}\n", category, count));
+    }
+    report.push('\n');
+    for (message, count) in messages {
+        match count {
+            1 => report.push_str(&format!("{}\n", message)),
+            _ => report.push_str(&format!("{} (x{})\n", message, count)),
+        }
+    }
+    report
+}
+
+pub fn show_parse_report(warnings: &[ParseWarning]) {
+    let report = format_parse_report(warnings);
+
+    let mut window = Window::default()
+        .with_label("Parse Report")
+        .with_size(REPORT_WIDTH, REPORT_HEIGHT)
+        .center_screen();
+    window.make_modal(true);
+
+    let mut buffer = TextBuffer::default();
+    buffer.set_text(&report);
+    let mut display = TextDisplay::new(
+        REPORT_PADDING,
+        REPORT_PADDING,
+        REPORT_WIDTH - 2 * REPORT_PADDING,
+        REPORT_HEIGHT - 3 * REPORT_PADDING - BUTTON_HEIGHT,
+        None,
+    );
+    display.set_buffer(buffer);
+
+    let button_y = REPORT_HEIGHT - REPORT_PADDING - BUTTON_HEIGHT;
+    let mut copy_button = Button::new(
+        REPORT_WIDTH - 2 * (REPORT_PADDING + BUTTON_WIDTH),
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Copy",
+    );
+    let mut close_button = Button::new(
+        REPORT_WIDTH - REPORT_PADDING - BUTTON_WIDTH,
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Close",
+    );
+    window.end();
+    window.resizable(&display);
+
+    copy_button.set_callback(move |_| app::copy(&report));
+    close_button.set_callback({
+        let mut window = window.clone();
+        move |_| window.hide()
+    });
+
+    window.show();
+    while window.shown() {
+        app::wait();
+    }
+}
+
+const REPORT_WIDTH: i32 = 640;
+const REPORT_HEIGHT: i32 = 400;
+const REPORT_PADDING: i32 = 10;
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 25;
@@ -0,0 +1,50 @@
+use std::io::{Result, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use fltk::enums::ColorDepth;
+use fltk::image::RgbImage;
+use fltk::prelude::*;
+
+pub fn write_png<W: Write>(writer: &mut W, image: &RgbImage) -> Result<()> {
+    let (channels, color_type) = match image.depth() {
+        ColorDepth::L8 => (1, 0),
+        ColorDepth::La8 => (2, 4),
+        ColorDepth::Rgb8 => (3, 2),
+        ColorDepth::Rgba8 => (4, 6),
+    };
+    let width = image.data_w() as usize;
+    let height = image.data_h() as usize;
+    let data = image.to_rgb_data();
+
+    writer.write_all(PNG_SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    let stride = width * channels;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in data.chunks_exact(stride).take(height) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    write_chunk(writer, b"IDAT", &encoder.finish()?)?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.sum().to_be_bytes())
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
@@ -0,0 +1,82 @@
+use fltk::app;
+use fltk::button::Button;
+use fltk::enums::Font;
+use fltk::prelude::*;
+use fltk::text::{TextBuffer, TextDisplay};
+use fltk::window::Window;
+
+use crate::metric::{Timestamp, TimestampFormat};
+
+// The values are shown as the decoder produced them, before deltas, scaling or sampling. FTDC
+// stores integers, which are printed as such.
+pub fn format_raw_values(values: &[(Timestamp, f64)]) -> String {
+    let mut text = String::new();
+    for (time, value) in values {
+        let value = if value.is_nan() {
+            "-".to_string()
+        } else if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            (*value as i64).to_string()
+        } else {
+            value.to_string()
+        };
+        text.push_str(&format!("{}\t{}\n", time.to_timestamp_string(), value));
+    }
+    text
+}
+
+pub fn show_raw_values(title: &str, values: &[(Timestamp, f64)]) {
+    let text = format_raw_values(values);
+
+    let mut window = Window::default()
+        .with_label(title)
+        .with_size(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .center_screen();
+    window.make_modal(true);
+
+    let mut buffer = TextBuffer::default();
+    buffer.set_text(&text);
+    let mut display = TextDisplay::new(
+        WINDOW_PADDING,
+        WINDOW_PADDING,
+        WINDOW_WIDTH - 2 * WINDOW_PADDING,
+        WINDOW_HEIGHT - 3 * WINDOW_PADDING - BUTTON_HEIGHT,
+        None,
+    );
+    display.set_buffer(buffer);
+    display.set_text_font(Font::Courier);
+
+    let button_y = WINDOW_HEIGHT - WINDOW_PADDING - BUTTON_HEIGHT;
+    let mut copy_button = Button::new(
+        WINDOW_WIDTH - 2 * (WINDOW_PADDING + BUTTON_WIDTH),
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Copy",
+    );
+    let mut close_button = Button::new(
+        WINDOW_WIDTH - WINDOW_PADDING - BUTTON_WIDTH,
+        button_y,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+        "Close",
+    );
+    window.end();
+    window.resizable(&display);
+
+    copy_button.set_callback(move |_| app::copy(&text));
+    close_button.set_callback({
+        let mut window = window.clone();
+        move |_| window.hide()
+    });
+
+    window.show();
+    while window.shown() {
+        app::wait();
+    }
+}
+
+const WINDOW_WIDTH: i32 = 480;
+const WINDOW_HEIGHT: i32 = 480;
+const WINDOW_PADDING: i32 = 10;
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 25;
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::metric::MetricKey;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Workspace {
+    pub data_file: PathBuf,
+    #[serde(default)]
+    pub descriptors_file: Option<PathBuf>,
+    // RFC 3339, so that the file stays readable and editable by hand
+    #[serde(default)]
+    pub zoom: Option<(String, String)>,
+    #[serde(default)]
+    pub view: ViewSettings,
+    #[serde(default)]
+    pub pinned: Vec<MetricKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewSettings {
+    pub counter_deltas: bool,
+    pub relative_values: bool,
+    pub envelope_sampling: bool,
+    pub section_banding: bool,
+    pub extreme_markers: bool,
+    pub point_counts: bool,
+    pub chart_stats: bool,
+    pub sparklines: bool,
+    pub step_lines: bool,
+    pub hover_tick: bool,
+    pub chart_size: i32,
+    pub max_value_ticks: usize,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            counter_deltas: false,
+            relative_values: false,
+            envelope_sampling: false,
+            section_banding: true,
+            extreme_markers: false,
+            point_counts: false,
+            chart_stats: false,
+            sparklines: false,
+            step_lines: false,
+            hover_tick: true,
+            chart_size: 0,
+            max_value_ticks: 5,
+        }
+    }
+}
+
+impl Workspace {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// The contents go to a temporary file first, which then replaces the target, so that a crash, or
+// another instance saving at the same time, never leaves the target half-written.
+pub fn write_atomically(path: &Path, contents: &str) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    if let Err(err) = std::fs::rename(&temp_path, path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+pub fn recovery_path() -> Option<PathBuf> {
+    Some(app_data_dir()?.join(RECOVERY_FILE_NAME))
+}
+
+// Where files that belong to the app rather than to any document go, following the convention of
+// each platform.
+pub fn app_data_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local").join("share")))
+    };
+    Some(base?.join(APP_DIR_NAME))
+}
+
+const APP_DIR_NAME: &str = "r2t2";
+const RECOVERY_FILE_NAME: &str = "recovery.json";
@@ -1,105 +1,652 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
 use std::fs::File;
-use std::ops::RangeInclusive;
+use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::ops::{ControlFlow, Range, RangeInclusive};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use bson::Document;
+use flate2::bufread::GzDecoder;
 use fltk::app;
-use metric::{Descriptor, Descriptors};
+use metric::{Descriptor, Descriptors, Histogram, Transform, DEFAULT_NAME_SEPARATOR};
 
+mod config;
+mod export;
 mod ftdc;
 mod gui;
 mod metric;
+mod validate;
 
-use self::ftdc::{read_chunk, Chunk, Error, Result};
+#[cfg(feature = "parquet")]
+use self::export::parquet::{write_parquet, ExportColumn};
+use self::export::stats::{write_stats_csv, MetricStats};
+use self::ftdc::{
+    chunk_start, decode_batch_len, decode_chunks, is_data_chunk, Chunk, ChunkReader, Error,
+    FormatInfo, MetricTypes, MetricsChunk, ParseWarning, Result,
+};
 use self::gui::MainWindow;
 use self::gui::Update;
 use self::metric::{MetricKey, Timestamp};
 
 #[derive(Debug)]
 pub enum Message {
-    OpenFile(PathBuf),
+    OpenFile(PathBuf, Option<RangeInclusive<Timestamp>>),
     LoadDescriptors(PathBuf),
-    SampleMetrics(Vec<usize>, RangeInclusive<Timestamp>, usize),
+    ResetDescriptors,
+    ExportDescriptorTemplate(PathBuf),
+    #[cfg(feature = "parquet")]
+    ExportParquet(PathBuf, Option<RangeInclusive<Timestamp>>, Option<usize>),
+    ExportStats(
+        PathBuf,
+        Vec<(usize, String)>,
+        RangeInclusive<Timestamp>,
+        SampleOptions,
+    ),
+    SampleMetrics(
+        Vec<usize>,
+        Vec<(usize, usize)>,
+        RangeInclusive<Timestamp>,
+        usize,
+        SampleOptions,
+    ),
+    FindSpike(Vec<usize>, Timestamp, SpikeThreshold, SampleOptions),
+    InspectRawValues(usize, RangeInclusive<Timestamp>),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleOptions {
+    pub counter_deltas: bool,
+    pub relative_to_start: bool,
+    pub sampling: SamplingMode,
+}
+
+// How the points in each bucket are picked when there are more points than samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplingMode {
+    // The first point
+    #[default]
+    First,
+    // The lowest and the highest point, in the order they came in, so that spikes aren't lost in
+    // the points that share a bucket with them.
+    Envelope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupMode {
+    Nearest,
+    Interpolate,
+    // The last point at or before the time, which is the value that was current then
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SpikeThreshold {
+    Absolute(f64),
+    StdDevs(f64),
+}
+
+// Where the descriptors came from. Descriptors loaded from a file stay until another file replaces
+// them, but those embedded in the metadata only go with the data they came with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DescriptorSource {
+    Default,
+    Embedded,
+    File,
 }
 
 struct DataSet {
+    // As they were loaded, with patterns, which `descriptors` has expanded for the loaded metrics
+    descriptor_templates: Descriptors,
     descriptors: Descriptors,
-    metadata: Document,
+    descriptor_source: DescriptorSource,
+    metadata: Vec<(Timestamp, Document)>,
+    format: FormatInfo,
     timestamps: Vec<Timestamp>,
     raw_data: HashMap<MetricKey, Vec<f64>>,
+    counters: HashSet<MetricKey>,
+    booleans: HashSet<MetricKey>,
+    warnings: Vec<ParseWarning>,
+    // Where the files of a directory are further apart than `file_gap_threshold`, from the end of
+    // one to the start of the next
+    gaps: Vec<RangeInclusive<Timestamp>>,
+    loaded_chunks: usize,
+    skipped_chunks: usize,
+    metric_types: MetricTypes,
+    name_separator: String,
+    file_gap_threshold: chrono::Duration,
 }
 
 impl DataSet {
     fn new() -> Self {
         Self {
+            descriptor_templates: Descriptors::new(),
             descriptors: Descriptors::new(),
-            metadata: Document::new(),
+            descriptor_source: DescriptorSource::Default,
+            metadata: vec![],
+            format: FormatInfo::default(),
             timestamps: vec![],
             raw_data: HashMap::new(),
+            counters: HashSet::new(),
+            booleans: HashSet::new(),
+            warnings: vec![],
+            gaps: vec![],
+            loaded_chunks: 0,
+            skipped_chunks: 0,
+            metric_types: MetricTypes::default(),
+            name_separator: DEFAULT_NAME_SEPARATOR.to_string(),
+            file_gap_threshold: chrono::Duration::seconds(DEFAULT_FILE_GAP_SECS),
+        }
+    }
+
+    /// An empty data set that loads data the way this one does, with the same descriptors, so that
+    /// this one stays as it is, descriptor IDs included, until the other data has loaded. The
+    /// transients are left out, since they are for the metrics of this data.
+    fn for_reload(&self) -> Self {
+        let mut descriptors = self.descriptors.clone();
+        descriptors.clear_transients();
+        Self {
+            descriptor_templates: self.descriptor_templates.clone(),
+            descriptors,
+            descriptor_source: self.descriptor_source,
+            metric_types: self.metric_types,
+            name_separator: self.name_separator.clone(),
+            file_gap_threshold: self.file_gap_threshold,
+            ..Self::new()
+        }
+    }
+
+    /// Loads the FTDC file, calling `progress`, if given, with the number of bytes read so far and
+    /// the length of the file after every chunk.
+    fn open_ftdc_file(
+        &mut self,
+        path: &Path,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        progress: Option<&mut dyn FnMut(u64, u64)>,
+    ) -> Result<()> {
+        self.load_ftdc_file(
+            path,
+            time_range,
+            &mut |_| ControlFlow::Continue(()),
+            progress,
+        )
+    }
+
+    /// Like `open_ftdc_file`, but passes every metrics chunk to `inspect` before merging it into
+    /// the data set. Any changes `inspect` makes to the chunk are reflected in the stored data.
+    /// Returning `ControlFlow::Break` stops reading without merging that chunk.
+    fn open_ftdc_file_with<F>(
+        &mut self,
+        path: &Path,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        mut inspect: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut MetricsChunk) -> ControlFlow<()>,
+    {
+        self.load_ftdc_file(path, time_range, &mut inspect, None)
+    }
+
+    fn load_ftdc_file<F>(
+        &mut self,
+        path: &Path,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        inspect: &mut F,
+        mut progress: Option<&mut dyn FnMut(u64, u64)>,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut MetricsChunk) -> ControlFlow<()>,
+    {
+        // How much there is to read from stdin isn't known up front.
+        let len = if is_stdin(path) { 0 } else { std::fs::metadata(path)?.len() };
+        self.clear();
+        // There is nothing after the file, so it doesn't matter whether to go on.
+        let _ = self.read_ftdc_file(path, time_range, inspect, &mut |bytes_read| {
+            if let Some(progress) = progress.as_mut() {
+                progress(bytes_read, len);
+            }
+        })?;
+        self.classify_metrics();
+        Ok(())
+    }
+
+    /// Loads all the metrics files in a `diagnostic.data` directory, in chronological order, as if
+    /// they were one file. The metadata of every file is kept, in order, so the first file's is the
+    /// primary one. A file that starts more than `file_gap_threshold` after the previous one ended
+    /// leaves a gap, which the charts aren't drawn across. The progress is over all the files
+    /// together.
+    fn open_ftdc_dir(
+        &mut self,
+        dir: &Path,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        mut progress: Option<&mut dyn FnMut(u64, u64)>,
+    ) -> Result<()> {
+        let paths = metrics_files(dir)?;
+        if paths.is_empty() {
+            return Err(Error::NoMetricsFiles);
+        }
+        let mut total_len = 0;
+        for path in paths.iter() {
+            total_len += std::fs::metadata(path)?.len();
+        }
+
+        self.clear();
+        let mut prev_files_len = 0;
+        for path in paths {
+            let prev_len = self.timestamps.len();
+            let flow = self.read_ftdc_file(
+                &path,
+                time_range,
+                &mut |_| ControlFlow::Continue(()),
+                &mut |bytes_read| {
+                    if let Some(progress) = progress.as_mut() {
+                        progress(prev_files_len + bytes_read, total_len);
+                    }
+                },
+            )?;
+            prev_files_len += std::fs::metadata(&path)?.len();
+            self.note_file_gap(prev_len);
+            if flow.is_break() {
+                break;
+            }
+        }
+        self.classify_metrics();
+        Ok(())
+    }
+
+    // Between the last sample before `prev_len` and the first one after it, which is where the file
+    // read since started, if either of them has any.
+    fn note_file_gap(&mut self, prev_len: usize) {
+        let (prev_end, start) = match (
+            prev_len.checked_sub(1).map(|idx| self.timestamps[idx]),
+            self.timestamps.get(prev_len),
+        ) {
+            (Some(prev_end), Some(&start)) => (prev_end, start),
+            _ => return,
+        };
+        if start - prev_end > self.file_gap_threshold {
+            self.gaps.push(prev_end..=start);
+            self.warnings
+                .push(ParseWarning::FileGap { start, prev_end });
         }
     }
 
-    fn open_ftdc_file(&mut self, path: &Path) -> Result<()> {
-        let mut file = File::open(path)?;
+    fn clear(&mut self) {
         self.metadata.clear();
+        self.format.clear();
         self.timestamps.clear();
         self.raw_data.clear();
+        self.counters.clear();
+        self.booleans.clear();
+        self.warnings.clear();
+        self.gaps.clear();
+        self.loaded_chunks = 0;
+        self.skipped_chunks = 0;
+    }
+
+    // Appends the chunks in the file to the data set. Breaks if nothing after the file can be of
+    // interest, either because `inspect` says so, or because the file reaches past the time range.
+    fn read_ftdc_file<F>(
+        &mut self,
+        path: &Path,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        inspect: &mut F,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<ControlFlow<()>>
+    where
+        F: FnMut(&mut MetricsChunk) -> ControlFlow<()>,
+    {
+        // The bytes are counted as they come from the file, so that the progress is the same
+        // whether the file is gzipped or not. Nothing below seeks, so stdin, which can't, works
+        // like any file, skipping corrupt chunks included.
+        let bytes_read = Rc::new(Cell::new(0));
+        let inner: Box<dyn Read> = if is_stdin(path) {
+            Box::new(std::io::stdin().lock())
+        } else {
+            Box::new(File::open(path)?)
+        };
+        let file = CountingReader { inner, count: Rc::clone(&bytes_read) };
+
+        // Some tools gzip the whole file on top of the compression of each chunk.
+        let mut file = BufReader::new(file);
+        let file: Box<dyn Read> = if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut chunks = ChunkReader::with_types(file, self.metric_types);
 
+        // With a time range, a data chunk is only decoded once the start of the next one shows
+        // whether it reaches into the range. Chunks are in chronological order, so reading stops at
+        // the first one that starts after the range.
+        let mut deferred: Option<Document> = None;
+        // Chunks to decode, and the range to keep of each, which are decoded a batch at a time.
+        let batch_len = decode_batch_len();
+        let mut pending = Vec::with_capacity(batch_len);
+        let mut any_chunks = false;
         loop {
-            match read_chunk(&mut file) {
-                Ok(chunk) => match chunk {
-                    Chunk::Metadata(doc) => {
-                        if self.metadata.is_empty() {
-                            self.metadata = doc;
-                        } else {
-                            // TODO: Log
-                        }
-                    }
-                    Chunk::Data(mut chunk) => {
-                        let num_values = chunk.timestamps.len();
+            let next_doc = chunks.next_doc();
+            progress(bytes_read.get());
+            let chunk_doc = match next_doc {
+                Some(Ok(chunk_doc)) => {
+                    self.format.count_chunk(&chunk_doc);
+                    chunk_doc
+                }
+                None => break,
+                // A crash in the middle of writing a chunk leaves it cut short at the end of the
+                // file.
+                Some(Err(Error::IO(err))) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.skipped_chunks += 1;
+                    break;
+                }
+                // A length too short to be a chunk's doesn't say where the next one starts, so the
+                // rest of the file is junk.
+                Some(Err(Error::InvalidChunkLength(_))) => {
+                    self.skipped_chunks += 1;
+                    break;
+                }
+                Some(Err(err)) => {
+                    self.skip_corrupt_chunk(err)?;
+                    continue;
+                }
+            };
 
-                        for (key, values) in self.raw_data.iter_mut() {
-                            match chunk.metrics.remove(key) {
-                                Some(chunk_values) => {
-                                    values.extend(chunk_values.into_iter().map(|v| v as f64))
-                                }
-                                None => values.extend((0..num_values).map(|_| f64::NAN)),
-                            };
-                        }
+            let is_data_chunk = match is_data_chunk(&chunk_doc) {
+                Ok(is_data_chunk) => is_data_chunk,
+                // Past the first chunk, it's an FTDC file with a chunk that lost its type.
+                Err(Error::NotFtdcFile) if any_chunks => {
+                    self.skipped_chunks += 1;
+                    continue;
+                }
+                Err(err) => {
+                    self.skip_corrupt_chunk(err)?;
+                    continue;
+                }
+            };
+            any_chunks = true;
 
-                        for (key, chunk_values) in chunk.metrics {
-                            if !self.descriptors.contains_key(&key) {
-                                self.descriptors
-                                    .add(Descriptor::default_for_key(key.clone()));
-                            }
-                            let values = match self.raw_data.get_mut(&key) {
-                                Some(values) => values,
-                                None => self.raw_data.entry(key).or_insert_with(Vec::new),
-                            };
-                            values.extend((0..self.timestamps.len()).map(|_| f64::NAN));
-                            values.extend(chunk_values.into_iter().map(|v| v as f64));
-                        }
+            let range = match time_range {
+                Some(range) if is_data_chunk => range,
+                _ => {
+                    pending.push((chunk_doc, None));
+                    if pending.len() >= batch_len
+                        && self.merge_chunk_docs(&mut pending, inspect)?.is_break()
+                    {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                    continue;
+                }
+            };
 
-                        self.timestamps.append(&mut chunk.timestamps);
+            let start = match self.skip_if_corrupt(chunk_start(&chunk_doc))? {
+                Some(start) => start,
+                None => continue,
+            };
+            if let Some(prev_doc) = deferred.take() {
+                if start > *range.start() {
+                    pending.push((prev_doc, time_range));
+                    if pending.len() >= batch_len
+                        && self.merge_chunk_docs(&mut pending, inspect)?.is_break()
+                    {
+                        return Ok(ControlFlow::Break(()));
                     }
-                },
-                Err(Error::EOF) => return Ok(()),
-                Err(err) => return Err(err),
+                }
+            }
+            if start > *range.end() {
+                let _ = self.merge_chunk_docs(&mut pending, inspect)?;
+                return Ok(ControlFlow::Break(()));
+            }
+            deferred = Some(chunk_doc);
+        }
+
+        // The file ended before the time range did, so this chunk is in it.
+        if let Some(chunk_doc) = deferred {
+            pending.push((chunk_doc, time_range));
+        }
+        self.merge_chunk_docs(&mut pending, inspect)
+    }
+
+    // Each chunk decodes on its own, which is most of the work of loading, so the chunks are
+    // decoded in parallel. They are then merged in order, the same as if they were decoded one by
+    // one.
+    fn merge_chunk_docs<F>(
+        &mut self,
+        chunk_docs: &mut Vec<(Document, Option<&RangeInclusive<Timestamp>>)>,
+        inspect: &mut F,
+    ) -> Result<ControlFlow<()>>
+    where
+        F: FnMut(&mut MetricsChunk) -> ControlFlow<()>,
+    {
+        let (chunk_docs, ranges): (Vec<_>, Vec<_>) = chunk_docs.drain(..).unzip();
+        for (chunk, range) in decode_chunks(chunk_docs, self.metric_types)
+            .into_iter()
+            .zip(ranges)
+        {
+            if let Some(chunk) = self.skip_if_corrupt(chunk)? {
+                self.loaded_chunks += 1;
+                if self.merge_chunk(chunk, range, inspect).is_break() {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn skip_if_corrupt<T>(&mut self, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => self.skip_corrupt_chunk(err).map(|_| None),
+        }
+    }
+
+    // Counts the chunk as skipped if the error is in its contents, so that the rest of the data can
+    // still be loaded. Anything else, like failing to read the file, is passed on.
+    fn skip_corrupt_chunk(&mut self, err: Error) -> Result<()> {
+        match err {
+            Error::BSON(_)
+            | Error::InvalidDocumentFormat(_)
+            | Error::UnknownChunkType(_)
+            | Error::InvalidNumericFormat(_)
+            | Error::IncompleteChunkData { .. }
+            | Error::CorruptChunkData(_)
+            | Error::VersionMismatch { .. }
+            | Error::ChunkTooLarge
+            | Error::MissingTimestampField => {
+                self.skipped_chunks += 1;
+                Ok(())
             }
+            err => Err(err),
         }
     }
 
-    fn load_descriptors(&mut self, path: &Path) -> std::io::Result<()> {
+    fn classify_metrics(&mut self) {
+        self.booleans = self
+            .raw_data
+            .iter()
+            .filter(|(_, values)| is_boolean(values))
+            .map(|(key, _)| key.clone())
+            .collect();
+        // A flag that switches on once and stays on never decreases, but its deltas say nothing
+        // that the on/off bands don't.
+        self.counters = self
+            .raw_data
+            .iter()
+            .filter(|(key, values)| !self.booleans.contains(*key) && is_counter(values))
+            .map(|(key, _)| key.clone())
+            .collect();
+    }
+
+    fn merge_chunk<F>(
+        &mut self,
+        chunk: Chunk,
+        time_range: Option<&RangeInclusive<Timestamp>>,
+        inspect: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(&mut MetricsChunk) -> ControlFlow<()>,
+    {
+        let mut chunk = match chunk {
+            Chunk::Metadata(chunk) => {
+                if self.metadata.is_empty() {
+                    self.format.set_metadata(&chunk.doc);
+                }
+                self.metadata.push((chunk.timestamp, chunk.doc));
+                return ControlFlow::Continue(());
+            }
+            Chunk::Data(chunk) => chunk,
+        };
+
+        if let Some(range) = time_range {
+            chunk.retain_range(range);
+        }
+
+        inspect(&mut chunk)?;
+
+        self.warnings.append(&mut chunk.warnings);
+        if let (Some(&prev_end), Some(&start)) = (self.timestamps.last(), chunk.timestamps.first())
+        {
+            if start < prev_end {
+                self.warnings
+                    .push(ParseWarning::OutOfOrderChunk { start, prev_end });
+            }
+        }
+
+        let num_values = chunk.timestamps.len();
+
+        for (key, values) in self.raw_data.iter_mut() {
+            match chunk.metrics.remove(key) {
+                Some(chunk_values) => values.extend(chunk_values.into_iter().map(|v| v as f64)),
+                None => values.extend((0..num_values).map(|_| f64::NAN)),
+            };
+        }
+
+        for (key, chunk_values) in chunk.metrics {
+            if !self.descriptors.covers_key(&key) {
+                self.descriptors.add(Descriptor::default_for_key(
+                    key.clone(),
+                    &self.name_separator,
+                ));
+            }
+            let values = match self.raw_data.get_mut(&key) {
+                Some(values) => values,
+                None => self.raw_data.entry(key).or_insert_with(Vec::new),
+            };
+            values.extend((0..self.timestamps.len()).map(|_| f64::NAN));
+            values.extend(chunk_values.into_iter().map(|v| v as f64));
+        }
+
+        self.timestamps.append(&mut chunk.timestamps);
+
+        ControlFlow::Continue(())
+    }
+
+    // The first metadata document describes the process as it was when the capture started, which
+    // is what matters for things like the server version.
+    fn primary_metadata(&self) -> Option<&Document> {
+        self.metadata.first().map(|(_, doc)| doc)
+    }
+
+    fn load_descriptors(&mut self, path: &Path) -> anyhow::Result<()> {
         let file = File::open(path)?;
-        self.descriptors = serde_json::from_reader(file)?;
+        let is_yaml = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+            .unwrap_or_default();
+        let descriptors =
+            if is_yaml { serde_yaml::from_reader(file)? } else { serde_json::from_reader(file)? };
+        self.set_descriptors(descriptors, DescriptorSource::File);
+        Ok(())
+    }
+
+    /// Goes back from descriptors loaded from a file to those embedded in the metadata of the loaded
+    /// data, if there are any, or the defaults.
+    fn reset_descriptors(&mut self) -> anyhow::Result<()> {
+        self.set_descriptors(Descriptors::new(), DescriptorSource::Default);
+        self.apply_embedded_descriptors()?;
+        Ok(())
+    }
+
+    /// Applies the descriptors embedded in the metadata of the loaded data, in the same shape as a
+    /// descriptors file, under the `r2t2Descriptors` field. Descriptors loaded from a file take
+    /// precedence. Returns whether the descriptors changed, which includes going back to the
+    /// defaults when the previous data had embedded descriptors and this one doesn't.
+    fn apply_embedded_descriptors(&mut self) -> anyhow::Result<bool> {
+        if self.descriptor_source == DescriptorSource::File {
+            return Ok(false);
+        }
+
+        let embedded = self
+            .primary_metadata()
+            .and_then(|doc| doc.get_document(EMBEDDED_DESCRIPTORS_FIELD).ok())
+            .cloned();
+        match embedded {
+            Some(doc) => match bson::from_document(doc) {
+                Ok(descriptors) => {
+                    self.set_descriptors(descriptors, DescriptorSource::Embedded);
+                    Ok(true)
+                }
+                Err(err) => {
+                    // Don't leave the descriptors embedded in some other data in place.
+                    self.set_descriptors(Descriptors::new(), DescriptorSource::Default);
+                    Err(err.into())
+                }
+            },
+            None if self.descriptor_source == DescriptorSource::Embedded => {
+                self.set_descriptors(Descriptors::new(), DescriptorSource::Default);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn set_descriptors(&mut self, descriptors: Descriptors, source: DescriptorSource) {
+        self.descriptor_templates = descriptors;
+        self.descriptor_source = source;
+        self.expand_descriptors();
+    }
+
+    /// Expands the patterns in the descriptors again for the metrics loaded since, if there are
+    /// any. Returns whether there were.
+    fn refresh_patterns(&mut self) -> bool {
+        if !self.descriptor_templates.has_patterns() {
+            return false;
+        }
+        self.expand_descriptors();
+        true
+    }
+
+    fn expand_descriptors(&mut self) {
+        let mut keys: Vec<_> = self.raw_data.keys().cloned().collect();
+        keys.sort();
+        self.descriptors = self
+            .descriptor_templates
+            .expanded(&keys, &self.name_separator);
         for key in self.raw_data.keys() {
-            if !self.descriptors.contains_key(key) {
-                self.descriptors
-                    .add(Descriptor::default_for_key(key.clone()));
+            if !self.descriptors.covers_key(key) {
+                self.descriptors.add(Descriptor::default_for_key(
+                    key.clone(),
+                    &self.name_separator,
+                ));
             }
         }
+    }
+
+    fn export_descriptor_template(&self, path: &Path) -> anyhow::Result<()> {
+        let mut keys: Vec<_> = self.raw_data.keys().cloned().collect();
+        keys.sort();
+
+        let descriptors: Vec<_> = keys
+            .into_iter()
+            .map(|key| Descriptor::default_for_key(key, &self.name_separator))
+            .collect();
+        let template = HashMap::from([(TEMPLATE_SECTION, descriptors)]);
+
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &template)?;
         Ok(())
     }
 
@@ -108,84 +655,943 @@ impl DataSet {
         ids: Vec<usize>,
         range: RangeInclusive<Timestamp>,
         num_samples: usize,
+        options: SampleOptions,
     ) -> HashMap<usize, Vec<(Timestamp, f64)>> {
         let mut result = HashMap::with_capacity(ids.len());
 
         for id in ids {
             let desc = Rc::clone(&self.descriptors[id]);
-            let values = match self.raw_data.get(&desc.key) {
-                Some(values) => values,
-                None => {
-                    result.insert(id, vec![]);
-                    continue;
+            let samples = match self.metric_values(&desc) {
+                Some(values) => {
+                    self.sample_values(&values, &desc, &desc.key, &range, num_samples, options)
+                }
+                None => vec![],
+            };
+            result.insert(id, samples);
+        }
+
+        result
+    }
+
+    // The overlaid series of the charts that have any, each sampled like the main one and scaled the
+    // same way. A key that isn't in the data still gets a series, so that the rest keep their
+    // places.
+    fn sample_overlays(
+        &self,
+        ids: &[usize],
+        range: RangeInclusive<Timestamp>,
+        num_samples: usize,
+        options: SampleOptions,
+    ) -> HashMap<usize, Vec<Vec<(Timestamp, f64)>>> {
+        ids.iter()
+            .map(|&id| &self.descriptors[id])
+            .filter(|desc| !desc.overlay.is_empty())
+            .map(|desc| {
+                let series = desc
+                    .overlay
+                    .iter()
+                    .map(|key| match self.raw_data.get(key) {
+                        Some(values) => self.sample_values(
+                            &self.transformed(Cow::from(&values[..]), desc.transform),
+                            desc,
+                            key,
+                            &range,
+                            num_samples,
+                            options,
+                        ),
+                        None => vec![],
+                    })
+                    .collect();
+                (desc.id, series)
+            })
+            .collect()
+    }
+
+    // The values are those of `key`, transformed and scaled the way `desc` says. The transform is
+    // already applied to them, and only keeps counter deltas from being taken of them again.
+    fn sample_values(
+        &self,
+        values: &[f64],
+        desc: &Descriptor,
+        key: &MetricKey,
+        range: &RangeInclusive<Timestamp>,
+        num_samples: usize,
+        options: SampleOptions,
+    ) -> Vec<(Timestamp, f64)> {
+        let deltas =
+            options.counter_deltas && desc.transform.is_none() && self.counters.contains(key);
+        let value_at = |idx: usize| metric_value(values, idx, deltas, desc);
+
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+
+        // A zero-width range between two points has no points of its own, so show the value
+        // that was current at that time instead. Otherwise, when there are no more points than
+        // samples, downsampling could only lose points that happen to share a bucket, so just
+        // take all of them. A range that is entirely before or after the data, or that is turned
+        // around, has no points at all.
+        let mut samples = if range.start() == range.end() && start_idx == end_idx {
+            self.value_at(*range.start(), LookupMode::Previous, value_at)
+                .map(|value| vec![(*range.start(), value)])
+                .unwrap_or_default()
+        } else if start_idx >= end_idx || num_samples == 0 {
+            vec![]
+        } else if end_idx - start_idx <= num_samples {
+            (start_idx..end_idx)
+                .map(|idx| (self.timestamps[idx], value_at(idx)))
+                .filter(|(_, value)| !value.is_nan())
+                .collect()
+        } else {
+            self.bucket_samples(
+                start_idx..end_idx,
+                range,
+                num_samples,
+                options.sampling,
+                value_at,
+            )
+        };
+
+        // Gaps are already filtered out, so the first sample is the first valid value. Booleans
+        // are drawn as on/off bands, so they are left as they are.
+        if options.relative_to_start && !self.booleans.contains(key) {
+            if let Some(&(_, first)) = samples.first() {
+                for (_, value) in samples.iter_mut() {
+                    *value -= first;
                 }
+            }
+        }
+
+        samples
+    }
+
+    fn bucket_samples<F: Fn(usize) -> f64>(
+        &self,
+        indices: Range<usize>,
+        range: &RangeInclusive<Timestamp>,
+        num_samples: usize,
+        mode: SamplingMode,
+        value_at: F,
+    ) -> Vec<(Timestamp, f64)> {
+        let mut samples = Vec::with_capacity(num_samples);
+        let start_millis = range.start().timestamp_millis();
+        let delta = std::cmp::max(
+            1,
+            (*range.end() - *range.start()).num_milliseconds() / (num_samples as i64),
+        );
+        // Every point is assigned to a bucket of width `delta`. This applies all the way to the end
+        // of the range, so that the density of samples is uniform across the chart.
+        match mode {
+            SamplingMode::First => {
+                // Only the first valid point in each bucket is kept.
+                let mut sample_millis = start_millis;
+                for idx in indices.clone() {
+                    let timestamp = self.timestamps[idx];
+                    let millis = timestamp.timestamp_millis();
+                    if millis < sample_millis {
+                        continue;
+                    }
+
+                    let value = value_at(idx);
+                    if value.is_nan() {
+                        continue;
+                    }
+
+                    samples.push((timestamp, value));
+                    sample_millis = millis - (millis - start_millis) % delta + delta;
+                }
+            }
+            SamplingMode::Envelope => envelope_samples(&mut samples, indices.clone(), |idx| {
+                let timestamp = self.timestamps[idx];
+                let bucket = (timestamp.timestamp_millis() - start_millis) / delta;
+                (bucket, timestamp, value_at(idx))
+            }),
+        }
+
+        // The last bucket would otherwise end the line at its first point, short of the end of
+        // the data.
+        let last = indices
+            .rev()
+            .map(|idx| (self.timestamps[idx], value_at(idx)))
+            .find(|(_, value)| !value.is_nan());
+        if let Some(last) = last {
+            if samples.last().map(|(timestamp, _)| *timestamp) != Some(last.0) {
+                samples.push(last);
+            }
+        }
+
+        samples
+    }
+
+    /// Looks up the value of a metric at `timestamp`, where `value_of` gives its value, already
+    /// scaled, at each index into the timestamps. See [`lookup_value`] for how the value is found.
+    fn value_at<F: Fn(usize) -> f64>(
+        &self,
+        timestamp: Timestamp,
+        mode: LookupMode,
+        value_of: F,
+    ) -> Option<f64> {
+        lookup_value(&self.timestamps, timestamp, mode, |t| *t, value_of)
+    }
+
+    /// The values that a descriptor charts, which for a histogram come from its bucket counts.
+    fn metric_values(&self, desc: &Descriptor) -> Option<Cow<'_, [f64]>> {
+        let values = match desc.histogram.as_ref() {
+            Some(histogram) => self.histogram_values(desc, histogram)?,
+            None => Cow::from(&self.raw_data.get(&desc.key)?[..]),
+        };
+        Some(self.transformed(values, desc.transform))
+    }
+
+    fn histogram_values(&self, desc: &Descriptor, histogram: &Histogram) -> Option<Cow<'_, [f64]>> {
+        let missing = vec![f64::NAN; self.timestamps.len()];
+        let mut found = false;
+        let buckets: Vec<&[f64]> = (0..histogram.bounds.len())
+            .map(|idx| {
+                let mut key = desc.key.clone();
+                key.push(&idx.to_string());
+                match self.raw_data.get(&key) {
+                    Some(values) => {
+                        found = true;
+                        &values[..]
+                    }
+                    None => &missing[..],
+                }
+            })
+            .collect();
+        found.then(|| Cow::from(histogram.percentiles(&buckets, self.timestamps.len())))
+    }
+
+    fn transformed<'v>(
+        &self,
+        values: Cow<'v, [f64]>,
+        transform: Option<Transform>,
+    ) -> Cow<'v, [f64]> {
+        match transform {
+            None => values,
+            Some(Transform::Rate) => Cow::from(rates(&values, &self.timestamps)),
+        }
+    }
+
+    // Gaps aren't points, so a range with nothing but gaps has none.
+    fn count_points(
+        &self,
+        ids: &[usize],
+        range: &RangeInclusive<Timestamp>,
+    ) -> HashMap<usize, usize> {
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+        ids.iter()
+            .map(|&id| {
+                // A range that ends before it starts has no points rather than a slice to take.
+                let count = self
+                    .metric_values(&self.descriptors[id])
+                    .map_or(0, |values| {
+                        values.get(start_idx..end_idx).map_or(0, |values| {
+                            values.iter().filter(|value| !value.is_nan()).count()
+                        })
+                    });
+                (id, count)
+            })
+            .collect()
+    }
+
+    fn raw_values(&self, id: usize, range: RangeInclusive<Timestamp>) -> Vec<(Timestamp, f64)> {
+        let values = match self.raw_data.get(&self.descriptors[id].key) {
+            Some(values) => values,
+            None => return vec![],
+        };
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+        (start_idx..end_idx)
+            .map(|idx| (self.timestamps[idx], values[idx]))
+            .collect()
+    }
+
+    /// Finds the first timestamp after `after` at which any of the metrics rises above the
+    /// threshold. Only the first point of a run above the threshold counts, so that searching again
+    /// from a spike moves on to the next one.
+    fn find_spike(
+        &self,
+        ids: Vec<usize>,
+        after: Timestamp,
+        threshold: SpikeThreshold,
+        options: SampleOptions,
+    ) -> Option<Timestamp> {
+        let start_idx = self.timestamps.partition_point(|t| *t <= after);
+        let mut result: Option<usize> = None;
+
+        for id in ids {
+            let desc = &self.descriptors[id];
+            let values = match self.metric_values(desc) {
+                Some(values) => values,
+                None => continue,
             };
+            let deltas = options.counter_deltas
+                && desc.transform.is_none()
+                && self.counters.contains(&desc.key);
+            let value_at = |idx: usize| metric_value(&values, idx, deltas, desc);
 
-            let mut start_idx = match self.timestamps.binary_search(range.start()) {
-                Ok(idx) => idx,
-                Err(idx) => idx,
+            let limit = match threshold {
+                SpikeThreshold::Absolute(limit) => limit,
+                SpikeThreshold::StdDevs(num_devs) => {
+                    let valid: Vec<_> = (0..values.len())
+                        .map(value_at)
+                        .filter(|v| !v.is_nan())
+                        .collect();
+                    if valid.is_empty() {
+                        continue;
+                    }
+                    let mean = valid.iter().sum::<f64>() / valid.len() as f64;
+                    let variance = valid.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>()
+                        / valid.len() as f64;
+                    mean + num_devs * variance.sqrt()
+                }
             };
-            let end_idx = match self.timestamps.binary_search(range.end()) {
-                Ok(idx) => idx,
-                Err(idx) => idx - 1,
+
+            // No need to look past a spike that was already found in another metric.
+            let end_idx = result.unwrap_or(self.timestamps.len());
+            let above = |idx: usize| value_at(idx) > limit;
+            let found =
+                (start_idx..end_idx).find(|&idx| above(idx) && (idx == 0 || !above(idx - 1)));
+            if found.is_some() {
+                result = found;
+            }
+        }
+
+        result.map(|idx| self.timestamps[idx])
+    }
+
+    /// Samples the metrics at the same `num_samples` timestamps, evenly spaced across the range, so
+    /// that the values line up across metrics. Each grid point takes the value of the nearest data
+    /// point in the range, or NaN if there is none or the metric has no value there.
+    fn sample_on_grid(
+        &self,
+        ids: &[usize],
+        range: RangeInclusive<Timestamp>,
+        num_samples: usize,
+    ) -> (Vec<Timestamp>, HashMap<usize, Vec<f64>>) {
+        let (grid, nearest) = self.time_grid(range, num_samples);
+
+        let mut result = HashMap::with_capacity(ids.len());
+        for &id in ids {
+            let desc = &self.descriptors[id];
+            let values = match self.metric_values(desc) {
+                Some(values) => nearest
+                    .iter()
+                    .map(|idx| idx.map_or(f64::NAN, |idx| desc.scaled(values[idx])))
+                    .collect(),
+                None => vec![f64::NAN; grid.len()],
             };
+            result.insert(id, values);
+        }
 
-            let mut samples = Vec::with_capacity(num_samples);
-            let delta = (*range.end() - *range.start()).num_milliseconds() / (num_samples as i64);
-            let mut sample_time = range.start().timestamp_millis();
+        (grid, result)
+    }
 
-            while (end_idx - start_idx) >= num_samples {
-                let start_time = self.timestamps[start_idx];
-                if start_time.timestamp_millis() >= sample_time {
-                    let value = values[start_idx];
-                    if !value.is_nan() {
-                        samples.push((start_time, value / desc.scale));
+    /// Samples the difference between each pair of metrics, the first minus the second, on the same
+    /// grid as `sample_on_grid`. Where either of them has no value, neither does the difference.
+    fn sample_differences(
+        &self,
+        pairs: &[(usize, usize)],
+        range: RangeInclusive<Timestamp>,
+        num_samples: usize,
+    ) -> Vec<Vec<(Timestamp, f64)>> {
+        if pairs.is_empty() {
+            return vec![];
+        }
+
+        let ids: Vec<_> = pairs.iter().flat_map(|&(lhs, rhs)| [lhs, rhs]).collect();
+        let (grid, values) = self.sample_on_grid(&ids, range, num_samples);
+        pairs
+            .iter()
+            .map(|(lhs, rhs)| {
+                grid.iter()
+                    .zip(values[lhs].iter().zip(values[rhs].iter()))
+                    .map(|(t, (lhs, rhs))| (*t, lhs - rhs))
+                    .filter(|(_, value)| !value.is_nan())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Returns `num_samples` timestamps evenly spaced across the range, along with the index of the
+    // data point nearest to each one, if there are any points in the range.
+    fn time_grid(
+        &self,
+        range: RangeInclusive<Timestamp>,
+        num_samples: usize,
+    ) -> (Vec<Timestamp>, Vec<Option<usize>>) {
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+
+        let span_millis = (*range.end() - *range.start()).num_milliseconds();
+        let grid: Vec<_> = (0..num_samples)
+            .map(|i| match num_samples {
+                1 => *range.start(),
+                _ => {
+                    let offset = span_millis * (i as i64) / ((num_samples - 1) as i64);
+                    *range.start() + chrono::Duration::milliseconds(offset)
+                }
+            })
+            .collect();
+
+        let nearest: Vec<_> = grid
+            .iter()
+            .map(|t| {
+                if start_idx >= end_idx {
+                    return None;
+                }
+                let idx =
+                    start_idx + self.timestamps[start_idx..end_idx].partition_point(|p| p < t);
+                let after = (idx < end_idx).then_some(idx);
+                let before = (idx > start_idx).then(|| idx - 1);
+                match (before, after) {
+                    (Some(before), Some(after)) => {
+                        if *t - self.timestamps[before] <= self.timestamps[after] - *t {
+                            Some(before)
+                        } else {
+                            Some(after)
+                        }
                     }
-                    sample_time += delta;
+                    (before, after) => before.or(after),
+                }
+            })
+            .collect();
+
+        (grid, nearest)
+    }
+
+    /// Writes the min, max, mean and last value, and the number of points, of each metric in the
+    /// range to a CSV file, one row per metric in the given order. Values are transformed the same
+    /// way `sample_metrics` does, but without downsampling.
+    fn export_stats(
+        &self,
+        path: &Path,
+        metrics: Vec<(usize, String)>,
+        range: RangeInclusive<Timestamp>,
+        options: SampleOptions,
+    ) -> anyhow::Result<()> {
+        let start_idx = self.timestamps.partition_point(|t| t < range.start());
+        let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+
+        let stats: Vec<_> = metrics
+            .into_iter()
+            .map(|(id, name)| {
+                let desc = &self.descriptors[id];
+                let values = match self.metric_values(desc) {
+                    Some(values) => values,
+                    None => return MetricStats::compute(name, []),
+                };
+                let deltas = options.counter_deltas
+                    && desc.transform.is_none()
+                    && self.counters.contains(&desc.key);
+                let mut values: Vec<_> = (start_idx..end_idx)
+                    .map(|idx| metric_value(&values, idx, deltas, desc))
+                    .collect();
+                if options.relative_to_start && !self.booleans.contains(&desc.key) {
+                    if let Some(&first) = values.iter().find(|value| !value.is_nan()) {
+                        for value in values.iter_mut() {
+                            *value -= first;
+                        }
+                    }
+                }
+                MetricStats::compute(name, values)
+            })
+            .collect();
+
+        let file = BufWriter::new(File::create(path)?);
+        write_stats_csv(file, &stats)
+    }
+
+    /// Writes the raw values of all metrics to a Parquet file, one column per metric. With
+    /// `num_rows`, the values are taken on an evenly spaced grid like `sample_on_grid` does,
+    /// otherwise every point in the range is written.
+    #[cfg(feature = "parquet")]
+    fn export_parquet(
+        &self,
+        path: &Path,
+        range: Option<RangeInclusive<Timestamp>>,
+        num_rows: Option<usize>,
+    ) -> anyhow::Result<()> {
+        let range = match range {
+            Some(range) => range,
+            None => match (self.timestamps.first(), self.timestamps.last()) {
+                (Some(&start), Some(&end)) => start..=end,
+                _ => Timestamp::MIN_UTC..=Timestamp::MAX_UTC,
+            },
+        };
+        let (timestamps, indices): (Vec<_>, Vec<_>) = match num_rows {
+            Some(num_rows) => self.time_grid(range, num_rows),
+            None => {
+                let start_idx = self.timestamps.partition_point(|t| t < range.start());
+                let end_idx = self.timestamps.partition_point(|t| t <= range.end());
+                if start_idx >= end_idx {
+                    (Vec::new(), Vec::new())
+                } else {
+                    (
+                        self.timestamps[start_idx..end_idx].to_vec(),
+                        (start_idx..end_idx).map(Some).collect(),
+                    )
                 }
-                start_idx += 1;
             }
-            samples.extend(
-                (start_idx..=end_idx)
-                    .into_iter()
-                    .filter(|&idx| !values[idx].is_nan())
-                    .map(|idx| (self.timestamps[idx], values[idx] / desc.scale)),
-            );
+        };
 
-            result.insert(id, samples);
+        let mut keys: Vec<_> = self.raw_data.keys().collect();
+        keys.sort();
+        let columns: Vec<_> = keys
+            .into_iter()
+            .map(|key| {
+                let values = &self.raw_data[key];
+                ExportColumn {
+                    name: key.join(metric::KEY_PATH_SEPARATOR),
+                    values: indices
+                        .iter()
+                        .map(|idx| idx.map_or(f64::NAN, |idx| values[idx]))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let file = BufWriter::new(File::create(path)?);
+        write_parquet(file, &timestamps, &columns)
+    }
+}
+
+const TEMPLATE_SECTION: &str = "UNGROUPED";
+const EMBEDDED_DESCRIPTORS_FIELD: &str = "r2t2Descriptors";
+const LOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const STDIN_PATH: &str = "-";
+// Files follow each other within a couple of samples, which are a second apart, unless the server
+// was down in between.
+const DEFAULT_FILE_GAP_SECS: i64 = 10;
+const MAX_FILE_GAP_SECS: i64 = 365 * 24 * 60 * 60;
+const METRICS_FILE_PREFIX: &str = "metrics.";
+const METRICS_INTERIM_FILE_NAME: &str = "metrics.interim";
+
+/// Looks up the value at `timestamp` among `points`, which are in ascending order of time. The value
+/// comes from the nearest point, by interpolating between the points on either side, or from the
+/// point before, depending on `mode`. Returns `None` if the timestamp is outside of the points, or
+/// if the points it would use are gaps.
+pub fn lookup_value<T>(
+    points: &[T],
+    timestamp: Timestamp,
+    mode: LookupMode,
+    time_of: impl Fn(&T) -> Timestamp,
+    value_at: impl Fn(usize) -> f64,
+) -> Option<f64> {
+    let (first, last) = (time_of(points.first()?), time_of(points.last()?));
+    if timestamp < first || timestamp > last {
+        return None;
+    }
+
+    let idx = points.partition_point(|point| time_of(point) < timestamp);
+    if time_of(&points[idx]) == timestamp {
+        let value = value_at(idx);
+        return (!value.is_nan()).then_some(value);
+    }
+
+    // The timestamp is strictly between two points, so there is one on either side.
+    let (before, after) = (idx - 1, idx);
+    let (before_time, after_time) = (time_of(&points[before]), time_of(&points[after]));
+    let value = match mode {
+        LookupMode::Nearest => {
+            let (near, far) = if timestamp - before_time <= after_time - timestamp {
+                (before, after)
+            } else {
+                (after, before)
+            };
+            let value = value_at(near);
+            if value.is_nan() {
+                value_at(far)
+            } else {
+                value
+            }
         }
+        LookupMode::Interpolate => {
+            let span = (after_time - before_time).num_milliseconds();
+            let offset = (timestamp - before_time).num_milliseconds();
+            let fraction = offset as f64 / span as f64;
+            let (before_value, after_value) = (value_at(before), value_at(after));
+            before_value + (after_value - before_value) * fraction
+        }
+        LookupMode::Previous => value_at(before),
+    };
+    (!value.is_nan()).then_some(value)
+}
 
-        result
+fn metric_value(values: &[f64], idx: usize, deltas: bool, desc: &Descriptor) -> f64 {
+    match (deltas, idx) {
+        (false, _) => desc.scaled(values[idx]),
+        (true, 0) => f64::NAN,
+        (true, _) => (values[idx] - values[idx - 1]) / desc.scale,
+    }
+}
+
+// `point_at` gives the bucket, the time and the value of each point. Each bucket gets its lowest and
+// its highest point, or just the one if they are the same.
+fn envelope_samples<F>(samples: &mut Vec<(Timestamp, f64)>, indices: Range<usize>, point_at: F)
+where
+    F: Fn(usize) -> (i64, Timestamp, f64),
+{
+    type Envelope = (i64, (Timestamp, f64), (Timestamp, f64));
+    let flush = |samples: &mut Vec<_>, (_, min, max): Envelope| {
+        if min.0 == max.0 {
+            samples.push(min);
+        } else if min.0 < max.0 {
+            samples.extend([min, max]);
+        } else {
+            samples.extend([max, min]);
+        }
+    };
+
+    let mut current: Option<Envelope> = None;
+    for idx in indices {
+        let (bucket, timestamp, value) = point_at(idx);
+        if value.is_nan() {
+            continue;
+        }
+        match current.as_mut() {
+            Some((current_bucket, min, max)) if *current_bucket == bucket => {
+                if value < min.1 {
+                    *min = (timestamp, value);
+                }
+                if value > max.1 {
+                    *max = (timestamp, value);
+                }
+            }
+            _ => {
+                if let Some(envelope) = current.take() {
+                    flush(samples, envelope);
+                }
+                current = Some((bucket, (timestamp, value), (timestamp, value)));
+            }
+        }
+    }
+    if let Some(envelope) = current {
+        flush(samples, envelope);
+    }
+}
+
+// The change per second between each point and the one before it. A counter that went down was
+// reset, which counts as no change rather than as a negative rate.
+fn rates(values: &[f64], timestamps: &[Timestamp]) -> Vec<f64> {
+    (0..values.len())
+        .map(|idx| {
+            let prev = match idx.checked_sub(1) {
+                Some(prev) => prev,
+                None => return f64::NAN,
+            };
+            let secs = (timestamps[idx] - timestamps[prev]).num_milliseconds() as f64 / 1000.0;
+            let delta = values[idx] - values[prev];
+            if secs <= 0.0 || delta.is_nan() {
+                f64::NAN
+            } else {
+                delta.max(0.0) / secs
+            }
+        })
+        .collect()
+}
+
+// A boolean is a metric whose only values are 0 and 1, which is how BSON booleans are stored, but
+// also covers numeric flags.
+fn is_boolean(values: &[f64]) -> bool {
+    let mut values = values.iter().filter(|value| !value.is_nan()).peekable();
+    values.peek().is_some() && values.all(|&value| value == 0.0 || value == 1.0)
+}
+
+// A counter is a metric whose value never decreases, but does change at some point.
+fn is_counter(values: &[f64]) -> bool {
+    let mut values = values.iter().filter(|value| !value.is_nan());
+    let mut prev = match values.next() {
+        Some(value) => value,
+        None => return false,
+    };
+    let mut changed = false;
+    for value in values {
+        if value < prev {
+            return false;
+        }
+        changed |= value != prev;
+        prev = value;
+    }
+    changed
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count.set(self.count.get() + len as u64);
+        Ok(len)
+    }
+}
+
+// MongoDB names the metrics files in diagnostic.data after the time they were started, with a
+// counter to break ties, so they sort chronologically by name. The interim file holds the most
+// recent samples, which are yet to go into a regular file.
+fn metrics_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut interim = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        match name.to_str() {
+            Some(METRICS_INTERIM_FILE_NAME) => interim = Some(entry.path()),
+            Some(name) if name.starts_with(METRICS_FILE_PREFIX) => files.push(entry.path()),
+            _ => (),
+        }
     }
+    files.sort();
+    files.extend(interim);
+    Ok(files)
+}
+
+// Stdin can only be read once, so opening it again later, like for a time range, finds it empty.
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+}
+
+struct Args {
+    file: Option<PathBuf>,
+    descriptors: Option<PathBuf>,
+    from: Option<Timestamp>,
+    to: Option<Timestamp>,
+    validate: bool,
+    json: bool,
+    metric_types: MetricTypes,
+    name_separator: String,
+    file_gap_secs: i64,
+}
+
+const USAGE: &str = "\
+Usage: r2t2 [OPTIONS] [PATH]
+
+Arguments:
+  [PATH]           Same as --file PATH
+
+Options:
+  --file <PATH>    Open the FTDC file, or diagnostic.data directory, on startup, or read the
+                   file from stdin if PATH is -
+  --descriptors <PATH>
+                   Load the descriptors in the JSON or YAML file at PATH on startup, instead of
+                   the ones from the last session
+  --from <TIME>    Zoom to start at TIME (RFC 3339, e.g. 2023-10-01T12:00:00Z)
+  --to <TIME>      Zoom to end at TIME (RFC 3339)
+  --validate       Read the file given by PATH or --file without opening the window, print a
+                   summary of it, and exit with a nonzero code if it couldn't be read to the end
+  --json           Print the --validate summary as JSON
+  --exclude-types <TYPES>
+                   Don't chart metrics stored as any of the comma-separated BSON TYPES (int32,
+                   int64, double, decimal128, boolean, datetime, timestamp)
+  --key-separator <SEP>
+                   Join the elements of metric keys with SEP in the names of metrics that have no
+                   descriptor (default: a space)
+  --gap-threshold <SECS>
+                   Break the charts between files of a diagnostic.data directory that are more
+                   than SECS seconds apart (default: 10)
+  --help           Print this message and exit
+
+Zoom > Copy View as Arguments puts the options that reproduce the current view on the clipboard.";
+
+fn parse_args() -> std::result::Result<Args, String> {
+    let mut args = Args {
+        file: None,
+        descriptors: None,
+        from: None,
+        to: None,
+        validate: false,
+        json: false,
+        metric_types: MetricTypes::default(),
+        name_separator: DEFAULT_NAME_SEPARATOR.to_string(),
+        file_gap_secs: DEFAULT_FILE_GAP_SECS,
+    };
+    let mut argv = std::env::args_os().skip(1);
+    while let Some(arg_os) = argv.next() {
+        let arg = arg_os.to_string_lossy().into_owned();
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "--validate" => {
+                args.validate = true;
+                continue;
+            }
+            "--json" => {
+                args.json = true;
+                continue;
+            }
+            // Anything that isn't an option is the file, including - for stdin.
+            _ if !arg.starts_with("--") => {
+                if args.file.is_some() {
+                    return Err(format!("more than one file given: {}", arg));
+                }
+                args.file = Some(PathBuf::from(arg_os));
+                continue;
+            }
+            _ => (),
+        }
+
+        let value = argv
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--file" => args.file = Some(PathBuf::from(value)),
+            "--descriptors" => args.descriptors = Some(PathBuf::from(value)),
+            "--from" => args.from = Some(parse_time_arg(&arg, &value)?),
+            "--to" => args.to = Some(parse_time_arg(&arg, &value)?),
+            "--exclude-types" => {
+                for name in value.to_string_lossy().split(',') {
+                    if !args.metric_types.set(name.trim(), false) {
+                        return Err(format!("invalid value for {}: {}", arg, name));
+                    }
+                }
+            }
+            "--key-separator" => args.name_separator = value.to_string_lossy().into_owned(),
+            "--gap-threshold" => {
+                args.file_gap_secs = match value.to_string_lossy().parse() {
+                    Ok(secs) if (0..=MAX_FILE_GAP_SECS).contains(&secs) => secs,
+                    _ => return Err(format!("invalid value for {}: {:?}", arg, value)),
+                }
+            }
+            _ => return Err(format!("unrecognized argument: {}", arg)),
+        }
+    }
+    if args.validate && args.file.is_none() {
+        return Err("--validate requires a file".to_string());
+    }
+    Ok(args)
+}
+
+fn parse_time_arg(arg: &str, value: &OsStr) -> std::result::Result<Timestamp, String> {
+    let value = value.to_string_lossy();
+    chrono::DateTime::parse_from_rfc3339(&value)
+        .map(Into::into)
+        .map_err(|err| format!("invalid value for {}: {}: {}", arg, value, err))
 }
 
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.validate {
+        std::process::exit(validate::validate(
+            args.file.as_ref().unwrap(),
+            args.metric_types,
+            args.json,
+        ));
+    }
+
     let app = app::App::default();
     let (tx, rx) = app::channel();
 
     let main_window = MainWindow::new(1280, 720, tx);
     let mut dataset = DataSet::new();
+    dataset.metric_types = args.metric_types;
+    dataset.name_separator = args.name_separator;
+    dataset.file_gap_threshold = chrono::Duration::seconds(args.file_gap_secs);
 
     app::add_check({
         let main_window = Rc::clone(&main_window);
         move |_| {
             while let Some(msg) = rx.recv() {
                 match msg {
-                    Message::OpenFile(path) => {
-                        match dataset.open_ftdc_file(&path) {
+                    Message::OpenFile(path, time_range) => {
+                        // Every chunk would be far too often to redraw the window.
+                        let mut last_progress = Instant::now();
+                        let mut progress = |bytes_read, len| {
+                            if last_progress.elapsed() >= LOAD_PROGRESS_INTERVAL {
+                                last_progress = Instant::now();
+                                main_window.update(Update::LoadProgress(bytes_read, len));
+                            }
+                        };
+                        // The window keeps showing the current data if this fails, so it is only
+                        // replaced once the new data is there.
+                        let mut loaded = dataset.for_reload();
+                        let result = if !is_stdin(&path) && path.is_dir() {
+                            loaded.open_ftdc_dir(&path, time_range.as_ref(), Some(&mut progress))
+                        } else {
+                            loaded.open_ftdc_file(&path, time_range.as_ref(), Some(&mut progress))
+                        };
+                        match result {
+                            Err(Error::NotFtdcFile) => {
+                                fltk::dialog::alert_default("This doesn't look like an FTDC file.");
+                                main_window.update(Update::LoadFailed);
+                            }
+                            Err(Error::NoMetricsFiles) => {
+                                fltk::dialog::alert_default(
+                                    "This directory has no FTDC metrics files.",
+                                );
+                                main_window.update(Update::LoadFailed);
+                            }
                             Err(err) => {
                                 fltk::dialog::alert_default(&format!(
                                     "Error loading FTDC file: {}",
                                     err
                                 ));
+                                main_window.update(Update::LoadFailed);
+                            }
+                            Ok(()) if loaded.timestamps.is_empty() && time_range.is_some() => {
+                                fltk::dialog::alert_default(
+                                    "The FTDC file has no data in the selected time range",
+                                );
+                                main_window.update(Update::LoadFailed);
+                            }
+                            // Such as a file with only the metadata in it
+                            Ok(()) if loaded.timestamps.is_empty() => {
+                                fltk::dialog::alert_default("No metric data found in this file");
+                                main_window.update(Update::LoadFailed);
                             }
                             Ok(()) => {
-                                // TODO: What if empty?
+                                dataset = loaded;
+                                let expanded = dataset.refresh_patterns();
+                                let embedded = match dataset.apply_embedded_descriptors() {
+                                    Ok(changed) => changed,
+                                    Err(err) => {
+                                        fltk::dialog::alert_default(&format!(
+                                            "Error loading the descriptors embedded in the \
+                                             metadata, using the defaults: {}",
+                                            err
+                                        ));
+                                        true
+                                    }
+                                };
                                 main_window.update(Update::DataSetLoaded {
+                                    path,
+                                    sections: (embedded || expanded)
+                                        .then(|| dataset.descriptors.sections().clone()),
                                     start: *dataset.timestamps.first().unwrap(),
                                     end: *dataset.timestamps.last().unwrap(),
                                     transients: dataset.descriptors.transients().clone(),
+                                    booleans: dataset.booleans.clone(),
+                                    warnings: dataset.warnings.clone(),
+                                    gaps: dataset.gaps.clone(),
                                 });
+                                if !dataset.metadata.is_empty() {
+                                    main_window.update(Update::MetadataLoaded(
+                                        dataset.metadata.clone(),
+                                        dataset.format.to_string(),
+                                    ));
+                                }
+                                if dataset.skipped_chunks > 0 {
+                                    main_window.update(Update::ChunksSkipped {
+                                        loaded: dataset.loaded_chunks,
+                                        skipped: dataset.skipped_chunks,
+                                    });
+                                }
                             }
                         }
                     }
@@ -195,18 +1601,79 @@ fn main() {
                                 "Error loading descriptors: {}",
                                 err
                             ));
+                            main_window.update(Update::LoadFailed);
                         }
-                        Ok(()) => main_window.update(Update::DescriptorsLoaded {
+                        Ok(()) => {
+                            let unknown = dataset.descriptors.unknown_conversions();
+                            if !unknown.is_empty() {
+                                fltk::dialog::alert_default(&format!(
+                                    "Unknown unit conversions were ignored:\n{}",
+                                    unknown.join("\n")
+                                ));
+                            }
+                            main_window.update(Update::DescriptorsLoaded {
+                                path: Some(path),
+                                sections: dataset.descriptors.sections().clone(),
+                                transients: dataset.descriptors.transients().clone(),
+                            });
+                        }
+                    },
+                    Message::ResetDescriptors => {
+                        if let Err(err) = dataset.reset_descriptors() {
+                            fltk::dialog::alert_default(&format!(
+                                "Error loading the descriptors embedded in the metadata, using \
+                                 the defaults: {}",
+                                err
+                            ));
+                        }
+                        main_window.update(Update::DescriptorsLoaded {
+                            path: None,
                             sections: dataset.descriptors.sections().clone(),
                             transients: dataset.descriptors.transients().clone(),
-                        }),
-                    },
-                    Message::SampleMetrics(ids, range, num_samples) => {
-                        main_window.update(Update::MetricsSampled(dataset.sample_metrics(
-                            ids,
-                            range,
-                            num_samples,
-                        )));
+                        });
+                    }
+                    Message::ExportDescriptorTemplate(path) => {
+                        if let Err(err) = dataset.export_descriptor_template(&path) {
+                            fltk::dialog::alert_default(&format!(
+                                "Error exporting descriptor template: {}",
+                                err
+                            ));
+                        }
+                    }
+                    #[cfg(feature = "parquet")]
+                    Message::ExportParquet(path, range, num_rows) => {
+                        if let Err(err) = dataset.export_parquet(&path, range, num_rows) {
+                            fltk::dialog::alert_default(&format!(
+                                "Error exporting to Parquet: {}",
+                                err
+                            ));
+                        }
+                    }
+                    Message::ExportStats(path, metrics, range, options) => {
+                        if let Err(err) = dataset.export_stats(&path, metrics, range, options) {
+                            fltk::dialog::alert_default(&format!("Error exporting stats: {}", err));
+                        }
+                    }
+                    Message::FindSpike(ids, after, threshold, options) => {
+                        main_window.update(Update::SpikeFound(
+                            dataset.find_spike(ids, after, threshold, options),
+                        ));
+                    }
+                    Message::InspectRawValues(id, range) => {
+                        main_window.update(Update::RawValues(id, dataset.raw_values(id, range)));
+                    }
+                    Message::SampleMetrics(ids, differences, range, num_samples, options) => {
+                        let differences =
+                            dataset.sample_differences(&differences, range.clone(), num_samples);
+                        let point_counts = dataset.count_points(&ids, &range);
+                        let overlays =
+                            dataset.sample_overlays(&ids, range.clone(), num_samples, options);
+                        main_window.update(Update::MetricsSampled(
+                            dataset.sample_metrics(ids, range, num_samples, options),
+                            differences,
+                            point_counts,
+                            overlays,
+                        ));
                     }
                 }
             }
@@ -214,5 +1681,777 @@ fn main() {
     });
 
     main_window.show();
+
+    // The descriptors go first, so that the data comes up with them rather than being reloaded.
+    if let Some(path) = args.descriptors {
+        main_window.load_descriptors(path);
+    }
+    if let Some(file) = args.file {
+        let zoom = match (args.from, args.to) {
+            (None, None) => None,
+            (from, to) => {
+                Some(from.unwrap_or(Timestamp::MIN_UTC)..=to.unwrap_or(Timestamp::MAX_UTC))
+            }
+        };
+        main_window.open_file(file, zoom);
+    } else {
+        main_window.offer_recovery();
+    }
+
     app.run().unwrap();
+
+    main_window.clear_recovery();
+    main_window.save_config();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use bson::spec::BinarySubtype;
+    use bson::{doc, Bson};
+    use flate2::write::GzEncoder;
+
+    use super::*;
+    use crate::ftdc::fixture::{
+        compress, data_chunk, data_chunk_with, file_bytes, metadata_chunk, payload, sample,
+        temp_path, write_file,
+    };
+
+    fn open(name: &str, chunks: &[Document]) -> DataSet {
+        let path = write_file(name, &file_bytes(chunks));
+        let mut dataset = DataSet::new();
+        dataset.open_ftdc_file(&path, None, None).unwrap();
+        std::fs::remove_file(path).unwrap();
+        dataset
+    }
+
+    fn key(path: &str) -> MetricKey {
+        MetricKey::from(&path.split('.').collect::<Vec<_>>()[..])
+    }
+
+    fn counting_samples(start_millis: i64, values: &[i64]) -> Vec<Document> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(idx, &value)| sample(start_millis + idx as i64 * 1000, doc! { "x": value }))
+            .collect()
+    }
+
+    fn reload(dataset: &DataSet, path: &Path) -> Result<DataSet> {
+        let mut loaded = dataset.for_reload();
+        loaded.open_ftdc_file(path, None, None)?;
+        Ok(loaded)
+    }
+
+    #[test]
+    fn reload_keeps_sections_and_transient_ids() {
+        let mut dataset = DataSet::new();
+        let descriptors =
+            serde_json::from_str(r#"{"Ops": [{"key": ["x"], "name": "X"}]}"#).unwrap();
+        dataset.set_descriptors(descriptors, DescriptorSource::File);
+
+        let samples = |millis, other| sample(millis, doc! { "x": 1i64, other: 2i64 });
+        let path_a = write_file("a.ftdc", &file_bytes(&[data_chunk(&[samples(0, "y")])]));
+        let path_b = write_file("b.ftdc", &file_bytes(&[data_chunk(&[samples(1000, "z")])]));
+        let not_ftdc = write_file("not-ftdc.bson", &bson::to_vec(&doc! { "a": 1 }).unwrap());
+
+        dataset = reload(&dataset, &path_a).unwrap();
+        let transients = dataset.descriptors.transients().clone();
+        assert_eq!(transients.len(), 2);
+
+        // A failed load leaves the data set, and so the IDs the window has, as they were.
+        assert!(matches!(
+            reload(&dataset, &not_ftdc),
+            Err(Error::NotFtdcFile)
+        ));
+        for desc in transients.iter() {
+            assert_eq!(dataset.descriptors[desc.id].key, desc.key);
+            let samples = dataset.sample_metrics(
+                vec![desc.id],
+                dataset.timestamps[0]..=dataset.timestamps[0],
+                10,
+                SampleOptions::default(),
+            );
+            assert_eq!(samples[&desc.id].len(), 1);
+        }
+
+        dataset = reload(&dataset, &path_b).unwrap();
+        let sections = dataset.descriptors.sections();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].name, "Ops");
+        assert_eq!(sections[0].metrics[0].key, key("x"));
+        let mut transient_keys: Vec<_> = dataset
+            .descriptors
+            .transients()
+            .iter()
+            .map(|desc| {
+                assert_eq!(dataset.descriptors[desc.id].key, desc.key);
+                desc.key.clone()
+            })
+            .collect();
+        transient_keys.sort();
+        assert_eq!(transient_keys, [key("start"), key("z")]);
+
+        for path in [path_a, path_b, not_ftdc] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn at(millis: i64) -> Timestamp {
+        metric::unix_millis_to_timestamp(millis)
+    }
+
+    #[test]
+    fn count_points_of_reversed_range_is_zero() {
+        let dataset = open(
+            "count-points.ftdc",
+            &[data_chunk(&counting_samples(0, &[1, 2, 3, 4]))],
+        );
+        let id = dataset
+            .descriptors
+            .transients()
+            .iter()
+            .find(|desc| desc.key == key("x"))
+            .unwrap()
+            .id;
+
+        assert_eq!(dataset.count_points(&[id], &(at(1000)..=at(2000)))[&id], 2);
+        assert_eq!(dataset.count_points(&[id], &(at(2500)..=at(1500)))[&id], 0);
+        assert_eq!(dataset.count_points(&[id], &(at(3000)..=at(0)))[&id], 0);
+    }
+
+    #[test]
+    fn time_range_keeps_only_samples_in_range() {
+        let path = write_file(
+            "time-range.ftdc",
+            &file_bytes(&[
+                data_chunk(&counting_samples(0, &[0, 1, 2])),
+                data_chunk(&counting_samples(3000, &[3, 4, 5])),
+                data_chunk(&counting_samples(6000, &[6, 7, 8])),
+            ]),
+        );
+        let mut dataset = DataSet::new();
+        dataset
+            .open_ftdc_file(&path, Some(&(at(4000)..=at(5000))), None)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(dataset.timestamps, [at(4000), at(5000)]);
+        assert_eq!(dataset.raw_data[&key("x")], [4.0, 5.0]);
+        // Neither of the other chunks reaches into the range, so they weren't decoded at all.
+        assert_eq!(dataset.loaded_chunks, 1);
+    }
+
+    #[test]
+    fn time_range_outside_the_data_loads_nothing() {
+        let path = write_file(
+            "time-range-outside.ftdc",
+            &file_bytes(&[data_chunk(&counting_samples(0, &[0, 1, 2]))]),
+        );
+        let mut dataset = DataSet::new();
+        dataset
+            .open_ftdc_file(&path, Some(&(at(10000)..=at(20000))), None)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert!(dataset.timestamps.is_empty());
+    }
+
+    #[test]
+    fn directory_loads_files_in_order_with_all_metadata() {
+        let dir = temp_path("diagnostic.data");
+        std::fs::create_dir(&dir).unwrap();
+        let files = [
+            ("metrics.2024-01-01T00-00-00Z-00000", 0, "7.0.1", [1, 2]),
+            ("metrics.2024-01-01T00-00-05Z-00000", 5000, "7.0.2", [3, 4]),
+            ("metrics.interim", 10000, "7.0.2", [5, 6]),
+        ];
+        // Listed in an order other than the one they load in
+        for (name, start, version, values) in files.iter().rev() {
+            let chunks = [
+                metadata_chunk(*start, doc! { "buildInfo": { "version": *version } }),
+                data_chunk(&counting_samples(*start, values)),
+            ];
+            std::fs::write(dir.join(name), file_bytes(&chunks)).unwrap();
+        }
+        std::fs::write(dir.join("other.txt"), "not metrics").unwrap();
+
+        let mut dataset = DataSet::new();
+        dataset.open_ftdc_dir(&dir, None, None).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            dataset.timestamps,
+            [at(0), at(1000), at(5000), at(6000), at(10000), at(11000)]
+        );
+        assert_eq!(dataset.raw_data[&key("x")], [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let metadata: Vec<_> = dataset
+            .metadata
+            .iter()
+            .map(|(timestamp, doc)| {
+                let version = doc.get_document("buildInfo").unwrap().get_str("version");
+                (*timestamp, version.unwrap().to_string())
+            })
+            .collect();
+        assert_eq!(
+            metadata,
+            [
+                (at(0), "7.0.1".to_string()),
+                (at(5000), "7.0.2".to_string()),
+                (at(10000), "7.0.2".to_string()),
+            ]
+        );
+        assert_eq!(dataset.format.server_version.as_deref(), Some("7.0.1"));
+    }
+
+    #[test]
+    fn files_far_apart_leave_a_gap() {
+        let dir = temp_path("diagnostic.data");
+        std::fs::create_dir(&dir).unwrap();
+        // The second file follows the first one closely, the third starts a minute later
+        let files = [
+            ("metrics.2024-01-01T00-00-00Z-00000", 0, [1, 2]),
+            ("metrics.2024-01-01T00-00-05Z-00000", 5000, [3, 4]),
+            ("metrics.2024-01-01T00-01-06Z-00000", 66000, [5, 6]),
+        ];
+        for (name, start, values) in &files {
+            let chunks = [data_chunk(&counting_samples(*start, values))];
+            std::fs::write(dir.join(name), file_bytes(&chunks)).unwrap();
+        }
+
+        let mut dataset = DataSet::new();
+        dataset.file_gap_threshold = chrono::Duration::seconds(10);
+        dataset.open_ftdc_dir(&dir, None, None).unwrap();
+        let mut lenient = DataSet::new();
+        lenient.file_gap_threshold = chrono::Duration::seconds(120);
+        lenient.open_ftdc_dir(&dir, None, None).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(dataset.gaps, [at(6000)..=at(66000)]);
+        assert!(matches!(
+            dataset.warnings[..],
+            [ParseWarning::FileGap { start, prev_end }]
+                if start == at(66000) && prev_end == at(6000)
+        ));
+        assert!(lenient.gaps.is_empty());
+        assert!(lenient.warnings.is_empty());
+    }
+
+    #[test]
+    fn gap_threshold_is_kept_across_reloads() {
+        let mut dataset = DataSet::new();
+        dataset.file_gap_threshold = chrono::Duration::seconds(30);
+        assert_eq!(
+            dataset.for_reload().file_gap_threshold,
+            chrono::Duration::seconds(30)
+        );
+    }
+
+    #[test]
+    fn value_lookup_outside_the_data_is_none() {
+        let timestamps = [at(1000), at(2000), at(4000)];
+        let values = [10.0, 20.0, 40.0];
+        for mode in [
+            LookupMode::Nearest,
+            LookupMode::Interpolate,
+            LookupMode::Previous,
+        ] {
+            let lookup =
+                |millis| lookup_value(&timestamps, at(millis), mode, |t| *t, |idx| values[idx]);
+            assert_eq!(lookup(999), None);
+            assert_eq!(lookup(4001), None);
+            assert_eq!(lookup(1000), Some(10.0));
+            assert_eq!(lookup(4000), Some(40.0));
+            assert_eq!(lookup(2000), Some(20.0));
+        }
+        assert_eq!(
+            lookup_value(&[], at(0), LookupMode::Nearest, |t| *t, |_| 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn value_lookup_between_points_follows_the_mode() {
+        let timestamps = [at(1000), at(2000), at(4000)];
+        let values = [10.0, 20.0, 40.0];
+        let lookup =
+            |millis, mode| lookup_value(&timestamps, at(millis), mode, |t| *t, |idx| values[idx]);
+
+        assert_eq!(lookup(2500, LookupMode::Nearest), Some(20.0));
+        assert_eq!(lookup(3500, LookupMode::Nearest), Some(40.0));
+        assert_eq!(lookup(3000, LookupMode::Interpolate), Some(30.0));
+        assert_eq!(lookup(3500, LookupMode::Previous), Some(20.0));
+    }
+
+    #[test]
+    fn value_lookup_skips_gaps_only_when_it_can() {
+        let timestamps = [at(1000), at(2000), at(4000)];
+        let values = [10.0, f64::NAN, 40.0];
+        let lookup =
+            |millis, mode| lookup_value(&timestamps, at(millis), mode, |t| *t, |idx| values[idx]);
+
+        assert_eq!(lookup(2000, LookupMode::Nearest), None);
+        assert_eq!(lookup(2100, LookupMode::Nearest), Some(40.0));
+        assert_eq!(lookup(3000, LookupMode::Interpolate), None);
+        assert_eq!(lookup(3000, LookupMode::Previous), None);
+    }
+
+    #[test]
+    fn value_lookup_uses_the_data_set_timestamps() {
+        let dataset = open(
+            "value-at.ftdc",
+            &[data_chunk(&counting_samples(0, &[5, 7, 9]))],
+        );
+        let values = &dataset.raw_data[&key("x")];
+
+        let value_at = |millis, mode| dataset.value_at(at(millis), mode, |idx| values[idx]);
+        assert_eq!(value_at(1000, LookupMode::Nearest), Some(7.0));
+        assert_eq!(value_at(1500, LookupMode::Interpolate), Some(8.0));
+        assert_eq!(value_at(2500, LookupMode::Previous), None);
+        assert_eq!(value_at(-1, LookupMode::Previous), None);
+    }
+
+    #[test]
+    fn histogram_charts_the_percentile_of_its_buckets() {
+        let counts = [[2, 2, 0], [0, 4, 0], [0, 0, 0]];
+        let samples: Vec<_> = counts
+            .iter()
+            .enumerate()
+            .map(|(idx, [low, mid, high])| {
+                let fields = doc! { "latency": { "0": low, "1": mid, "2": high } };
+                sample(idx as i64 * 1000, fields)
+            })
+            .collect();
+        let dataset = open("histogram.ftdc", &[data_chunk(&samples)]);
+        let mut desc = Descriptor::default_for_key(key("latency"), DEFAULT_NAME_SEPARATOR);
+        desc.histogram = Some(Histogram {
+            bounds: vec![10.0, 20.0, 30.0],
+            percentile: 50.0,
+            cumulative: false,
+        });
+
+        let values = dataset.metric_values(&desc).unwrap();
+        assert_eq!(values[..2], [10.0, 15.0]);
+        assert!(values[2].is_nan());
+
+        desc.key = key("missing");
+        assert!(dataset.metric_values(&desc).is_none());
+    }
+
+    #[test]
+    fn downsampled_points_are_evenly_spaced_up_to_the_end() {
+        let values: Vec<_> = (0..100).collect();
+        let dataset = open("uniform.ftdc", &[data_chunk(&counting_samples(0, &values))]);
+        let desc = Descriptor::default_for_key(key("x"), DEFAULT_NAME_SEPARATOR);
+        let samples = dataset.sample_values(
+            &dataset.raw_data[&key("x")],
+            &desc,
+            &key("x"),
+            &(at(0)..=at(99000)),
+            10,
+            SampleOptions::default(),
+        );
+
+        assert_eq!(samples.first(), Some(&(at(0), 0.0)));
+        assert_eq!(samples.last(), Some(&(at(99000), 99.0)));
+        for pair in samples.windows(2) {
+            let spacing = (pair[1].0 - pair[0].0).num_milliseconds();
+            assert!((9000..=11000).contains(&spacing), "{:?}", samples);
+        }
+    }
+
+    #[test]
+    fn fewer_points_than_samples_are_all_kept() {
+        let values: Vec<_> = (0..10).collect();
+        let dataset = open(
+            "zoomed-in.ftdc",
+            &[data_chunk(&counting_samples(0, &values))],
+        );
+        let desc = Descriptor::default_for_key(key("x"), DEFAULT_NAME_SEPARATOR);
+        let sample = |range| {
+            dataset.sample_values(
+                &dataset.raw_data[&key("x")],
+                &desc,
+                &key("x"),
+                &range,
+                1000,
+                SampleOptions::default(),
+            )
+        };
+
+        let samples = sample(at(2500)..=at(6000));
+        assert_eq!(
+            samples,
+            [
+                (at(3000), 3.0),
+                (at(4000), 4.0),
+                (at(5000), 5.0),
+                (at(6000), 6.0)
+            ]
+        );
+        assert_eq!(sample(at(0)..=at(9000)).len(), values.len());
+    }
+
+    #[test]
+    fn zero_span_range_shows_the_value_at_that_time() {
+        let dataset = open(
+            "zero-span.ftdc",
+            &[data_chunk(&counting_samples(0, &[5, 7, 9]))],
+        );
+        let desc = Descriptor::default_for_key(key("x"), DEFAULT_NAME_SEPARATOR);
+        let sample = |millis| {
+            dataset.sample_values(
+                &dataset.raw_data[&key("x")],
+                &desc,
+                &key("x"),
+                &(at(millis)..=at(millis)),
+                100,
+                SampleOptions::default(),
+            )
+        };
+
+        assert_eq!(sample(1000), [(at(1000), 7.0)]);
+        assert_eq!(sample(1500), [(at(1500), 7.0)]);
+        assert_eq!(sample(2000), [(at(2000), 9.0)]);
+        assert_eq!(sample(-500), []);
+        assert_eq!(sample(2500), []);
+    }
+
+    #[test]
+    fn descriptors_embedded_in_the_metadata_are_applied() {
+        let embedded = doc! {
+            "r2t2Descriptors": { "Counting": [{ "key": ["x"], "name": "Count" }] },
+        };
+        let described_chunks = [
+            metadata_chunk(0, embedded),
+            data_chunk(&counting_samples(0, &[1, 2])),
+        ];
+        let path = write_file("described.ftdc", &file_bytes(&described_chunks));
+        let plain_path = write_file(
+            "plain.ftdc",
+            &file_bytes(&[data_chunk(&counting_samples(0, &[1, 2]))]),
+        );
+
+        let mut described = DataSet::new();
+        described.open_ftdc_file(&path, None, None).unwrap();
+        assert!(described.apply_embedded_descriptors().unwrap());
+        assert_eq!(described.descriptor_source, DescriptorSource::Embedded);
+        let sections = described.descriptors.sections();
+        assert_eq!(sections[0].name, "Counting");
+        assert_eq!(sections[0].metrics[0].name, "Count");
+
+        // Embedded descriptors only go with their own data.
+        let mut plain = reload(&described, &plain_path).unwrap();
+        assert!(plain.apply_embedded_descriptors().unwrap());
+        assert_eq!(plain.descriptor_source, DescriptorSource::Default);
+        assert!(plain.descriptors.sections().is_empty());
+
+        // Nor do they replace descriptors loaded from a file.
+        let mut from_file = DataSet::new();
+        from_file.descriptor_source = DescriptorSource::File;
+        from_file.open_ftdc_file(&path, None, None).unwrap();
+        assert!(!from_file.apply_embedded_descriptors().unwrap());
+        assert_eq!(from_file.descriptor_source, DescriptorSource::File);
+
+        // Resetting the descriptors brings them back.
+        from_file.reset_descriptors().unwrap();
+        assert_eq!(from_file.descriptor_source, DescriptorSource::Embedded);
+        assert_eq!(from_file.descriptors.sections()[0].name, "Counting");
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(plain_path).unwrap();
+    }
+
+    #[test]
+    fn gzipped_file_loads_the_same_as_the_original() {
+        let chunks = [
+            metadata_chunk(0, doc! { "buildInfo": { "version": "7.0.1" } }),
+            data_chunk(&counting_samples(0, &[1, 2, 3])),
+            data_chunk(&counting_samples(3000, &[5, 8, 13])),
+        ];
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&file_bytes(&chunks)).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let original = open("original.ftdc", &chunks);
+        let path = write_file("metrics.gz", &gzipped);
+        let mut unzipped = DataSet::new();
+        unzipped.open_ftdc_file(&path, None, None).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(unzipped.timestamps, original.timestamps);
+        assert_eq!(unzipped.raw_data, original.raw_data);
+        assert_eq!(unzipped.metadata.len(), 1);
+        assert_eq!(unzipped.skipped_chunks, 0);
+    }
+
+    #[test]
+    fn ranges_outside_the_data_sample_nothing() {
+        let values: Vec<_> = (0..10).collect();
+        let dataset = open(
+            "outside.ftdc",
+            &[data_chunk(&counting_samples(10000, &values))],
+        );
+        let desc = Descriptor::default_for_key(key("x"), DEFAULT_NAME_SEPARATOR);
+        let sample = |range, num_samples| {
+            dataset.sample_values(
+                &dataset.raw_data[&key("x")],
+                &desc,
+                &key("x"),
+                &range,
+                num_samples,
+                SampleOptions::default(),
+            )
+        };
+
+        for num_samples in [1, 4, 100] {
+            assert_eq!(sample(at(0)..=at(9999), num_samples), []);
+            assert_eq!(sample(at(19001)..=at(30000), num_samples), []);
+            assert_eq!(sample(at(15000)..=at(12000), num_samples), []);
+        }
+    }
+
+    #[test]
+    fn single_sample_is_kept_by_any_range_that_covers_it() {
+        let dataset = open("single.ftdc", &[data_chunk(&counting_samples(5000, &[42]))]);
+        let desc = Descriptor::default_for_key(key("x"), DEFAULT_NAME_SEPARATOR);
+        let sample = |range, num_samples| {
+            dataset.sample_values(
+                &dataset.raw_data[&key("x")],
+                &desc,
+                &key("x"),
+                &range,
+                num_samples,
+                SampleOptions::default(),
+            )
+        };
+
+        assert_eq!(sample(at(5000)..=at(5000), 100), [(at(5000), 42.0)]);
+        assert_eq!(sample(at(0)..=at(10000), 1), [(at(5000), 42.0)]);
+        assert_eq!(sample(at(0)..=at(10000), 100), [(at(5000), 42.0)]);
+        assert_eq!(sample(at(0)..=at(4999), 100), []);
+    }
+
+    #[test]
+    fn every_metadata_document_is_kept_with_its_time() {
+        let dataset = open(
+            "restarted.ftdc",
+            &[
+                metadata_chunk(0, doc! { "buildInfo": { "version": "7.0.1" } }),
+                data_chunk(&counting_samples(0, &[1, 2])),
+                metadata_chunk(5000, doc! { "buildInfo": { "version": "7.0.2" } }),
+                data_chunk(&counting_samples(5000, &[3, 4])),
+            ],
+        );
+
+        let versions: Vec<_> = dataset
+            .metadata
+            .iter()
+            .map(|(timestamp, doc)| {
+                let version = doc.get_document("buildInfo").unwrap().get_str("version");
+                (*timestamp, version.unwrap())
+            })
+            .collect();
+        assert_eq!(versions, [(at(0), "7.0.1"), (at(5000), "7.0.2")]);
+        assert_eq!(dataset.primary_metadata(), Some(&dataset.metadata[0].1));
+        // The primary metadata is the one the version comes from.
+        assert_eq!(dataset.format.server_version.as_deref(), Some("7.0.1"));
+    }
+
+    #[test]
+    fn plain_bson_is_not_ftdc() {
+        for doc in [doc! { "name": "x", "value": 1 }, doc! { "type": "metrics" }] {
+            let path = write_file("plain.bson", &bson::to_vec(&doc).unwrap());
+            let result = DataSet::new().open_ftdc_file(&path, None, None);
+            std::fs::remove_file(path).unwrap();
+            assert!(matches!(result, Err(Error::NotFtdcFile)), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn metadata_only_file_loads_without_data() {
+        let dataset = open(
+            "metadata-only.ftdc",
+            &[metadata_chunk(
+                0,
+                doc! { "buildInfo": { "version": "7.0.2" } },
+            )],
+        );
+
+        assert!(dataset.timestamps.is_empty());
+        assert!(dataset.raw_data.is_empty());
+        assert_eq!(dataset.metadata.len(), 1);
+    }
+
+    #[test]
+    fn null_metric_is_a_gap_in_its_chunk() {
+        let chunk = |start: i64, y: Bson| -> Vec<Document> {
+            (0..2)
+                .map(|idx| {
+                    let x = start / 1000 + idx;
+                    let y = match &y {
+                        Bson::Int64(y) => Bson::Int64(y + idx),
+                        y => y.clone(),
+                    };
+                    sample(start + idx * 1000, doc! { "x": x, "y": y })
+                })
+                .collect()
+        };
+        let truncated = chunk(4000, Bson::Int64(4));
+        let mut truncated_payload = payload(&truncated);
+        truncated_payload.truncate(truncated_payload.len() - 2);
+        let dataset = open(
+            "null-metric.ftdc",
+            &[
+                data_chunk(&chunk(0, Bson::Int64(0))),
+                data_chunk(&chunk(2000, Bson::Null)),
+                data_chunk_with(
+                    &truncated,
+                    compress(&truncated_payload),
+                    BinarySubtype::Generic,
+                ),
+                data_chunk(&chunk(6000, Bson::Int64(6))),
+            ],
+        );
+
+        assert_eq!(dataset.skipped_chunks, 1);
+        assert_eq!(dataset.raw_data[&key("x")], [0.0, 1.0, 2.0, 3.0, 6.0, 7.0]);
+        let y = &dataset.raw_data[&key("y")];
+        assert_eq!(y[..2], [0.0, 1.0]);
+        assert!(y[2].is_nan() && y[3].is_nan());
+        assert_eq!(y[4..], [6.0, 7.0]);
+        assert!(dataset.warnings.iter().any(
+            |warning| matches!(warning, ParseWarning::NullMetric { key: null } if *null == key("y"))
+        ));
+    }
+
+    #[test]
+    fn chunk_inspection_sees_and_changes_every_chunk() {
+        let path = write_file(
+            "inspect.ftdc",
+            &file_bytes(&[
+                data_chunk(&counting_samples(0, &[1, 2, 3])),
+                data_chunk(&counting_samples(3000, &[4, 5, 6])),
+            ]),
+        );
+        let mut sums = vec![];
+        let mut dataset = DataSet::new();
+        dataset
+            .open_ftdc_file_with(&path, None, |chunk| {
+                let values = chunk.metrics.get_mut(&key("x")).unwrap();
+                sums.push(values.iter().sum::<i64>());
+                values.iter_mut().for_each(|value| *value *= 10);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        let mut first_only = DataSet::new();
+        first_only
+            .open_ftdc_file_with(&path, None, |chunk| match chunk.timestamps[0] {
+                timestamp if timestamp == at(0) => ControlFlow::Continue(()),
+                _ => ControlFlow::Break(()),
+            })
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(sums, [6, 15]);
+        assert_eq!(
+            dataset.raw_data[&key("x")],
+            [10.0, 20.0, 30.0, 40.0, 50.0, 60.0]
+        );
+        assert_eq!(first_only.timestamps, [at(0), at(1000), at(2000)]);
+        assert_eq!(first_only.raw_data[&key("x")], [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn descriptors_file_format_goes_by_extension() {
+        let yaml_text = b"Memory:\n  - key: [mem, resident]\n    name: RSS\n";
+        let yaml = write_file("descriptors.YML", yaml_text);
+        let yaml_as_json = write_file("yaml-descriptors.json", yaml_text);
+        let json = write_file(
+            "descriptors.json",
+            br#"{ "Memory": [{ "key": ["mem", "resident"], "name": "RSS" }] }"#,
+        );
+        let mut from_yaml = DataSet::new();
+        let yaml_result = from_yaml.load_descriptors(&yaml);
+        let mut from_json = DataSet::new();
+        let json_result = from_json.load_descriptors(&json);
+        let mut misnamed = DataSet::new();
+        let misnamed_result = misnamed.load_descriptors(&yaml_as_json);
+        for path in [yaml, json, yaml_as_json] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        yaml_result.unwrap();
+        json_result.unwrap();
+        assert!(misnamed_result.is_err());
+        for dataset in [from_yaml, from_json] {
+            assert_eq!(dataset.descriptor_source, DescriptorSource::File);
+            let sections = dataset.descriptors.sections();
+            assert_eq!(sections[0].metrics[0].key, key("mem.resident"));
+        }
+    }
+
+    #[test]
+    fn version_mismatch_skips_only_that_chunk() {
+        let mismatched = counting_samples(3000, &[4, 7, 9]);
+        let mut mismatched_payload = payload(&mismatched);
+        mismatched_payload.pop();
+        let dataset = open(
+            "version-mismatch.ftdc",
+            &[
+                data_chunk(&counting_samples(0, &[1, 2, 3])),
+                data_chunk_with(
+                    &mismatched,
+                    compress(&mismatched_payload),
+                    BinarySubtype::Generic,
+                ),
+                data_chunk(&counting_samples(6000, &[10, 11, 12])),
+            ],
+        );
+
+        assert_eq!(dataset.loaded_chunks, 2);
+        assert_eq!(dataset.skipped_chunks, 1);
+        assert_eq!(
+            dataset.raw_data[&key("x")],
+            [1.0, 2.0, 3.0, 10.0, 11.0, 12.0]
+        );
+    }
+
+    #[test]
+    fn corrupt_compressed_data_skips_only_that_chunk() {
+        let mut garbage = 64u32.to_le_bytes().to_vec();
+        garbage.extend_from_slice(b"not a zlib stream");
+        let dataset = open(
+            "corrupt-data.ftdc",
+            &[
+                data_chunk(&counting_samples(0, &[1, 2, 3])),
+                data_chunk_with(
+                    &counting_samples(3000, &[4, 5, 6]),
+                    garbage,
+                    BinarySubtype::Generic,
+                ),
+                data_chunk(&counting_samples(6000, &[7, 8, 9])),
+            ],
+        );
+
+        assert_eq!(dataset.loaded_chunks, 2);
+        assert_eq!(dataset.skipped_chunks, 1);
+        assert_eq!(dataset.raw_data[&key("x")], [1.0, 2.0, 3.0, 7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn junk_at_end_of_file_is_skipped() {
+        let mut bytes = file_bytes(&[data_chunk(&counting_samples(0, &[1, 2, 3]))]);
+        bytes.extend_from_slice(&[1, 0, 0, 0, 0xde, 0xad, 0xbe, 0xef]);
+        let path = write_file("junk-tail.ftdc", &bytes);
+        let mut dataset = DataSet::new();
+        let result = dataset.open_ftdc_file(&path, None, None);
+        std::fs::remove_file(path).unwrap();
+
+        result.unwrap();
+        assert_eq!(dataset.loaded_chunks, 1);
+        assert_eq!(dataset.skipped_chunks, 1);
+        assert_eq!(dataset.raw_data[&key("x")], [1.0, 2.0, 3.0]);
+    }
 }
@@ -1,28 +1,93 @@
-use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::ops::Index;
 use std::rc::Rc;
 
 use serde::de::{DeserializeSeed, MapAccess, SeqAccess, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
+mod histogram;
 mod key;
 mod time;
+mod unit;
 
+pub use self::histogram::Histogram;
 pub use self::key::MetricKey;
-pub use self::time::{unix_millis_to_timestamp, Timestamp, TimestampFormat};
+pub use self::time::{unix_millis_to_timestamp, DisplayZone, Timestamp, TimestampFormat};
+use self::unit::resolve_conversion;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Descriptor {
     #[serde(skip)]
     pub id: usize,
 
+    // Empty when the descriptor has a pattern instead
+    #[serde(
+        default = "MetricKey::new",
+        skip_serializing_if = "MetricKey::is_empty"
+    )]
     pub key: MetricKey,
     pub name: String,
 
+    // Stands for a descriptor for each metric whose key matches, with `*` in an element matching
+    // any run of characters in it. Each `{}` in the name is replaced by the next element that a
+    // wildcard matched, and without any, they are appended to the name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<MetricKey>,
+
     #[serde(default = "default_scale")]
     pub scale: f64,
+
+    // Subtracted after dividing by the scale, so it is in the units the chart shows: a value is
+    // shown as `value / scale - offset`. Differences between values, like counter deltas, leave it
+    // out, since it cancels out in them.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: f64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    // A conversion between known units, like "bytes->MiB", which sets the scale and the unit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convert: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Histogram>,
+
+    // Bands of the chart's background by value, each from its boundary up to that of the next one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<Zone>,
+
+    // More metrics drawn as lines over the same chart, in the same units as the main one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlay: Vec<MetricKey>,
+
+    // Whether the chart starts out with a logarithmic value axis
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log_scale: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+}
+
+// What is charted instead of the values of the metric themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    // The change per second since the previous point, for counters
+    Rate,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Zone {
+    // In the units the chart shows, so after scaling.
+    pub from: f64,
+    // Like "#ffe0e0"
+    pub color: String,
 }
 
 #[derive(Debug, Clone)]
@@ -31,33 +96,119 @@ pub struct Section {
     pub metrics: Vec<Rc<Descriptor>>,
 }
 
+#[derive(Clone)]
 pub struct Descriptors {
     by_id: Vec<Rc<Descriptor>>,
     by_key: HashMap<MetricKey, Vec<Rc<Descriptor>>>,
     sections: Vec<Section>,
     transients: Vec<Rc<Descriptor>>,
+    unknown_conversions: Vec<String>,
+    histogram_prefixes: Vec<MetricKey>,
+}
+
+#[derive(Debug, Error)]
+pub enum DescriptorError {
+    #[error("descriptor {descriptor} in section \"{section}\" has an empty key")]
+    EmptyKey { section: String, descriptor: String },
+    #[error("descriptor {descriptor} in section \"{section}\" has both a key and a pattern")]
+    KeyAndPattern { section: String, descriptor: String },
 }
 
+// What joins the elements of a key in generated names, unless configured otherwise.
+pub const DEFAULT_NAME_SEPARATOR: &str = " ";
+// What joins the elements of a key wherever the key itself is shown, copied or exported, which is
+// how the keys are written in the mongo shell.
+pub const KEY_PATH_SEPARATOR: &str = ".";
+
 pub struct SectionBuilder<'o> {
     owner: &'o mut Descriptors,
     idx: usize,
 }
 
 impl Descriptor {
-    pub fn default_for_key(key: MetricKey) -> Self {
-        let key_str: &str = key.borrow();
-        let mut name = String::with_capacity(key_str.len());
-        let mut first = true;
-        for elem in key.iter() {
-            if first {
-                first = false;
-            } else {
-                name.push(' ');
+    // The name is the elements of the key, joined with `separator`.
+    pub fn default_for_key(key: MetricKey, separator: &str) -> Self {
+        let name = key.join(separator);
+
+        Self {
+            id: usize::MAX,
+            key,
+            name,
+            pattern: None,
+            scale: 1.0,
+            offset: 0.0,
+            decimals: None,
+            unit: None,
+            convert: None,
+            histogram: None,
+            zones: Vec::new(),
+            overlay: Vec::new(),
+            log_scale: false,
+            transform: None,
+        }
+    }
+
+    // The descriptor for a metric that the pattern matches, if it does.
+    pub fn for_match(&self, key: &MetricKey, separator: &str) -> Option<Self> {
+        let pattern = self.pattern.as_ref()?;
+        if pattern.len() != key.len() {
+            return None;
+        }
+
+        let mut matched = Vec::new();
+        for (pattern_elem, elem) in pattern.iter().zip(key.iter()) {
+            if !glob_matches(pattern_elem, elem) {
+                return None;
+            }
+            if pattern_elem.contains('*') {
+                matched.push(elem);
             }
-            name.push_str(elem);
         }
 
-        Self { id: usize::MAX, key, name, scale: 1.0 }
+        let name = if self.name.is_empty() {
+            key.join(separator)
+        } else if self.name.contains("{}") {
+            let mut parts = self.name.split("{}");
+            let mut name = parts.next().unwrap_or_default().to_string();
+            let mut matched = matched.iter();
+            for part in parts {
+                name.push_str(matched.next().copied().unwrap_or_default());
+                name.push_str(part);
+            }
+            name
+        } else if matched.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}{}{}", self.name, separator, matched.join(separator))
+        };
+
+        Some(Self {
+            key: key.clone(),
+            name,
+            pattern: None,
+            ..self.clone()
+        })
+    }
+
+    pub fn scaled(&self, value: f64) -> f64 {
+        value / self.scale - self.offset
+    }
+
+    // An unknown conversion leaves the values as they are, rather than guessing at a scale.
+    fn apply_conversion(&mut self) -> bool {
+        let spec = match self.convert.as_ref() {
+            Some(spec) => spec,
+            None => return true,
+        };
+        let (scale, unit) = match resolve_conversion(spec) {
+            Some(conversion) => conversion,
+            None => return false,
+        };
+        self.scale = scale;
+        if self.unit.is_none() {
+            self.unit = Some(unit.to_string());
+        }
+        true
     }
 }
 
@@ -65,6 +216,32 @@ fn default_scale() -> f64 {
     1.0
 }
 
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+// `*` matches any run of characters, including none.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts: Vec<_> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 impl Descriptors {
     pub fn new() -> Self {
         Self {
@@ -72,6 +249,8 @@ impl Descriptors {
             by_key: HashMap::new(),
             sections: Vec::new(),
             transients: Vec::new(),
+            unknown_conversions: Vec::new(),
+            histogram_prefixes: Vec::new(),
         }
     }
 
@@ -86,10 +265,62 @@ impl Descriptors {
         self.transients.push(desc);
     }
 
+    pub fn clear_transients(&mut self) {
+        for desc in self.transients.drain(..) {
+            if let Some(descs) = self.by_key.get_mut(&desc.key) {
+                descs.retain(|other| other.id != desc.id);
+                if descs.is_empty() {
+                    self.by_key.remove(&desc.key);
+                }
+            }
+        }
+
+        // Sections are always populated before any transients are added, so the transients occupy
+        // the tail of `by_id`, and removing them leaves the IDs of section descriptors intact.
+        let num_section_metrics = self.sections.iter().map(|s| s.metrics.len()).sum();
+        self.by_id.truncate(num_section_metrics);
+    }
+
     pub fn contains_key(&self, key: &MetricKey) -> bool {
         self.by_key.contains_key(key)
     }
 
+    /// Whether some descriptor charts the metric, either by its key or as a bucket of a histogram.
+    pub fn covers_key(&self, key: &MetricKey) -> bool {
+        self.contains_key(key)
+            || self
+                .histogram_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+    }
+
+    pub fn has_patterns(&self) -> bool {
+        self.by_id.iter().any(|desc| desc.pattern.is_some())
+    }
+
+    /// A copy of the section descriptors, with each one that has a pattern replaced by one for each
+    /// of `keys` that it matches, in the order of `keys`. A key that has a descriptor of its own
+    /// isn't matched.
+    pub fn expanded(&self, keys: &[MetricKey], separator: &str) -> Self {
+        let mut result = Self::new();
+        result.unknown_conversions = self.unknown_conversions.clone();
+        for section in self.sections.iter() {
+            let mut builder = result.begin_section(section.name.clone());
+            for desc in section.metrics.iter() {
+                if desc.pattern.is_none() {
+                    builder.add(Descriptor::clone(desc));
+                    continue;
+                }
+                for key in keys.iter().filter(|key| !self.by_key.contains_key(*key)) {
+                    if let Some(desc) = desc.for_match(key, separator) {
+                        builder.add(desc);
+                    }
+                }
+            }
+        }
+        result
+    }
+
     pub fn sections(&self) -> &Vec<Section> {
         &self.sections
     }
@@ -98,10 +329,20 @@ impl Descriptors {
         &self.transients
     }
 
+    /// The conversions that descriptors asked for but that aren't known, each with the name of the
+    /// descriptor.
+    pub fn unknown_conversions(&self) -> &[String] {
+        &self.unknown_conversions
+    }
+
     fn add_descriptor(&mut self, mut desc: Descriptor) -> Rc<Descriptor> {
         desc.id = self.by_id.len();
         let desc = Rc::new(desc);
 
+        if desc.histogram.is_some() {
+            self.histogram_prefixes.push(desc.key.clone());
+        }
+
         self.by_id.push(Rc::clone(&desc));
         self.by_key
             .entry(desc.key.clone())
@@ -145,8 +386,33 @@ impl<'de> Deserialize<'de> for Descriptors {
             }
 
             fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Self::Value, A::Error> {
-                while let Some(desc) = seq.next_element()? {
+                let mut idx = 0;
+                while let Some(mut desc) = seq.next_element::<Descriptor>()? {
+                    let has_pattern = desc.pattern.as_ref().is_some_and(|p| !p.is_empty());
+                    if desc.key.is_empty() != has_pattern {
+                        let section = self.section.name().to_string();
+                        let descriptor = if desc.name.is_empty() {
+                            format!("#{}", idx + 1)
+                        } else {
+                            format!("\"{}\"", desc.name)
+                        };
+                        let err = if has_pattern {
+                            DescriptorError::KeyAndPattern { section, descriptor }
+                        } else {
+                            DescriptorError::EmptyKey { section, descriptor }
+                        };
+                        return Err(serde::de::Error::custom(err));
+                    }
+                    if !desc.apply_conversion() {
+                        let message = format!(
+                            "\"{}\" in descriptor \"{}\"",
+                            desc.convert.as_deref().unwrap_or_default(),
+                            desc.name,
+                        );
+                        self.section.owner.unknown_conversions.push(message);
+                    }
                     self.section.add(desc);
+                    idx += 1;
                 }
 
                 Ok(self)
@@ -176,9 +442,203 @@ impl<'de> Deserialize<'de> for Descriptors {
     }
 }
 
+// In the same shape as they are read, so only the sections, and not the transients, are written.
+impl Serialize for Descriptors {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct SectionMetrics<'s>(&'s [Rc<Descriptor>]);
+
+        impl<'s> Serialize for SectionMetrics<'s> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter().map(|desc| desc.as_ref()))
+            }
+        }
+
+        serializer.collect_map(
+            self.sections
+                .iter()
+                .map(|section| (&section.name, SectionMetrics(&section.metrics))),
+        )
+    }
+}
+
 impl<'o> SectionBuilder<'o> {
+    pub fn name(&self) -> &str {
+        &self.owner.sections[self.idx].name
+    }
+
     pub fn add(&mut self, desc: Descriptor) {
         let desc = self.owner.add_descriptor(desc);
         self.owner.sections[self.idx].metrics.push(desc);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_DESCRIPTORS: &str = r#"{
+        "Connections": [
+            { "key": ["serverStatus", "connections", "current"], "name": "Current" },
+            {
+                "key": ["serverStatus", "mem", "resident"],
+                "name": "Resident",
+                "convert": "MiB->GiB",
+                "decimals": 1
+            }
+        ],
+        "Operations": [
+            {
+                "pattern": ["serverStatus", "opcounters", "*"],
+                "name": "{} ops",
+                "transform": "rate"
+            }
+        ]
+    }"#;
+
+    const YAML_DESCRIPTORS: &str = r#"
+Connections:
+  - key: [serverStatus, connections, current]
+    name: Current
+  # Charted in GiB, even though the server reports MiB
+  - key: [serverStatus, mem, resident]
+    name: Resident
+    convert: MiB->GiB
+    decimals: 1
+Operations:
+  - pattern: [serverStatus, opcounters, "*"]
+    name: "{} ops"
+    transform: rate
+"#;
+
+    #[test]
+    fn yaml_descriptors_are_the_same_as_json() {
+        let json: Descriptors = serde_json::from_str(JSON_DESCRIPTORS).unwrap();
+        let yaml: Descriptors = serde_yaml::from_str(YAML_DESCRIPTORS).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&yaml).unwrap(),
+            serde_json::to_value(&json).unwrap()
+        );
+        let names = |descriptors: &Descriptors| -> Vec<String> {
+            descriptors
+                .sections()
+                .iter()
+                .map(|section| section.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&yaml), ["Connections", "Operations"]);
+        assert_eq!(names(&json), names(&yaml));
+        assert_eq!(yaml.sections()[0].metrics[1].scale, 1024.0);
+    }
+
+    #[test]
+    fn empty_key_names_the_descriptor_and_section() {
+        let named = r#"{
+            "Memory": [
+                { "key": ["mem", "resident"], "name": "RSS" },
+                { "key": [], "name": "Virtual" }
+            ]
+        }"#;
+        let unnamed =
+            r#"{ "Memory": [{ "key": ["mem", "resident"], "name": "" }, { "name": "" }] }"#;
+
+        let err = serde_json::from_str::<Descriptors>(named).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with(r#"descriptor "Virtual" in section "Memory" has an empty key"#),
+            "{}",
+            err
+        );
+        let err = serde_json::from_str::<Descriptors>(unnamed).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with(r#"descriptor #2 in section "Memory" has an empty key"#),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn key_and_pattern_together_are_rejected() {
+        let both = r#"{ "Ops": [{ "key": ["ops"], "pattern": ["ops", "*"], "name": "Ops" }] }"#;
+
+        let err = serde_json::from_str::<Descriptors>(both).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with(r#"descriptor "Ops" in section "Ops" has both a key and a pattern"#),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn conversion_sets_the_scale_and_unknown_one_is_reported() {
+        let text = r#"{
+            "Memory": [
+                { "key": ["mem", "resident"], "name": "RSS", "convert": "bytes->MiB" },
+                {
+                    "key": ["mem", "virtual"],
+                    "name": "Virtual",
+                    "convert": "bytes->MiB",
+                    "unit": "M"
+                },
+                {
+                    "key": ["mem", "mapped"],
+                    "name": "Mapped",
+                    "convert": "bytes->parsecs",
+                    "scale": 2
+                }
+            ]
+        }"#;
+
+        let descriptors: Descriptors = serde_json::from_str(text).unwrap();
+        let metrics = &descriptors.sections()[0].metrics;
+        assert_eq!(metrics[0].scale, 1024.0 * 1024.0);
+        assert_eq!(metrics[0].unit.as_deref(), Some("MiB"));
+        assert_eq!(metrics[1].unit.as_deref(), Some("M"));
+        assert_eq!(metrics[2].scale, 2.0);
+        assert_eq!(metrics[2].unit, None);
+        assert_eq!(
+            descriptors.unknown_conversions(),
+            [r#""bytes->parsecs" in descriptor "Mapped""#]
+        );
+    }
+
+    #[test]
+    fn default_names_join_the_key_with_the_separator() {
+        let key = MetricKey::from(&["serverStatus", "connections", "current"][..]);
+
+        for (separator, name) in [
+            (DEFAULT_NAME_SEPARATOR, "serverStatus connections current"),
+            ("/", "serverStatus/connections/current"),
+            (" > ", "serverStatus > connections > current"),
+        ] {
+            let desc = Descriptor::default_for_key(key.clone(), separator);
+            assert_eq!(desc.name, name);
+            assert_eq!(desc.key, key);
+        }
+    }
+
+    #[test]
+    fn serialized_descriptors_read_back_the_same() {
+        let descriptors: Descriptors = serde_json::from_str(JSON_DESCRIPTORS).unwrap();
+        let text = serde_json::to_string(&descriptors).unwrap();
+        let read_back: Descriptors = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&read_back).unwrap(),
+            serde_json::to_value(&descriptors).unwrap()
+        );
+        // The sections are written in order, even though a JSON object doesn't have one.
+        assert!(text.find("Connections").unwrap() < text.find("Operations").unwrap());
+        let sections = read_back.sections();
+        assert_eq!(sections.len(), 2);
+        let resident = &sections[0].metrics[1];
+        assert_eq!(
+            resident.key,
+            MetricKey::from(&["serverStatus", "mem", "resident"][..])
+        );
+        assert_eq!(resident.name, "Resident");
+        assert_eq!(resident.scale, 1024.0);
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+// A metric whose key is the prefix of an array of bucket counts, charted as a percentile of the
+// distribution in each sample rather than as the buckets themselves.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Histogram {
+    // The upper bound of each bucket, in ascending order. The first bucket starts at zero, and each
+    // of the others at the bound of the one before it. Counts past the last bound are ignored.
+    pub bounds: Vec<f64>,
+
+    // From 0 to 100, like 99 for p99.
+    pub percentile: f64,
+
+    // Whether the counts only ever grow, like in serverStatus. The distribution of each sample is
+    // then that of the counts added since the previous one.
+    #[serde(default)]
+    pub cumulative: bool,
+}
+
+impl Histogram {
+    /// Computes the percentile for each sample from the bucket counts, indexed by bucket and then
+    /// by sample. A sample with no counts, or with a bucket that is a gap, has no distribution and
+    /// is a gap itself, and so is one where a cumulative count went down, since the process was
+    /// restarted.
+    pub fn percentiles(&self, buckets: &[&[f64]], num_samples: usize) -> Vec<f64> {
+        let mut counts = vec![0.0; buckets.len()];
+        (0..num_samples)
+            .map(|idx| {
+                for (count, values) in counts.iter_mut().zip(buckets) {
+                    *count = match (self.cumulative, idx.checked_sub(1)) {
+                        (false, _) => values[idx],
+                        (true, Some(prev)) => values[idx] - values[prev],
+                        (true, None) => f64::NAN,
+                    };
+                }
+                self.percentile_of(&counts)
+            })
+            .collect()
+    }
+
+    // The value is interpolated within the bucket that the percentile falls into, as if the values
+    // in it were spread evenly.
+    fn percentile_of(&self, counts: &[f64]) -> f64 {
+        if counts.iter().any(|count| count.is_nan() || *count < 0.0) {
+            return f64::NAN;
+        }
+        let total: f64 = counts.iter().sum();
+        if total <= 0.0 {
+            return f64::NAN;
+        }
+
+        let target = total * self.percentile.clamp(0.0, 100.0) / 100.0;
+        let mut cumulative = 0.0;
+        for (idx, (count, upper)) in counts.iter().zip(self.bounds.iter()).enumerate() {
+            if *count > 0.0 && cumulative + count >= target {
+                let lower = idx.checked_sub(1).map_or(0.0, |prev| self.bounds[prev]);
+                return lower + (upper - lower) * (target - cumulative) / count;
+            }
+            cumulative += count;
+        }
+        self.bounds.last().copied().unwrap_or(f64::NAN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(percentile: f64, cumulative: bool) -> Histogram {
+        Histogram {
+            bounds: vec![10.0, 20.0, 30.0],
+            percentile,
+            cumulative,
+        }
+    }
+
+    #[test]
+    fn percentile_is_interpolated_within_its_bucket() {
+        let buckets: [&[f64]; 3] = [&[2.0, 0.0], &[2.0, 4.0], &[0.0, 4.0]];
+
+        assert_eq!(
+            histogram(50.0, false).percentiles(&buckets, 2),
+            [10.0, 20.0]
+        );
+        assert_eq!(
+            histogram(75.0, false).percentiles(&buckets, 2),
+            [15.0, 25.0]
+        );
+        assert_eq!(
+            histogram(100.0, false).percentiles(&buckets, 2),
+            [20.0, 30.0]
+        );
+    }
+
+    #[test]
+    fn cumulative_counts_are_taken_as_deltas() {
+        let buckets: [&[f64]; 3] = [&[5.0, 7.0, 7.0, 1.0], &[0.0, 2.0, 2.0, 0.0], &[0.0; 4]];
+        let values = histogram(50.0, true).percentiles(&buckets, 4);
+
+        // The first sample has nothing to take deltas from, the third has no new counts, and the
+        // counts of the fourth went down.
+        assert!(values[0].is_nan());
+        assert_eq!(values[1], 10.0);
+        assert!(values[2].is_nan());
+        assert!(values[3].is_nan());
+    }
+
+    #[test]
+    fn samples_without_counts_or_with_gaps_are_gaps() {
+        let buckets: [&[f64]; 3] = [&[0.0, f64::NAN], &[0.0, 1.0], &[0.0, 1.0]];
+        let values = histogram(50.0, false).percentiles(&buckets, 2);
+
+        assert!(values.iter().all(|value| value.is_nan()));
+    }
+}
@@ -3,7 +3,7 @@ use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
 
 use serde::de::{SeqAccess, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone)]
 pub struct MetricKey {
@@ -80,20 +80,17 @@ impl<'de> Deserialize<'de> for MetricKey {
             type Value = MetricKey;
 
             fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
-                f.write_str("a nonempty sequence of strings")
+                f.write_str("a sequence of strings")
             }
 
+            // An empty key is accepted here, so that the descriptors deserializer can reject it with
+            // an error that points to the offending descriptor.
             fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
                 let mut key = MetricKey::new();
 
                 // NOTE: We have to use Cow, because JSON deserialization might need to unescape
                 // the value, which would require ownership. For more info, see:
                 // https://github.com/serde-rs/serde/issues/1413#issuecomment-494892266
-                key.push(
-                    seq.next_element::<Cow<str>>()?
-                        .ok_or_else(|| serde::de::Error::custom("key cannot be empty"))?
-                        .as_ref(),
-                );
                 while let Some(elem) = seq.next_element::<Cow<str>>()? {
                     key.push(elem.as_ref());
                 }
@@ -106,6 +103,12 @@ impl<'de> Deserialize<'de> for MetricKey {
     }
 }
 
+impl Serialize for MetricKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
 impl MetricKey {
     pub fn new() -> Self {
         Self { key: String::new(), indices: vec![] }
@@ -125,6 +128,10 @@ impl MetricKey {
         self.indices.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
     pub fn truncate(&mut self, len: usize) {
         if len >= self.len() {
             return;
@@ -140,9 +147,41 @@ impl MetricKey {
         self.indices.truncate(len);
     }
 
+    pub fn join(&self, separator: &str) -> String {
+        let mut result = String::with_capacity(self.key.len());
+        for (idx, elem) in self.iter().enumerate() {
+            if idx > 0 {
+                result.push_str(separator);
+            }
+            result.push_str(elem);
+        }
+        result
+    }
+
+    pub fn starts_with(&self, prefix: &MetricKey) -> bool {
+        self.len() >= prefix.len() && self.iter().zip(prefix.iter()).all(|(lhs, rhs)| lhs == rhs)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &str> {
         self.indices
             .iter()
             .map(|&(start, end)| &self.key[start..end])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_are_joined_with_the_separator() {
+        let key = MetricKey::from(&["serverStatus", "wiredTiger", "cache"][..]);
+
+        assert_eq!(key.join(" "), "serverStatus wiredTiger cache");
+        assert_eq!(key.join("."), "serverStatus.wiredTiger.cache");
+        assert_eq!(key.join(" > "), "serverStatus > wiredTiger > cache");
+        assert_eq!(key.join(""), "serverStatuswiredTigercache");
+        assert_eq!(MetricKey::from(&["uptime"][..]).join("/"), "uptime");
+        assert_eq!(MetricKey::new().join("/"), "");
+    }
+}
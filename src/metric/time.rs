@@ -1,20 +1,72 @@
-use chrono::{DateTime, SecondsFormat, Utc};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
 
 pub type Timestamp = DateTime<Utc>;
 
+// Times outside of what a timestamp can hold only come from corrupted data, and are clamped.
 pub fn unix_millis_to_timestamp(millis: i64) -> Timestamp {
     const NANOS_PER_MILLISECOND: u32 = std::time::Duration::from_millis(1).as_nanos() as u32;
-    let secs = millis / 1000;
-    let nanos = (millis % 1000) as u32 * NANOS_PER_MILLISECOND;
-    DateTime::from_timestamp(secs, nanos).unwrap()
+    let secs = millis.div_euclid(1000);
+    let nanos = millis.rem_euclid(1000) as u32 * NANOS_PER_MILLISECOND;
+    DateTime::from_timestamp(secs, nanos).unwrap_or(if millis < 0 {
+        Timestamp::MIN_UTC
+    } else {
+        Timestamp::MAX_UTC
+    })
+}
+
+// Where times are shown and entered. Timestamps stay in UTC regardless, and are only converted
+// when they are shown or parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayZone {
+    #[default]
+    Utc,
+    Local,
+    Offset(FixedOffset),
+}
+
+impl DisplayZone {
+    // The offset of the system zone can change over the year, so it depends on the time.
+    pub fn offset_at(&self, time: &Timestamp) -> FixedOffset {
+        match self {
+            Self::Utc => FixedOffset::east_opt(0).unwrap(),
+            Self::Local => *time.with_timezone(&Local).offset(),
+            Self::Offset(offset) => *offset,
+        }
+    }
+
+    pub fn to_local(&self, time: &Timestamp) -> DateTime<FixedOffset> {
+        time.with_timezone(&self.offset_at(time))
+    }
+
+    // A time that the zone skips, like when the clocks go forward, doesn't exist. One that it
+    // repeats is taken the first time.
+    pub fn from_local(&self, time: &NaiveDateTime) -> Option<Timestamp> {
+        match self {
+            Self::Utc => Some(time.and_utc()),
+            Self::Local => Local
+                .from_local_datetime(time)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+            Self::Offset(offset) => offset
+                .from_local_datetime(time)
+                .single()
+                .map(|time| time.with_timezone(&Utc)),
+        }
+    }
 }
 
 pub trait TimestampFormat {
     fn to_timestamp_string(&self) -> String;
+    fn to_timestamp_string_in(&self, zone: DisplayZone) -> String;
 }
 
 impl TimestampFormat for Timestamp {
     fn to_timestamp_string(&self) -> String {
         self.to_rfc3339_opts(SecondsFormat::Millis, true)
     }
+
+    fn to_timestamp_string_in(&self, zone: DisplayZone) -> String {
+        zone.to_local(self)
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
 }
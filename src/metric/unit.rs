@@ -0,0 +1,72 @@
+// Each unit is given by how many of the base unit of its kind it is. Converting between units of
+// the same kind divides by the ratio of the two, which is what a descriptor's scale does.
+const UNITS: &[(&str, UnitKind, f64)] = &[
+    ("B", UnitKind::Bytes, 1.0),
+    ("bytes", UnitKind::Bytes, 1.0),
+    ("KB", UnitKind::Bytes, 1e3),
+    ("MB", UnitKind::Bytes, 1e6),
+    ("GB", UnitKind::Bytes, 1e9),
+    ("TB", UnitKind::Bytes, 1e12),
+    ("KiB", UnitKind::Bytes, 1024.0),
+    ("MiB", UnitKind::Bytes, 1024.0 * 1024.0),
+    ("GiB", UnitKind::Bytes, 1024.0 * 1024.0 * 1024.0),
+    ("TiB", UnitKind::Bytes, 1024.0 * 1024.0 * 1024.0 * 1024.0),
+    ("ns", UnitKind::Time, 1.0),
+    ("us", UnitKind::Time, 1e3),
+    ("\u{b5}s", UnitKind::Time, 1e3),
+    ("ms", UnitKind::Time, 1e6),
+    ("s", UnitKind::Time, 1e9),
+    ("min", UnitKind::Time, 60e9),
+    ("h", UnitKind::Time, 3600e9),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnitKind {
+    Bytes,
+    Time,
+}
+
+/// Resolves a conversion like `"bytes->MiB"` to the scale that converts values in the first unit
+/// to the second, and the name of the second unit. Returns `None` if either unit is unknown, or if
+/// they measure different things.
+pub fn resolve_conversion(spec: &str) -> Option<(f64, &'static str)> {
+    let (from, to) = spec.split_once("->")?;
+    let (_, from_kind, from_size) = find_unit(from.trim())?;
+    let (to_name, to_kind, to_size) = find_unit(to.trim())?;
+    (from_kind == to_kind).then_some((to_size / from_size, to_name))
+}
+
+fn find_unit(name: &str) -> Option<(&'static str, UnitKind, f64)> {
+    UNITS
+        .iter()
+        .find(|(unit, _, _)| *unit == name)
+        .or_else(|| {
+            UNITS
+                .iter()
+                .find(|(unit, _, _)| unit.eq_ignore_ascii_case(name))
+        })
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_conversions_resolve_to_their_scale_and_unit() {
+        assert_eq!(
+            resolve_conversion("bytes->MiB"),
+            Some((1024.0 * 1024.0, "MiB"))
+        );
+        assert_eq!(resolve_conversion("us->ms"), Some((1e3, "ms")));
+        assert_eq!(resolve_conversion(" B -> kb "), Some((1e3, "KB")));
+        assert_eq!(resolve_conversion("ms->min"), Some((60e3, "min")));
+    }
+
+    #[test]
+    fn unknown_or_mismatched_conversions_do_not_resolve() {
+        assert_eq!(resolve_conversion("furlongs->MiB"), None);
+        assert_eq!(resolve_conversion("bytes->ms"), None);
+        assert_eq!(resolve_conversion("bytes"), None);
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::ftdc::{summarize_warnings, MetricTypes};
+use crate::metric::TimestampFormat;
+use crate::DataSet;
+
+// Reads the whole file the same way the GUI does and prints a summary of it. Returns the exit code,
+// which is nonzero if the file couldn't be read to the end.
+pub fn validate(path: &Path, metric_types: MetricTypes, json: bool) -> i32 {
+    let mut dataset = DataSet::new();
+    dataset.metric_types = metric_types;
+    let mut data_chunks = 0usize;
+    let result = dataset.open_ftdc_file_with(path, None, |_| {
+        data_chunks += 1;
+        ControlFlow::Continue(())
+    });
+
+    let span = match (dataset.timestamps.first(), dataset.timestamps.last()) {
+        (Some(start), Some(end)) => Some((start.to_timestamp_string(), end.to_timestamp_string())),
+        _ => None,
+    };
+    let (_, warnings) = summarize_warnings(&dataset.warnings);
+    let error = result.as_ref().err().map(|err| err.to_string());
+    // Chunks that had to be skipped don't stop the GUI from loading the rest, but they still mean
+    // that the file couldn't be read in full.
+    let ok = error.is_none() && dataset.skipped_chunks == 0;
+
+    if json {
+        let report = json!({
+            "file": path.display().to_string(),
+            "ok": ok,
+            "error": error,
+            "format": dataset.format.to_string(),
+            "metadata_chunks": dataset.metadata.len(),
+            "data_chunks": data_chunks,
+            "skipped_chunks": dataset.skipped_chunks,
+            "samples": dataset.timestamps.len(),
+            "metrics": dataset.raw_data.len(),
+            "start": span.as_ref().map(|(start, _)| start),
+            "end": span.as_ref().map(|(_, end)| end),
+            "warnings": warnings
+                .iter()
+                .map(|(message, count)| json!({ "message": message, "count": count }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", report);
+    } else {
+        println!("File:            {}", path.display());
+        println!("FTDC format:     {}", dataset.format);
+        println!("Metadata chunks: {}", dataset.metadata.len());
+        println!("Data chunks:     {}", data_chunks);
+        println!("Skipped chunks:  {}", dataset.skipped_chunks);
+        println!("Samples:         {}", dataset.timestamps.len());
+        println!("Metrics:         {}", dataset.raw_data.len());
+        if let Some((start, end)) = span.as_ref() {
+            println!("Time span:       {} to {}", start, end);
+        }
+        println!("Warnings:        {}", dataset.warnings.len());
+        for (message, count) in warnings.iter() {
+            println!("  {} (x{})", message, count);
+        }
+        match error.as_ref() {
+            Some(error) => println!("Error:           {}", error),
+            None if ok => println!("OK"),
+            None => (),
+        }
+    }
+
+    if ok {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use bson::spec::BinarySubtype;
+
+    use super::*;
+    use crate::ftdc::fixture::{
+        compress, data_chunk, data_chunk_with, file_bytes, metadata_chunk, payload, sample,
+        temp_path, write_file,
+    };
+
+    fn validate_bytes(name: &str, bytes: &[u8]) -> (i32, i32) {
+        let path = write_file(name, bytes);
+        let codes = (
+            validate(&path, MetricTypes::default(), false),
+            validate(&path, MetricTypes::default(), true),
+        );
+        std::fs::remove_file(path).unwrap();
+        codes
+    }
+
+    #[test]
+    fn good_file_is_valid() {
+        let samples = [sample(0, doc! { "x": 1 }), sample(1000, doc! { "x": 2 })];
+        let bytes = file_bytes(&[
+            metadata_chunk(0, doc! { "buildInfo": { "version": "7.0.1" } }),
+            data_chunk(&samples),
+        ]);
+
+        assert_eq!(validate_bytes("good.ftdc", &bytes), (0, 0));
+    }
+
+    #[test]
+    fn skipped_chunk_or_unreadable_file_is_not_valid() {
+        let good = [sample(0, doc! { "x": 1 }), sample(1000, doc! { "x": 2 })];
+        let corrupt = [sample(2000, doc! { "x": 3 }), sample(3000, doc! { "x": 5 })];
+        let mut corrupt_payload = payload(&corrupt);
+        corrupt_payload.pop();
+        let bytes = file_bytes(&[
+            data_chunk(&good),
+            data_chunk_with(&corrupt, compress(&corrupt_payload), BinarySubtype::Generic),
+        ]);
+
+        assert_eq!(validate_bytes("corrupt.ftdc", &bytes), (1, 1));
+        assert_eq!(
+            validate_bytes("not-ftdc.bson", &file_bytes(&[doc! { "a": 1 }])),
+            (1, 1)
+        );
+        let missing = temp_path("missing.ftdc");
+        assert_eq!(validate(&missing, MetricTypes::default(), false), 1);
+    }
+}